@@ -0,0 +1,444 @@
+//! A rare-byte prefilter wrapped around PCRE2-backed [`CompiledPattern`]s,
+//! so [`crate::pcre2_pool::PatternPool::acquire_pcre2`] can reject most
+//! non-matching candidates with a single-byte scan instead of running the
+//! full automaton.
+//!
+//! [`required_byte`] walks the raw pattern source at compile time and, if
+//! it can prove some literal byte run is unconditionally present (a
+//! prefix, suffix, or inner run not guarded by `*`/`?`/a top-level `|`),
+//! picks the rarest byte in it via [`BYTE_FREQUENCY`] -- a static table
+//! ranking how common each byte is in typical file names and paths, so
+//! the chosen byte is the one least likely to appear in non-matching
+//! text. [`PrefilteredPattern`] then scans for that byte with
+//! [`find_byte`] before delegating to the wrapped pattern, short-
+//! circuiting to "no match" when the byte is entirely absent. Patterns
+//! `required_byte` can't reduce to a guaranteed byte (`.*`, a bare
+//! class, a top-level alternation, ...) fall through to the wrapped
+//! pattern unconditionally, same as `LiteralPrefilter` falls through for
+//! non-literal query nodes (see `query::prefilter`).
+
+use crate::pcre2_pool::CompiledPattern;
+use std::sync::Arc;
+
+/// Rough commonness rank of each byte value in typical file names and
+/// paths, from 0 (rarest -- most selective to scan for) to 255 (most
+/// common -- e.g. `/` and lowercase ASCII letters, which appear in
+/// nearly everything and so reject almost nothing). Only the relative
+/// order matters; this isn't a real frequency distribution.
+static BYTE_FREQUENCY: [u8; 256] = build_byte_frequency();
+
+const fn build_byte_frequency() -> [u8; 256] {
+    let mut table = [10u8; 256];
+    let mut b = 0usize;
+    while b < 256 {
+        table[b] = match b as u8 {
+            b' ' => 250,
+            b'/' => 245,
+            b'a'..=b'z' => 210,
+            b'.' | b'_' | b'-' => 190,
+            b'0'..=b'9' => 150,
+            b'A'..=b'Z' => 120,
+            0x21..=0x7e => 60, // remaining printable ASCII symbols
+            _ => 10,           // control bytes and non-ASCII
+        };
+        b += 1;
+    }
+    table
+}
+
+/// Whether `\<c>` is an escape shorthand (`\d`, `\w`, `\s`, a
+/// backreference, word/string boundary, ...) that matches a class of
+/// bytes rather than one fixed byte.
+fn is_class_escape(c: u8) -> bool {
+    matches!(c, b'd' | b'D' | b'w' | b'W' | b's' | b'S' | b'b' | b'B' | b'A' | b'Z' | b'z' | b'1'..=b'9')
+}
+
+/// The literal byte `\<c>` stands for, if `c` escapes to itself (e.g.
+/// `\.`, `\(`, `\\`) rather than to a class shorthand.
+fn escaped_literal(c: u8) -> Option<u8> {
+    if is_class_escape(c) || c.is_ascii_alphanumeric() {
+        None
+    } else {
+        Some(c)
+    }
+}
+
+/// Parse a `{m,n}` (or `{m}` / `{m,}`) quantifier starting at `src[pos]`
+/// (which must be `{`), returning the minimum repeat count and the index
+/// just past the closing `}`. Returns `None` if `src[pos..]` isn't a
+/// well-formed bound, in which case `{` should be treated as a literal
+/// byte instead.
+fn parse_bound(src: &[u8], pos: usize) -> Option<(u32, usize)> {
+    let mut i = pos + 1;
+    let start = i;
+    while i < src.len() && src[i].is_ascii_digit() {
+        i += 1;
+    }
+    if i == start {
+        return None;
+    }
+    let min: u32 = std::str::from_utf8(&src[start..i]).ok()?.parse().ok()?;
+    if i < src.len() && src[i] == b',' {
+        i += 1;
+        while i < src.len() && src[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    if i < src.len() && src[i] == b'}' {
+        Some((min, i + 1))
+    } else {
+        None
+    }
+}
+
+/// Skip a bracketed character class starting at `src[pos]` (which must be
+/// `[`), returning the index just past the matching `]`. A `]` right
+/// after the opening `[` (or `[^`) is a literal member of the class, not
+/// its close, matching PCRE2's rule.
+fn skip_class(src: &[u8], pos: usize) -> usize {
+    let mut i = pos + 1;
+    if i < src.len() && src[i] == b'^' {
+        i += 1;
+    }
+    if i < src.len() && src[i] == b']' {
+        i += 1;
+    }
+    while i < src.len() && src[i] != b']' {
+        if src[i] == b'\\' && i + 1 < src.len() {
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    if i < src.len() { i + 1 } else { i }
+}
+
+/// Skip a parenthesized group starting at `src[pos]` (which must be `(`),
+/// returning the index just past its matching `)`. Group contents aren't
+/// inspected for literals at all -- a quantifier on the whole group (e.g.
+/// `(abc)+`) could make any byte inside it repeat zero times in some
+/// match, so treating the group as opaque keeps every extracted literal
+/// unconditionally required.
+fn skip_group(src: &[u8], pos: usize) -> usize {
+    let mut depth = 0i32;
+    let mut i = pos;
+    while i < src.len() {
+        match src[i] {
+            b'\\' if i + 1 < src.len() => i += 2,
+            b'[' => i = skip_class(src, i),
+            b'(' => {
+                depth += 1;
+                i += 1;
+            }
+            b')' => {
+                depth -= 1;
+                i += 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// Whether `pattern` contains a top-level `|` (outside any `[...]` class
+/// or `(...)` group). A top-level alternation means no byte is
+/// unconditionally present -- either branch could match alone -- so the
+/// whole pattern must be skipped rather than just the alternation's
+/// surrounding run.
+fn has_top_level_alternation(src: &[u8]) -> bool {
+    let mut i = 0;
+    while i < src.len() {
+        match src[i] {
+            b'\\' if i + 1 < src.len() => i += 2,
+            b'[' => i = skip_class(src, i),
+            b'(' => i = skip_group(src, i),
+            b'|' => return true,
+            _ => i += 1,
+        }
+    }
+    false
+}
+
+/// Whether `src` sets PCRE2's case-insensitive flag via an inline `(?i)`
+/// (or a modifier group starting `(?i` such as `(?im)`) anywhere in the
+/// pattern. `query::matcher`'s `build_pattern_for_regex`/
+/// `build_pattern_for_structural` prepend a bare (non-grouped) `(?i)`
+/// whenever a query has the `icase` modifier, which flips every literal
+/// byte that follows into matching both cases -- e.g. `required_byte`
+/// picking `'F'` out of `"(?i)Foo"` would wrongly reject `"foo"`, which
+/// the wrapped PCRE2 pattern actually matches. Once this is true,
+/// `required_literal_runs` gives up and returns no runs at all rather
+/// than try to reason about exactly which literals fall under the flag's
+/// scope, the same way it gives up on a top-level alternation.
+fn has_case_insensitive_flag(src: &[u8]) -> bool {
+    let mut i = 0;
+    while i + 2 < src.len() {
+        if &src[i..i + 3] == b"(?i" {
+            return true;
+        }
+        match src[i] {
+            b'\\' if i + 1 < src.len() => i += 2,
+            b'[' => i = skip_class(src, i),
+            _ => i += 1,
+        }
+    }
+    false
+}
+
+/// Extract every run of literal bytes `pattern` unconditionally requires
+/// present, in order. A run ends whenever the parser hits something that
+/// isn't a guaranteed fixed byte: `.`, a class, a group, an anchor, or a
+/// quantifier that makes the byte it follows optional (`*`, `?`, or
+/// `{0,n}`). A quantifier that still requires at least one occurrence
+/// (`+`, `{m,n}` with `m >= 1`) keeps the byte it follows but still ends
+/// the run there, since further repeats aren't guaranteed to immediately
+/// follow in a fixed position.
+fn required_literal_runs(pattern: &str) -> Vec<Vec<u8>> {
+    let src = pattern.as_bytes();
+    if has_top_level_alternation(src) || has_case_insensitive_flag(src) {
+        return Vec::new();
+    }
+
+    let mut runs = Vec::new();
+    let mut current = Vec::new();
+    let mut i = 0;
+    while i < src.len() {
+        match src[i] {
+            b'^' | b'$' => {
+                i += 1;
+            }
+            b'.' => {
+                if !current.is_empty() {
+                    runs.push(std::mem::take(&mut current));
+                }
+                i += 1;
+            }
+            b'[' => {
+                if !current.is_empty() {
+                    runs.push(std::mem::take(&mut current));
+                }
+                i = skip_class(src, i);
+            }
+            b'(' => {
+                if !current.is_empty() {
+                    runs.push(std::mem::take(&mut current));
+                }
+                i = skip_group(src, i);
+            }
+            b'{' => {
+                // A `{` that isn't a well-formed bound is a literal byte
+                // (PCRE2 treats it that way too when it can't parse one).
+                match parse_bound(src, i) {
+                    Some((min, next)) => {
+                        if min == 0 {
+                            current.pop();
+                        }
+                        if !current.is_empty() {
+                            runs.push(std::mem::take(&mut current));
+                        }
+                        i = next;
+                    }
+                    None => {
+                        current.push(b'{');
+                        i += 1;
+                    }
+                }
+            }
+            b'*' | b'?' => {
+                current.pop();
+                if !current.is_empty() {
+                    runs.push(std::mem::take(&mut current));
+                }
+                i += 1;
+            }
+            b'+' => {
+                if !current.is_empty() {
+                    runs.push(std::mem::take(&mut current));
+                }
+                i += 1;
+            }
+            b'\\' if i + 1 < src.len() => {
+                match escaped_literal(src[i + 1]) {
+                    Some(byte) => current.push(byte),
+                    None if is_class_escape(src[i + 1]) => {
+                        if !current.is_empty() {
+                            runs.push(std::mem::take(&mut current));
+                        }
+                    }
+                    // `\n`, `\x41`, `\p{L}`, `\Q...\E`, ...: the bytes after
+                    // the backslash aren't the literal text, so give up.
+                    None => return Vec::new(),
+                }
+                i += 2;
+            }
+            byte => {
+                current.push(byte);
+                i += 1;
+            }
+        }
+    }
+    if !current.is_empty() {
+        runs.push(current);
+    }
+    runs
+}
+
+/// The single rarest byte across every literal run `pattern`
+/// unconditionally requires, or `None` if no such run exists. This is
+/// all [`PrefilteredPattern`] needs: presence of one required byte is
+/// enough to reject a non-candidate, and the rarer the byte the more
+/// candidates a single scan rejects.
+fn required_byte(pattern: &str) -> Option<u8> {
+    required_literal_runs(pattern)
+        .into_iter()
+        .flatten()
+        .min_by_key(|&b| BYTE_FREQUENCY[b as usize])
+}
+
+/// A minimal single-byte scan. This crate has no SIMD-accelerated
+/// `memchr` available to reach for (no manifest, no external crates),
+/// but a plain scan over a contiguous slice still auto-vectorizes
+/// reasonably well under LLVM, and candidates here are short file names
+/// and paths rather than large buffers.
+fn find_byte(byte: u8, haystack: &[u8]) -> bool {
+    haystack.contains(&byte)
+}
+
+/// Wraps an inner [`CompiledPattern`] with a rare-byte presence check, so
+/// callers that always go through [`CompiledPattern::is_match`] /
+/// `captures_ranges` get the speedup transparently.
+struct PrefilteredPattern {
+    inner: Arc<dyn CompiledPattern>,
+    byte: u8,
+}
+
+impl CompiledPattern for PrefilteredPattern {
+    fn is_match(&self, text: &[u8]) -> bool {
+        find_byte(self.byte, text) && self.inner.is_match(text)
+    }
+
+    fn captures_ranges(&self, text: &[u8]) -> Option<Vec<(usize, usize)>> {
+        if !find_byte(self.byte, text) {
+            return None;
+        }
+        self.inner.captures_ranges(text)
+    }
+
+    fn capture_name_index(&self, name: &str) -> Option<usize> {
+        self.inner.capture_name_index(name)
+    }
+}
+
+/// Wrap `inner` (just compiled from `pattern`) in a rare-byte prefilter
+/// if one can be extracted, or hand `inner` back unchanged otherwise.
+/// Called once per compile, from [`crate::pcre2_pool::PatternPool`]'s
+/// cache-miss path, so the extraction cost is paid once per distinct
+/// pattern rather than once per candidate checked against it.
+pub(crate) fn wrap(pattern: &str, inner: Arc<dyn CompiledPattern>) -> Arc<dyn CompiledPattern> {
+    match required_byte(pattern) {
+        Some(byte) => Arc::new(PrefilteredPattern { inner, byte }),
+        None => inner,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_rarest_byte_from_a_plain_literal() {
+        // every byte in "abc" beats '/' or ' ' in rarity; 'a', 'b', 'c'
+        // all score the same under this table, so just check it picked
+        // one of them rather than bailing out.
+        let byte = required_byte("abc").unwrap();
+        assert!(b"abc".contains(&byte));
+    }
+
+    #[test]
+    fn optional_trailing_literal_is_dropped() {
+        // "s" is optional, so only "file" can be required.
+        let runs = required_literal_runs("files?");
+        assert_eq!(runs, vec![b"file".to_vec()]);
+    }
+
+    #[test]
+    fn star_drops_the_byte_it_follows() {
+        let runs = required_literal_runs("ab*c");
+        assert_eq!(runs, vec![b"a".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn plus_keeps_the_byte_it_follows() {
+        let runs = required_literal_runs("ab+c");
+        assert_eq!(runs, vec![b"ab".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn groups_and_classes_break_but_dont_poison_surrounding_literals() {
+        let runs = required_literal_runs(r"module[0-9]{3}/file_[0-9]{3}\.rs");
+        assert_eq!(runs, vec![b"module".to_vec(), b"/file_".to_vec(), b".rs".to_vec()]);
+    }
+
+    #[test]
+    fn top_level_alternation_yields_no_required_bytes() {
+        assert!(required_literal_runs("foo|bar").is_empty());
+        assert_eq!(required_byte("foo|bar"), None);
+    }
+
+    #[test]
+    fn alternation_inside_a_group_does_not_poison_the_whole_pattern() {
+        // the "(foo|bar)" group is skipped as opaque, but "log_" is still
+        // unconditionally required.
+        let runs = required_literal_runs("log_(foo|bar)");
+        assert_eq!(runs, vec![b"log_".to_vec()]);
+    }
+
+    #[test]
+    fn class_escape_breaks_the_run_without_consuming_it_as_literal() {
+        let runs = required_literal_runs(r"ab\d+cd");
+        assert_eq!(runs, vec![b"ab".to_vec(), b"cd".to_vec()]);
+    }
+
+    #[test]
+    fn pure_wildcard_pattern_has_no_required_byte() {
+        assert_eq!(required_byte(".*"), None);
+    }
+
+    #[test]
+    fn prefiltered_pattern_agrees_with_the_inner_pattern() {
+        use crate::pcre2_backend::Pcre2Compiled;
+
+        let inner: Arc<dyn CompiledPattern> = Arc::new(Pcre2Compiled::new("module[0-9]{3}").unwrap());
+        let pat = wrap("module[0-9]{3}", inner);
+        assert!(pat.is_match(b"module007"));
+        assert!(!pat.is_match(b"no match here"));
+        assert!(!pat.is_match(b"mod without the rest"));
+    }
+
+    #[test]
+    fn leading_inline_case_insensitive_flag_yields_no_required_bytes() {
+        // `query::matcher` prepends a bare `(?i)` for `icase` queries, so
+        // 'F' here can match a lowercase 'f' in the candidate -- picking
+        // it as a required byte would wrongly reject candidates that only
+        // contain the lowercase form.
+        assert!(required_literal_runs("(?i)Foo").is_empty());
+        assert_eq!(required_byte("(?i)Foo"), None);
+    }
+
+    #[test]
+    fn inline_case_insensitive_flag_anywhere_yields_no_required_bytes() {
+        assert_eq!(required_byte("abc(?i)def"), None);
+    }
+
+    #[test]
+    fn prefiltered_icase_pattern_still_matches_via_the_inner_regex() {
+        use crate::pcre2_backend::Pcre2Compiled;
+
+        let inner: Arc<dyn CompiledPattern> = Arc::new(Pcre2Compiled::new("(?i)Foo").unwrap());
+        let pat = wrap("(?i)Foo", inner);
+        assert!(pat.is_match(b"this is foo"));
+    }
+}
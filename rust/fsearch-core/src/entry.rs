@@ -1,26 +1,182 @@
-use std::path::PathBuf;
+use crate::interner::DirId;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::path::Path;
 use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Debug, Clone)]
 pub struct Entry {
     pub id: u64,
     pub name: String,
-    pub path: String,
+    /// The entry's containing directory, interned by the owning `Index`.
+    /// Reconstruct the full path via `Index::entry_path`.
+    pub parent: DirId,
     pub size: u64,
     pub mtime: u64,
-    // normalized: NFKC + lowercase folded representation used for fast comparisons
+    // normalized: folded representation (see `Normalizer`) used for fast comparisons
     pub normalized: String,
+    /// Maps a byte range of `normalized` back to the `name` byte range it
+    /// was folded from -- see `map_normalized_range`.
+    pub norm_map: NormalizationMap,
+}
+
+/// Maps a byte range inside a folded/normalized string back to the byte
+/// range of the original string it was produced from, so a match found
+/// against `Entry::normalized` can still be highlighted against the
+/// original `Entry::name`.
+///
+/// Built once, per `Entry`, grapheme cluster by grapheme cluster (see
+/// `build`): folding each cluster as a unit rather than folding the whole
+/// string and assuming offsets line up covers the cases a byte-for-byte
+/// assumption gets wrong --
+/// - one-to-many: a ligature like `ﬁ` (one cluster, 3 bytes) expands to
+///   `fi` (2 bytes) under NFKC.
+/// - many-to-one: a decomposed sequence like `A` + combining ring above
+///   (one cluster, 3 bytes) composes to `Å` (2 bytes) under NFKC, then
+///   folds to `å` (still 2 bytes).
+/// - length-changing case folds in general, not just the two cases above.
+///
+/// Modeled on `search::Utf16GraphemeMap`: two parallel arrays of boundary
+/// offsets (one per string), looked up with a binary search per endpoint
+/// instead of storing a mapping for every single byte.
+#[derive(Debug, Clone, Default)]
+pub struct NormalizationMap {
+    /// Byte offset into the normalized string where each original
+    /// grapheme cluster's folded form starts, plus a trailing entry at
+    /// the normalized string's length.
+    norm_starts: Vec<usize>,
+    /// Byte offset into the original string where the corresponding
+    /// grapheme cluster starts, plus a trailing entry at the original
+    /// string's length.
+    orig_starts: Vec<usize>,
+}
+
+/// How `NormalizationMap::build` (and `search::fold_for_match`, which
+/// needs to agree with it byte-for-byte) folds text before comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalizer {
+    /// No transformation at all -- every grapheme cluster passes through
+    /// unchanged.
+    Raw,
+    /// Unicode canonical composition (NFC) only, no case folding.
+    Nfc,
+    /// Unicode compatibility composition (NFKC) only, no case folding
+    /// (see `fold_cluster`'s `CaseFold` arm for the case-insensitive
+    /// equivalent).
+    Nfkc,
+    /// NFKC followed by Unicode case folding (see `case_fold`). The
+    /// default, and what `Entry::new`/`from_cached` use.
+    CaseFold,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer::CaseFold
+    }
+}
+
+impl Normalizer {
+    /// Fold one grapheme cluster per this mode.
+    fn fold_cluster(self, cluster: &str) -> String {
+        match self {
+            Normalizer::Raw => cluster.into(),
+            Normalizer::Nfc => cluster.nfc().collect(),
+            Normalizer::Nfkc => cluster.nfkc().collect(),
+            Normalizer::CaseFold => case_fold(&cluster.nfkc().collect::<String>()),
+        }
+    }
+}
+
+/// Unicode case folding, for the cases `str::to_lowercase` gets wrong
+/// because it's a *lowercasing* operation, not a *case-folding* one:
+/// - `ß` stays `ß` under lowercasing (it's already lowercase) but should
+///   fold to `"ss"` so it compares equal to `SS`/`Ss`/`ss`.
+/// - the final form of sigma, `ς`, stays `ς` under lowercasing but should
+///   fold to the same thing as medial `σ` so position within a word
+///   doesn't affect whether two strings compare equal.
+///
+/// This is not the full CaseFolding.txt table -- just the two mappings
+/// that actually diverge from `to_lowercase` for the scripts this crate
+/// has been asked to handle. Everything else already round-trips through
+/// `char::to_lowercase`, which Rust implements as Unicode's locale-
+/// independent full (unconditional) lowercase mapping, so e.g. `İ`
+/// becomes `i` followed by a combining dot above.
+pub fn case_fold(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            'ß' => out.push_str("ss"),
+            'ς' => out.push('σ'),
+            _ => out.extend(c.to_lowercase()),
+        }
+    }
+    out
+}
+
+impl NormalizationMap {
+    /// Fold `name` under `normalizer` one grapheme cluster at a time,
+    /// returning the folded string alongside the map from its byte
+    /// offsets back to `name`'s.
+    fn build(name: &str, normalizer: Normalizer) -> (String, Self) {
+        let mut normalized = String::new();
+        let mut norm_starts = Vec::new();
+        let mut orig_starts = Vec::new();
+        for (orig_start, cluster) in name.grapheme_indices(true) {
+            norm_starts.push(normalized.len());
+            orig_starts.push(orig_start);
+            normalized.push_str(&normalizer.fold_cluster(cluster));
+        }
+        norm_starts.push(normalized.len());
+        orig_starts.push(name.len());
+        (normalized, NormalizationMap { norm_starts, orig_starts })
+    }
+
+    /// Translate a `[start, end)` byte range of the normalized string back
+    /// to the `[start, end)` byte range of the original grapheme clusters
+    /// it came from. A default-constructed (empty) map -- as a `no_std`
+    /// embedder gets by setting `Entry::norm_map` to its `Default` when
+    /// constructing an `Entry` directly -- is treated as the identity
+    /// mapping.
+    pub fn map(&self, start: usize, end: usize) -> (usize, usize) {
+        if self.norm_starts.is_empty() {
+            return (start, end);
+        }
+        let lo = match self.norm_starts.binary_search(&start) {
+            Ok(i) => i,
+            Err(i) => i.saturating_sub(1),
+        };
+        let hi = match self.norm_starts.binary_search(&end) {
+            Ok(i) => i,
+            Err(i) => i,
+        };
+        (self.orig_starts[lo], self.orig_starts[hi])
+    }
 }
 
 impl Entry {
-    pub fn new(id: u64, path: PathBuf) -> Self {
-        let path_str = path.to_string_lossy().into_owned();
+    /// Build an `Entry` by `stat`-ing `path` on the local filesystem, under
+    /// the already-interned containing directory `parent`. Only available
+    /// with the `std` feature; a no_std embedder that already has name/
+    /// size/mtime from elsewhere constructs `Entry` directly.
+    #[cfg(feature = "std")]
+    pub fn new(id: u64, parent: DirId, path: &Path) -> Self {
+        Self::new_with_normalizer(id, parent, path, Normalizer::default())
+    }
+
+    /// Like `new`, but folds `normalized` under `normalizer` instead of the
+    /// default `Normalizer::CaseFold` -- for callers that need `normalized`
+    /// to stay in lockstep with a query side that isn't case-folding (see
+    /// `search::fold_for_match`).
+    #[cfg(feature = "std")]
+    pub fn new_with_normalizer(id: u64, parent: DirId, path: &Path, normalizer: Normalizer) -> Self {
         let name = path
             .file_name()
             .map(|s| s.to_string_lossy().into_owned())
             .unwrap_or_else(|| String::from(""));
 
-        let metadata = std::fs::metadata(&path);
+        let metadata = std::fs::metadata(path);
         let (size, mtime) = match metadata {
             Ok(m) => {
                 let size = m.len();
@@ -35,40 +191,138 @@ impl Entry {
             Err(_) => (0u64, 0u64),
         };
 
-        // Normalize to NFKC then case-fold via to_lowercase()
-        // This approximates Unicode case folding; for full case-fold semantics consider
-        // adding a dedicated case-folding crate later.
-        let normalized = name.nfkc().collect::<String>().to_lowercase();
+        let (normalized, norm_map) = NormalizationMap::build(&name, normalizer);
 
         Entry {
             id,
             name,
-            path: path_str,
+            parent,
             size,
             mtime,
             normalized,
+            norm_map,
         }
     }
+
+    /// Translate a `[start, end)` byte range found by matching against
+    /// `self.normalized` back into the `[start, end)` byte range of
+    /// `self.name` it was folded from -- see `NormalizationMap`.
+    pub fn map_normalized_range(&self, range: (usize, usize)) -> (usize, usize) {
+        self.norm_map.map(range.0, range.1)
+    }
+
+    /// Build an `Entry` from already-known `name`/`size`/`mtime` instead of
+    /// `stat`-ing a path -- used when restoring entries from
+    /// `fs_cache::try_load` rather than walking the filesystem.
+    #[cfg(feature = "std")]
+    pub(crate) fn from_cached(id: u64, parent: DirId, name: String, size: u64, mtime: u64) -> Self {
+        Self::from_cached_with_normalizer(id, parent, name, size, mtime, Normalizer::default())
+    }
+
+    /// Like `from_cached`, but folds `normalized` under `normalizer` --
+    /// see `new_with_normalizer`.
+    #[cfg(feature = "std")]
+    pub(crate) fn from_cached_with_normalizer(id: u64, parent: DirId, name: String, size: u64, mtime: u64, normalizer: Normalizer) -> Self {
+        let (normalized, norm_map) = NormalizationMap::build(&name, normalizer);
+        Entry { id, name, parent, size, mtime, normalized, norm_map }
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use super::Entry;
+    use super::{case_fold, Entry};
+    use crate::interner::PathInterner;
     use std::path::PathBuf;
 
+    #[test]
+    fn case_fold_expands_sharp_s_to_ss() {
+        // 'ß' is already lowercase, so `to_lowercase` leaves it alone --
+        // case folding has to expand it to "ss" for `STRASSE` to match
+        // `straße`.
+        assert_eq!(case_fold("STRASSE"), "strasse");
+        assert_eq!(case_fold("straße"), "strasse");
+    }
+
+    #[test]
+    fn case_fold_unifies_final_and_medial_sigma() {
+        // 'ς' (final sigma) and 'σ' (medial sigma) are the same letter;
+        // `to_lowercase` preserves the positional form, but folding must
+        // not.
+        assert_eq!(case_fold("ΟΔΟΣ"), case_fold("οδος"));
+        assert_eq!(case_fold("ς"), "σ");
+    }
+
+    #[test]
+    fn case_fold_leaves_turkic_dotless_i_distinct_from_dotted_i() {
+        // Without locale context, 'İ' (dotted capital I) and 'I' (dotless
+        // capital I) fold to different things -- 'İ' carries its dot
+        // along as a combining mark rather than merging with plain 'i',
+        // and 'ı' (dotless lowercase) stays separate from both. This
+        // matches `to_lowercase`'s locale-independent behavior; Turkish/
+        // Azeri-locale folding is out of scope.
+        assert_eq!(case_fold("İ"), "i\u{307}");
+        assert_eq!(case_fold("I"), "i");
+        assert_eq!(case_fold("ı"), "ı");
+    }
+
     #[test]
     fn nfkc_and_lowercase_normalization() {
         // 'ﬁ' ligature (U+FB01) should normalize to 'fi' under NFKC
+        let mut interner = PathInterner::new();
+        let parent = interner.intern("/tmp");
         let tmp = PathBuf::from("/tmp/\u{FB01}file.txt");
-        let e = Entry::new(1, tmp);
+        let e = Entry::new(1, parent, &tmp);
         assert!(e.normalized.contains("fi"), "normalized='{}'", e.normalized);
     }
 
     #[test]
     fn case_fold_example() {
         // 'Å' should fold to lowercase 'å'
+        let mut interner = PathInterner::new();
+        let parent = interner.intern("/tmp");
         let tmp = PathBuf::from("/tmp/Åfile.txt");
-        let e = Entry::new(2, tmp);
+        let e = Entry::new(2, parent, &tmp);
         assert!(e.normalized.contains("å") || e.normalized.contains("åfile") || e.normalized.contains("åfile.txt"), "normalized='{}'", e.normalized);
     }
+
+    #[test]
+    fn map_normalized_range_handles_a_one_to_many_ligature_expansion() {
+        // 'ﬁ' (3 bytes) expands to "fi" (2 bytes) under NFKC -- a match on
+        // "fi" in `normalized` should map back to the whole 3-byte ligature
+        // in `name`, not just its first two bytes.
+        let mut interner = PathInterner::new();
+        let parent = interner.intern("/tmp");
+        let tmp = PathBuf::from("/tmp/\u{FB01}le.txt");
+        let e = Entry::new(1, parent, &tmp);
+        assert_eq!(&e.name[..3], "\u{FB01}");
+        assert_eq!(&e.normalized[..2], "fi");
+        assert_eq!(e.map_normalized_range((0, 2)), (0, 3));
+    }
+
+    #[test]
+    fn map_normalized_range_handles_a_same_length_case_fold() {
+        // 'Å' (2 bytes) lowercases to 'å' (2 bytes) -- same byte length
+        // here, but the match still needs to land on the right cluster,
+        // not assume name and normalized share an encoding.
+        let mut interner = PathInterner::new();
+        let parent = interner.intern("/tmp");
+        let tmp = PathBuf::from("/tmp/Åfile.txt");
+        let e = Entry::new(2, parent, &tmp);
+        let cluster_len = "Å".len();
+        assert_eq!(e.map_normalized_range((0, cluster_len)), (0, cluster_len));
+    }
+
+    #[test]
+    fn map_normalized_range_handles_a_many_to_one_combining_sequence() {
+        // "A" + combining ring above (U+030A) is one grapheme cluster (3
+        // bytes) that NFKC composes to 'Å', then folds to 'å' (2 bytes) --
+        // two original codepoints collapsing into one normalized one.
+        let mut interner = PathInterner::new();
+        let parent = interner.intern("/tmp");
+        let tmp = PathBuf::from("/tmp/A\u{30A}file.txt");
+        let e = Entry::new(3, parent, &tmp);
+        assert_eq!(&e.name[..3], "A\u{30A}");
+        assert_eq!(&e.normalized[.."å".len()], "å");
+        assert_eq!(e.map_normalized_range((0, "å".len())), (0, 3));
+    }
 }
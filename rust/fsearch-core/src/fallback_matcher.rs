@@ -0,0 +1,89 @@
+//! A pure-Rust, dependency-free substring matcher used in place of PCRE2
+//! when the `std` feature (and the PCRE2 linkage/`pcre2_pool` it brings)
+//! isn't available -- e.g. embedding the query parser and index in a
+//! `no_std` or WASM target. It only understands plain literal substring
+//! matching, optionally case-insensitive (mirroring `query::aho_corasick`'s
+//! ASCII-lowercasing convention), not full regex syntax; `match_engine`
+//! falls back to it for literal queries and simply reports no match for
+//! regex queries, since no regex engine is available without `std`.
+
+use alloc::vec::Vec;
+
+fn bytes_eq(a: u8, b: u8, icase: bool) -> bool {
+    if icase {
+        a.to_ascii_lowercase() == b.to_ascii_lowercase()
+    } else {
+        a == b
+    }
+}
+
+/// The byte range of the first occurrence of `pattern` in `text`, or `None`
+/// if it doesn't occur. An empty `pattern` matches at `(0, 0)`.
+pub fn find_first(pattern: &[u8], text: &[u8], icase: bool) -> Option<(usize, usize)> {
+    if pattern.is_empty() {
+        return Some((0, 0));
+    }
+    if pattern.len() > text.len() {
+        return None;
+    }
+    for start in 0..=(text.len() - pattern.len()) {
+        if text[start..start + pattern.len()]
+            .iter()
+            .zip(pattern.iter())
+            .all(|(&t, &p)| bytes_eq(t, p, icase))
+        {
+            return Some((start, start + pattern.len()));
+        }
+    }
+    None
+}
+
+/// Whether `pattern` occurs anywhere in `text`.
+pub fn is_match(pattern: &[u8], text: &[u8], icase: bool) -> bool {
+    find_first(pattern, text, icase).is_some()
+}
+
+/// Every non-overlapping byte range where `pattern` occurs in `text`, left
+/// to right.
+pub fn find_ranges(pattern: &[u8], text: &[u8], icase: bool) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while offset <= text.len() {
+        match find_first(pattern, &text[offset..], icase) {
+            Some((s, e)) => {
+                ranges.push((offset + s, offset + e));
+                offset += e.max(s + 1);
+            }
+            None => break,
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_a_literal_substring() {
+        assert_eq!(find_first(b"foo", b"this is foo bar", false), Some((8, 11)));
+        assert_eq!(find_first(b"qux", b"this is foo bar", false), None);
+    }
+
+    #[test]
+    fn is_case_insensitive_when_asked() {
+        assert!(is_match(b"FOO", b"this is foo bar", true));
+        assert!(!is_match(b"FOO", b"this is foo bar", false));
+    }
+
+    #[test]
+    fn finds_every_non_overlapping_occurrence() {
+        let ranges = find_ranges(b"ab", b"ababab", false);
+        assert_eq!(ranges, vec![(0, 2), (2, 4), (4, 6)]);
+    }
+
+    #[test]
+    fn empty_pattern_matches_at_the_start() {
+        assert_eq!(find_first(b"", b"anything", false), Some((0, 0)));
+    }
+}
@@ -0,0 +1,318 @@
+//! An on-disk cache of a previous `Index` walk over a fixed set of root
+//! paths (see `index::WalkOptions::cache`), so repeated searches over a
+//! large static tree can skip re-walking the filesystem entirely.
+//!
+//! Guarded by an advisory file lock the same way rustdoc's cross-crate
+//! search index guards concurrent writers to one shared `search-index.js`:
+//! readers and writers lock a sibling `<cache>.lock` file rather than the
+//! data file itself, so a rewrite can still atomically replace the data
+//! file (via a temp file + rename) without disturbing a lock already held
+//! on the old file's inode.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// One cached file: its containing directory, name, size, and mtime --
+/// enough for `Entry::from_cached` to rebuild it without re-`stat`-ing.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+    pub parent: String,
+    pub name: String,
+    pub size: u64,
+    pub mtime: u64,
+}
+
+/// The subset of `index::WalkOptions` that changes which entries a walk
+/// produces (as opposed to `cache`, which only says where to persist the
+/// result). A cache built under one `WalkKey` must not be reused for a
+/// walk under a different one, even over the identical unchanged root set
+/// -- e.g. a `respect_gitignore: false` walk would otherwise silently hand
+/// back the filtered entry set a prior `respect_gitignore: true` walk
+/// cached.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct WalkKey {
+    pub respect_gitignore: bool,
+    pub include_hidden: bool,
+    pub max_depth: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    /// The root paths this cache was built from, in the order
+    /// `Index::build_from_paths_with_options` received them; a cache only
+    /// applies to a walk over the identical root set.
+    roots: Vec<String>,
+    /// The newest mtime observed across `roots` at write time. The cache
+    /// is stale once any root's current mtime moves past this.
+    roots_mtime: u64,
+    /// The walk configuration this cache was built under; see `WalkKey`.
+    walk_key: WalkKey,
+    entries: Vec<CacheEntry>,
+}
+
+fn lock_path(cache_path: &Path) -> PathBuf {
+    let mut name = cache_path.as_os_str().to_os_string();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Every lock file this process currently holds open, keyed by its path,
+/// so `release_all_locks` (called from `search::shutdown_all`) can unlock
+/// and drop them even if the thread that called `store` was cancelled
+/// mid-walk instead of reaching its own unlock.
+static HELD_LOCKS: Lazy<Mutex<HashMap<PathBuf, File>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// How long to sleep between `try_lock_exclusive` polls in `store`.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+fn mtime_secs(m: &std::fs::Metadata) -> u64 {
+    m.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The newest mtime across every directory and file recursively beneath
+/// `root` (including `root` itself). A root's own mtime alone only moves
+/// when one of its *direct* children is added or removed -- a change
+/// three levels down bumps that subdirectory's mtime, not the root's --
+/// so staleness has to follow the whole tree, not just the root paths
+/// themselves. `read_dir` failures (permissions, a root that's gone)
+/// are treated as contributing nothing, the same way `try_load`/`store`
+/// already tolerate missing roots.
+fn newest_mtime_recursive(root: &Path) -> u64 {
+    let mut newest = std::fs::metadata(root).map(|m| mtime_secs(&m)).unwrap_or(0);
+    if let Ok(entries) = std::fs::read_dir(root) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => newest = newest.max(newest_mtime_recursive(&path)),
+                Ok(_) => {
+                    if let Ok(m) = entry.metadata() {
+                        newest = newest.max(mtime_secs(&m));
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    newest
+}
+
+fn newest_mtime(roots: &[String]) -> u64 {
+    roots.iter().map(|r| newest_mtime_recursive(Path::new(r))).max().unwrap_or(0)
+}
+
+/// Load `cache_path` if it covers exactly `roots`, was built under the
+/// identical `walk_key` (see `WalkKey`), and nothing anywhere in those
+/// trees (see `newest_mtime_recursive`) has a newer mtime than the cache's
+/// `roots_mtime`. Takes a brief shared lock on the lock file while
+/// reading. Returns `None` on any kind of miss (missing file, different
+/// roots, different walk configuration, staleness, lock contention, or a
+/// parse error) -- the caller falls back to a live walk in every such case.
+pub(crate) fn try_load(cache_path: &Path, roots: &[String], walk_key: &WalkKey) -> Option<Vec<CacheEntry>> {
+    let lock_file = File::open(lock_path(cache_path)).ok()?;
+    fs4::FileExt::try_lock_shared(&lock_file).ok()?;
+
+    let loaded = (|| -> Option<Vec<CacheEntry>> {
+        let mut buf = Vec::new();
+        File::open(cache_path).ok()?.read_to_end(&mut buf).ok()?;
+        let cached: CacheFile = serde_cbor::from_slice(&buf).ok()?;
+        if cached.roots != roots || &cached.walk_key != walk_key || cached.roots_mtime != newest_mtime(roots) {
+            return None;
+        }
+        Some(cached.entries)
+    })();
+
+    let _ = fs4::FileExt::unlock(&lock_file);
+    loaded
+}
+
+/// Atomically rewrite `cache_path` with `entries` for `roots`, holding an
+/// exclusive lock on its lock file for the duration. Polls `cancel`
+/// between lock attempts instead of blocking indefinitely, and gives up
+/// without writing if it fires first -- see `release_all_locks` for the
+/// other half of that contract.
+pub(crate) fn store(cache_path: &Path, roots: &[String], walk_key: &WalkKey, entries: &[CacheEntry], cancel: &AtomicBool) {
+    let lock_file = match File::create(lock_path(cache_path)) {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+    loop {
+        if fs4::FileExt::try_lock_exclusive(&lock_file).is_ok() {
+            break;
+        }
+        if cancel.load(Ordering::SeqCst) {
+            return;
+        }
+        std::thread::sleep(LOCK_POLL_INTERVAL);
+    }
+    HELD_LOCKS.lock().insert(lock_path(cache_path), lock_file);
+
+    let cache = CacheFile {
+        roots: roots.to_vec(),
+        roots_mtime: newest_mtime(roots),
+        walk_key: walk_key.clone(),
+        entries: entries.to_vec(),
+    };
+    if let Ok(bytes) = serde_cbor::to_vec(&cache) {
+        let tmp_path = cache_path.with_extension("tmp");
+        if std::fs::write(&tmp_path, &bytes).is_ok() {
+            let _ = std::fs::rename(&tmp_path, cache_path);
+        }
+    }
+
+    release_lock(cache_path);
+}
+
+/// Release and drop the lock this process holds for `cache_path`, if any.
+fn release_lock(cache_path: &Path) {
+    if let Some(f) = HELD_LOCKS.lock().remove(&lock_path(cache_path)) {
+        let _ = fs4::FileExt::unlock(&f);
+    }
+}
+
+/// Release every lock this process currently holds, across every cache it
+/// has written to. Called from `search::shutdown_all` so a process that's
+/// being torn down mid-write doesn't leave a cache wedged for others
+/// sharing it.
+pub(crate) fn release_all_locks() {
+    for (_, f) in HELD_LOCKS.lock().drain() {
+        let _ = fs4::FileExt::unlock(&f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::thread;
+    use tempfile::tempdir;
+
+    fn no_cancel() -> AtomicBool {
+        AtomicBool::new(false)
+    }
+
+    // mtime resolution on some filesystems/CI runners is 1 second; sleep
+    // past it so a just-written file's mtime is provably newer than the
+    // cache's recorded `roots_mtime`.
+    fn wait_past_mtime_resolution() {
+        thread::sleep(Duration::from_millis(1100));
+    }
+
+    fn walk_key() -> WalkKey {
+        WalkKey { respect_gitignore: false, include_hidden: true, max_depth: None }
+    }
+
+    #[test]
+    fn fresh_cache_round_trips_entries_for_an_unchanged_tree() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.txt"), b"hi").unwrap();
+        let roots = vec![root.to_string_lossy().into_owned()];
+
+        let entries = vec![CacheEntry { parent: root.to_string_lossy().into_owned(), name: "a.txt".into(), size: 2, mtime: 0 }];
+        store(&dir.path().join("cache"), &roots, &walk_key(), &entries, &no_cancel());
+
+        let loaded = try_load(&dir.path().join("cache"), &roots, &walk_key()).expect("cache should be fresh");
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].name, "a.txt");
+    }
+
+    #[test]
+    fn adding_a_file_in_a_nested_subdirectory_invalidates_the_cache() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        let nested = root.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(nested.join("existing.txt"), b"hi").unwrap();
+        let roots = vec![root.to_string_lossy().into_owned()];
+
+        store(&dir.path().join("cache"), &roots, &walk_key(), &[], &no_cancel());
+        assert!(try_load(&dir.path().join("cache"), &roots, &walk_key()).is_some());
+
+        wait_past_mtime_resolution();
+        std::fs::write(nested.join("new.txt"), b"new").unwrap();
+
+        assert!(
+            try_load(&dir.path().join("cache"), &roots, &walk_key()).is_none(),
+            "a new file two levels below the root should invalidate the cache, \
+             even though the root's own mtime didn't change"
+        );
+    }
+
+    #[test]
+    fn modifying_an_existing_nested_file_in_place_invalidates_the_cache() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        let nested = root.join("a");
+        std::fs::create_dir_all(&nested).unwrap();
+        let file_path = nested.join("existing.txt");
+        std::fs::write(&file_path, b"hi").unwrap();
+        let roots = vec![root.to_string_lossy().into_owned()];
+
+        store(&dir.path().join("cache"), &roots, &walk_key(), &[], &no_cancel());
+        assert!(try_load(&dir.path().join("cache"), &roots, &walk_key()).is_some());
+
+        wait_past_mtime_resolution();
+        let mut f = std::fs::OpenOptions::new().write(true).open(&file_path).unwrap();
+        f.write_all(b"changed").unwrap();
+        drop(f);
+
+        assert!(
+            try_load(&dir.path().join("cache"), &roots, &walk_key()).is_none(),
+            "editing a file's contents in place (no add/remove) should still invalidate the cache"
+        );
+    }
+
+    #[test]
+    fn removing_a_nested_file_invalidates_the_cache() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        let nested = root.join("a");
+        std::fs::create_dir_all(&nested).unwrap();
+        let file_path = nested.join("existing.txt");
+        std::fs::write(&file_path, b"hi").unwrap();
+        let roots = vec![root.to_string_lossy().into_owned()];
+
+        store(&dir.path().join("cache"), &roots, &walk_key(), &[], &no_cancel());
+        assert!(try_load(&dir.path().join("cache"), &roots, &walk_key()).is_some());
+
+        wait_past_mtime_resolution();
+        std::fs::remove_file(&file_path).unwrap();
+
+        assert!(
+            try_load(&dir.path().join("cache"), &roots, &walk_key()).is_none(),
+            "removing a nested file should invalidate the cache"
+        );
+    }
+
+    #[test]
+    fn a_cache_built_under_one_walk_configuration_misses_under_a_different_one() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.txt"), b"hi").unwrap();
+        let roots = vec![root.to_string_lossy().into_owned()];
+
+        let gitignore_off = WalkKey { respect_gitignore: false, include_hidden: true, max_depth: None };
+        let gitignore_on = WalkKey { respect_gitignore: true, include_hidden: false, max_depth: None };
+
+        store(&dir.path().join("cache"), &roots, &gitignore_off, &[], &no_cancel());
+        assert!(try_load(&dir.path().join("cache"), &roots, &gitignore_off).is_some());
+        assert!(
+            try_load(&dir.path().join("cache"), &roots, &gitignore_on).is_none(),
+            "a cache built with respect_gitignore/include_hidden off should not be reused \
+             for a walk with different settings, even over the identical unchanged root"
+        );
+    }
+}
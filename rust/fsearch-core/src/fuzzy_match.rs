@@ -0,0 +1,267 @@
+//! fzf/nucleo-style fuzzy matching: scores how well a short query matches a
+//! candidate string when the query's characters are typed in order but not
+//! necessarily contiguously (e.g. query `fb` matches candidate `FooBar`),
+//! and reports which candidate byte ranges were actually matched so a
+//! caller can bold them for the user. Used by `search`'s `fz:` query mode.
+//!
+//! This is a from-scratch, dependency-free re-implementation of the
+//! Smith-Waterman-style dynamic program fzf v2/nucleo are built on, not a
+//! port of either: a score matrix `m` tracks the best score for matching
+//! the first `i` query characters ending at candidate character `j`, a
+//! parallel matrix `consecutive` tracks the run length of an unbroken
+//! match ending there (for the consecutive-match bonus), and a third,
+//! `gap_run`, tracks how many candidate characters in a row were skipped
+//! (so the first skipped character after a match costs more than the
+//! next one, matching how fzf penalizes opening a new gap more than
+//! extending an existing one).
+
+use alloc::vec::Vec;
+
+const MATCH_SCORE: i64 = 16;
+const GAP_START_PENALTY: i64 = 3;
+const GAP_EXTENSION_PENALTY: i64 = 1;
+/// Bonus for a character right after a path/word separator (`/_-. `).
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_FIRST_CHAR: i64 = 2;
+/// Bonus for a lowercase-to-uppercase transition (`camelCase`, `PascalCase`).
+const BONUS_CAMEL: i64 = 7;
+/// Extra bonus added on top of `MATCH_SCORE` for each match that extends
+/// an already-running consecutive match.
+const BONUS_CONSECUTIVE: i64 = 8;
+
+const NEG_INF: i64 = i64::MIN / 2;
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '/' | '_' | '-' | '.' | ' ')
+}
+
+/// Case-insensitive (Unicode-aware) character equality.
+fn ci_eq(a: char, b: char) -> bool {
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Smart-case: if the query contains an uppercase letter, the whole match
+/// becomes case-sensitive; otherwise it's case-insensitive.
+fn matches_char(query_has_upper: bool, q: char, c: char) -> bool {
+    if query_has_upper {
+        q == c
+    } else {
+        ci_eq(q, c)
+    }
+}
+
+/// The per-position bonus for starting (or continuing into) a match at
+/// `chars[j]`, based on what precedes it in the candidate.
+fn bonus_at(chars: &[char], j: usize) -> i64 {
+    if j == 0 {
+        return BONUS_FIRST_CHAR;
+    }
+    let prev = chars[j - 1];
+    if is_separator(prev) {
+        BONUS_BOUNDARY
+    } else if prev.is_lowercase() && chars[j].is_uppercase() {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    None,
+    Match,
+    Gap,
+}
+
+/// The result of a successful fuzzy match: `score` ranks candidates
+/// (higher is a better match) and `ranges` are the non-overlapping,
+/// left-to-right byte ranges in `candidate` that were matched, already
+/// collapsed into contiguous runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// Score `candidate` against `query`, returning `None` if `query`'s
+/// characters don't all occur in `candidate` in order. An empty `query`
+/// trivially matches everything with a score of `0` and no ranges.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let q_chars: Vec<char> = query.chars().collect();
+    let c_chars: Vec<char> = candidate.chars().collect();
+    let m = q_chars.len();
+    let n = c_chars.len();
+
+    if m == 0 {
+        return Some(FuzzyMatch { score: 0, ranges: Vec::new() });
+    }
+    if n < m {
+        return None;
+    }
+
+    let query_has_upper = q_chars.iter().any(|c| c.is_uppercase());
+    let bonus: Vec<i64> = (0..n).map(|j| bonus_at(&c_chars, j)).collect();
+
+    // All matrices are (m+1) x (n+1), 1-indexed into q_chars/c_chars so row/
+    // column 0 represent "zero characters considered yet".
+    let cols = n + 1;
+    let mut score = alloc::vec![NEG_INF; (m + 1) * cols];
+    let mut consecutive = alloc::vec![0i64; (m + 1) * cols];
+    let mut gap_run = alloc::vec![0usize; (m + 1) * cols];
+    let mut step = alloc::vec![Step::None; (m + 1) * cols];
+    let at = |i: usize, j: usize| i * cols + j;
+
+    for j in 0..=n {
+        score[at(0, j)] = 0;
+    }
+
+    for i in 1..=m {
+        for j in 1..=n {
+            let gap_score = if score[at(i, j - 1)] <= NEG_INF {
+                NEG_INF
+            } else {
+                let penalty = if gap_run[at(i, j - 1)] == 0 { GAP_START_PENALTY } else { GAP_EXTENSION_PENALTY };
+                score[at(i, j - 1)] - penalty
+            };
+
+            let mut match_score = NEG_INF;
+            let mut match_consec = 0;
+            if matches_char(query_has_upper, q_chars[i - 1], c_chars[j - 1]) && score[at(i - 1, j - 1)] > NEG_INF {
+                match_consec = consecutive[at(i - 1, j - 1)] + 1;
+                let mut s = score[at(i - 1, j - 1)] + MATCH_SCORE + bonus[j - 1];
+                if match_consec > 1 {
+                    s += BONUS_CONSECUTIVE;
+                }
+                match_score = s;
+            }
+
+            if match_score >= gap_score && match_score > NEG_INF {
+                score[at(i, j)] = match_score;
+                consecutive[at(i, j)] = match_consec;
+                gap_run[at(i, j)] = 0;
+                step[at(i, j)] = Step::Match;
+            } else if gap_score > NEG_INF {
+                score[at(i, j)] = gap_score;
+                consecutive[at(i, j)] = 0;
+                gap_run[at(i, j)] = gap_run[at(i, j - 1)] + 1;
+                step[at(i, j)] = Step::Gap;
+            }
+        }
+    }
+
+    let (best_j, best_score) = (1..=n)
+        .map(|j| (j, score[at(m, j)]))
+        .max_by_key(|&(_, s)| s)?;
+    if best_score <= NEG_INF {
+        return None;
+    }
+
+    // Backtrack from (m, best_j) to recover which candidate characters
+    // were actually matched.
+    let mut matched_chars = Vec::new();
+    let (mut i, mut j) = (m, best_j);
+    while i > 0 {
+        match step[at(i, j)] {
+            Step::Match => {
+                matched_chars.push(j - 1);
+                i -= 1;
+                j -= 1;
+            }
+            Step::Gap => j -= 1,
+            Step::None => break,
+        }
+    }
+    matched_chars.reverse();
+
+    // Byte offset of each candidate char, plus one past the end, so
+    // matched_chars[k] maps to char_byte_starts[matched_chars[k]]..
+    // char_byte_starts[matched_chars[k] + 1].
+    let mut char_byte_starts = Vec::with_capacity(n + 1);
+    let mut offset = 0;
+    for c in &c_chars {
+        char_byte_starts.push(offset);
+        offset += c.len_utf8();
+    }
+    char_byte_starts.push(candidate.len());
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &ci in &matched_chars {
+        let (start, end) = (char_byte_starts[ci], char_byte_starts[ci + 1]);
+        match ranges.last_mut() {
+            Some((_, last_end)) if *last_end == start => *last_end = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    Some(FuzzyMatch { score: best_score, ranges })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_candidates_missing_a_query_character() {
+        assert_eq!(fuzzy_match("xyz", "foobar"), None);
+    }
+
+    #[test]
+    fn matches_a_contiguous_substring() {
+        let m = fuzzy_match("foo", "foobar").unwrap();
+        assert_eq!(m.ranges, alloc::vec![(0, 3)]);
+    }
+
+    #[test]
+    fn matches_scattered_characters_in_order() {
+        let m = fuzzy_match("fb", "foobar").unwrap();
+        assert_eq!(m.ranges, alloc::vec![(0, 1), (3, 4)]);
+    }
+
+    #[test]
+    fn camelcase_boundary_scores_higher_than_a_mid_word_match() {
+        // both candidates have a 'b' at the same index, matched
+        // case-insensitively by the lowercase query; "FooBar"'s is a
+        // camelCase boundary (follows a lowercase letter with an
+        // uppercase one) and should score higher than "foobar"'s, which
+        // isn't.
+        let camel = fuzzy_match("b", "FooBar").unwrap();
+        let plain = fuzzy_match("b", "foobar").unwrap();
+        assert!(camel.score > plain.score);
+    }
+
+    #[test]
+    fn is_case_insensitive_without_an_uppercase_query_char() {
+        let m = fuzzy_match("foo", "FOOBAR").unwrap();
+        assert_eq!(m.ranges, alloc::vec![(0, 3)]);
+    }
+
+    #[test]
+    fn smart_case_forces_exact_match_when_query_has_uppercase() {
+        assert_eq!(fuzzy_match("Foo", "foobar"), None);
+        assert!(fuzzy_match("Foo", "Foobar").is_some());
+    }
+
+    #[test]
+    fn separator_boundary_scores_higher_than_a_mid_word_match() {
+        // both "foo_bar" and "foobar" contain a 'b' that could match a
+        // one-char query, but the one right after the '_' separator
+        // should score higher.
+        let after_sep = fuzzy_match("b", "foo_bar").unwrap();
+        let mid_word = fuzzy_match("b", "foobar").unwrap();
+        assert!(after_sep.score > mid_word.score);
+    }
+
+    #[test]
+    fn empty_query_matches_trivially() {
+        let m = fuzzy_match("", "anything").unwrap();
+        assert_eq!(m.score, 0);
+        assert!(m.ranges.is_empty());
+    }
+
+    #[test]
+    fn consecutive_matches_score_higher_than_scattered_ones() {
+        let contiguous = fuzzy_match("bar", "foobarbaz").unwrap();
+        let scattered = fuzzy_match("bar", "b_a_r_baz").unwrap();
+        assert!(contiguous.score > scattered.score);
+    }
+}
@@ -1,20 +1,82 @@
 use crate::entry::Entry;
+use crate::interner::PathInterner;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+#[cfg(feature = "std")]
 use std::path::PathBuf;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicBool, Ordering};
 
-#[derive(Clone)]
-pub struct Index {
+/// Controls how `Index::build_from_paths_with_options` walks the
+/// filesystem, mirrored by four fields on `search::SearchOptions` (see
+/// that type's doc comment for why the two don't just share one struct).
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct WalkOptions {
+    /// Skip paths excluded by `.gitignore`/`.ignore`/global git excludes,
+    /// ripgrep-style.
+    pub respect_gitignore: bool,
+    /// Include dotfiles and dot-directories in the walk.
+    pub include_hidden: bool,
+    /// Maximum descent depth from each root, or `None` for unlimited.
+    pub max_depth: Option<usize>,
+    /// Persist the walk's result under this path (see `fs_cache`) and
+    /// reuse it on a later call over the identical root set instead of
+    /// re-walking, as long as none of those roots has changed since.
+    pub cache: Option<PathBuf>,
+}
+
+#[cfg(feature = "std")]
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions { respect_gitignore: false, include_hidden: true, max_depth: None, cache: None }
+    }
+}
+
+/// The entry storage and directory interner backing an `Index`. Held
+/// behind an `Arc` (see `Index`) so handing out a snapshot -- e.g. the FFI
+/// boundary stashing one clone in `CURRENT_INDEX` while returning another
+/// to the caller -- is a refcount bump rather than a deep copy of every
+/// entry and its interned paths.
+pub struct IndexData {
     pub entries: Vec<Entry>,
+    interner: PathInterner,
     next_id: u64,
 }
 
+/// A snapshot of the indexed file tree. Cheap to `clone` (an `Arc` bump):
+/// only the builder that holds the sole reference during `build_from_paths`
+/// mutates the underlying `IndexData` in place.
+#[derive(Clone)]
+pub struct Index {
+    data: Arc<IndexData>,
+}
+
+impl Deref for Index {
+    type Target = IndexData;
+    fn deref(&self) -> &IndexData {
+        &self.data
+    }
+}
+
 impl Index {
     pub fn new() -> Self {
         Index {
-            entries: Vec::new(),
-            next_id: 1,
+            data: Arc::new(IndexData {
+                entries: Vec::new(),
+                interner: PathInterner::new(),
+                next_id: 1,
+            }),
         }
     }
 
+    /// Walk `paths` on the local filesystem, `stat`-ing every file found
+    /// underneath them into an `Entry`. Not available without the `std`
+    /// feature: a no_std embedder builds an `Index` by pushing `Entry`
+    /// values it gathered some other way directly onto `entries` instead.
+    #[cfg(feature = "std")]
     pub fn build_from_paths(&mut self, paths: Vec<String>) {
         for p in paths.into_iter() {
             let pb = PathBuf::from(p);
@@ -22,6 +84,7 @@ impl Index {
         }
     }
 
+    #[cfg(feature = "std")]
     fn visit_path(&mut self, path: PathBuf) {
         if let Ok(md) = std::fs::metadata(&path) {
             if md.is_dir() {
@@ -32,11 +95,244 @@ impl Index {
                     }
                 }
             } else {
-                let id = self.next_id;
-                self.next_id += 1;
-                let e = Entry::new(id, path);
-                self.entries.push(e);
+                let parent_dir = path.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+                let data = Arc::get_mut(&mut self.data).expect("Index is uniquely owned while being built");
+                let id = data.next_id;
+                data.next_id += 1;
+                let parent = data.interner.intern(&parent_dir);
+                let e = Entry::new(id, parent, &path);
+                data.entries.push(e);
             }
         }
     }
+
+    /// Like `build_from_paths`, but walks with `ignore`'s `WalkParallel`
+    /// instead of a plain recursive `read_dir`, so `options` can apply
+    /// gitignore/hidden-file/depth rules the way ripgrep does. `cancel` is
+    /// polled from every walker thread; once it's set (by `cancel_search`,
+    /// or a caller that registered this build's handle the same way -- see
+    /// `lib::start_index_build`), each thread's closure returns
+    /// `ignore::WalkState::Quit` so the walk winds down promptly instead of
+    /// finishing the directories already in flight. If `options.cache` is
+    /// set and still fresh for `paths`, the walk is skipped entirely in
+    /// favor of the cached result (see `fs_cache::try_load`); otherwise the
+    /// live walk's result is written back to it afterward.
+    #[cfg(feature = "std")]
+    pub fn build_from_paths_with_options(&mut self, paths: Vec<String>, options: WalkOptions, cancel: &Arc<AtomicBool>) {
+        let walk_key = crate::fs_cache::WalkKey {
+            respect_gitignore: options.respect_gitignore,
+            include_hidden: options.include_hidden,
+            max_depth: options.max_depth,
+        };
+
+        if let Some(cache_path) = options.cache.as_deref() {
+            if let Some(cached) = crate::fs_cache::try_load(cache_path, &paths, &walk_key) {
+                for c in cached {
+                    let data = Arc::get_mut(&mut self.data).expect("Index is uniquely owned while being built");
+                    let id = data.next_id;
+                    data.next_id += 1;
+                    let parent = data.interner.intern(&c.parent);
+                    let e = Entry::from_cached(id, parent, c.name, c.size, c.mtime);
+                    data.entries.push(e);
+                }
+                return;
+            }
+        }
+
+        let roots_snapshot = paths.clone();
+        let mut roots = paths.into_iter();
+        let first = match roots.next() {
+            Some(p) => p,
+            None => return,
+        };
+        let mut builder = ignore::WalkBuilder::new(&first);
+        for p in roots {
+            builder.add(p);
+        }
+        builder
+            .hidden(!options.include_hidden)
+            .git_ignore(options.respect_gitignore)
+            .git_global(options.respect_gitignore)
+            .git_exclude(options.respect_gitignore)
+            .ignore(options.respect_gitignore)
+            .max_depth(options.max_depth);
+
+        // `WalkParallel` fans out across its own thread pool and each
+        // per-thread closure only gets a shared `&self` view, but `Index`
+        // is only meant to be mutated by the single builder thread driving
+        // this call (see `IndexData`) -- so walker threads hand matches to
+        // this queue instead of touching `self.data` directly, and the
+        // builder thread drains it into `self.data` once the walk (or an
+        // early `Quit`) is done. Both `found` and `cancel` are `Arc`-backed
+        // so the per-thread closures below can be `'static`, as `run`
+        // requires, while this call still blocks until every thread exits.
+        let found: Arc<parking_lot::Mutex<Vec<PathBuf>>> = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        builder.build_parallel().run(|| {
+            let cancel = cancel.clone();
+            let found = found.clone();
+            Box::new(move |entry| {
+                if cancel.load(Ordering::SeqCst) {
+                    return ignore::WalkState::Quit;
+                }
+                if let Ok(entry) = entry {
+                    if entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                        found.lock().push(entry.into_path());
+                    }
+                }
+                ignore::WalkState::Continue
+            })
+        });
+
+        let found = Arc::try_unwrap(found).map(|m| m.into_inner()).unwrap_or_default();
+        let mut cache_entries = Vec::new();
+        for path in found {
+            if cancel.load(Ordering::SeqCst) {
+                break;
+            }
+            let parent_dir = path.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+            let data = Arc::get_mut(&mut self.data).expect("Index is uniquely owned while being built");
+            let id = data.next_id;
+            data.next_id += 1;
+            let parent = data.interner.intern(&parent_dir);
+            let e = Entry::new(id, parent, &path);
+            if options.cache.is_some() {
+                cache_entries.push(crate::fs_cache::CacheEntry {
+                    parent: parent_dir,
+                    name: e.name.clone(),
+                    size: e.size,
+                    mtime: e.mtime,
+                });
+            }
+            data.entries.push(e);
+        }
+
+        if let Some(cache_path) = options.cache.as_deref() {
+            if !cancel.load(Ordering::SeqCst) {
+                crate::fs_cache::store(cache_path, &roots_snapshot, &walk_key, &cache_entries, cancel);
+            }
+        }
+    }
+
+    /// Reconstruct `entry`'s full path from its interned parent directory
+    /// and file name. `Entry` itself only stores the small `(parent,
+    /// name)` pair, so callers that need the original full path string
+    /// (the FFI boundary, search results) go through the owning `Index`.
+    pub fn entry_path(&self, entry: &Entry) -> String {
+        let dir = self.data.interner.path_of(entry.parent);
+        if dir.is_empty() {
+            entry.name.clone()
+        } else {
+            let mut s = String::with_capacity(dir.len() + 1 + entry.name.len());
+            s.push_str(dir);
+            s.push('/');
+            s.push_str(&entry.name);
+            s
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use tempfile::tempdir;
+
+    fn names_of(idx: &Index) -> Vec<String> {
+        let mut names: Vec<String> = idx.entries.iter().map(|e| e.name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn respects_ignore_file_and_excludes_hidden_files_by_default() {
+        let dir = tempdir().expect("tempdir");
+        let p = dir.path();
+        // a plain `.ignore` file (unlike `.gitignore`) is honored even
+        // outside a git repository, which is what makes it the right fit
+        // for a hermetic test.
+        std::fs::write(p.join(".ignore"), b"excluded.txt\n").unwrap();
+        std::fs::write(p.join("excluded.txt"), b"x").unwrap();
+        std::fs::write(p.join("kept.txt"), b"x").unwrap();
+        std::fs::write(p.join(".hidden.txt"), b"x").unwrap();
+
+        let mut idx = Index::new();
+        let options = WalkOptions { respect_gitignore: true, include_hidden: false, max_depth: None, cache: None };
+        idx.build_from_paths_with_options(vec![p.to_string_lossy().into_owned()], options, &Arc::new(AtomicBool::new(false)));
+
+        assert_eq!(names_of(&idx), vec!["kept.txt".to_string()]);
+    }
+
+    #[test]
+    fn include_hidden_true_surfaces_dotfiles_ignore_file_still_applies() {
+        let dir = tempdir().expect("tempdir");
+        let p = dir.path();
+        std::fs::write(p.join(".ignore"), b"excluded.txt\n").unwrap();
+        std::fs::write(p.join("excluded.txt"), b"x").unwrap();
+        std::fs::write(p.join("kept.txt"), b"x").unwrap();
+        std::fs::write(p.join(".hidden.txt"), b"x").unwrap();
+
+        let mut idx = Index::new();
+        let options = WalkOptions { respect_gitignore: true, include_hidden: true, max_depth: None, cache: None };
+        idx.build_from_paths_with_options(vec![p.to_string_lossy().into_owned()], options, &Arc::new(AtomicBool::new(false)));
+
+        // `.ignore` is itself a dotfile and a real entry in the tree, so
+        // with hidden files included it's indexed just like `.hidden.txt`.
+        assert_eq!(names_of(&idx), vec![".hidden.txt".to_string(), ".ignore".to_string(), "kept.txt".to_string()]);
+    }
+
+    #[test]
+    fn cache_built_under_one_walk_configuration_is_not_reused_for_another() {
+        let dir = tempdir().expect("tempdir");
+        let p = dir.path();
+        std::fs::write(p.join(".ignore"), b"excluded.txt\n").unwrap();
+        std::fs::write(p.join("excluded.txt"), b"x").unwrap();
+        std::fs::write(p.join("kept.txt"), b"x").unwrap();
+        let cache_path = p.join("cache");
+
+        let mut idx = Index::new();
+        let filtered = WalkOptions {
+            respect_gitignore: true,
+            include_hidden: false,
+            max_depth: None,
+            cache: Some(cache_path.clone()),
+        };
+        idx.build_from_paths_with_options(vec![p.to_string_lossy().into_owned()], filtered, &Arc::new(AtomicBool::new(false)));
+        assert_eq!(names_of(&idx), vec!["kept.txt".to_string()]);
+
+        // Same unchanged root, but a different walk configuration: the
+        // cache from the filtered walk above must not be reused, or this
+        // would still come back with just "kept.txt".
+        let mut idx2 = Index::new();
+        let unfiltered = WalkOptions {
+            respect_gitignore: false,
+            include_hidden: true,
+            max_depth: None,
+            cache: Some(cache_path),
+        };
+        idx2.build_from_paths_with_options(vec![p.to_string_lossy().into_owned()], unfiltered, &Arc::new(AtomicBool::new(false)));
+        let names = names_of(&idx2);
+        assert!(names.contains(&"excluded.txt".to_string()));
+        assert!(names.contains(&".ignore".to_string()));
+    }
+
+    #[test]
+    fn respect_gitignore_false_surfaces_every_file_including_ignored_ones() {
+        let dir = tempdir().expect("tempdir");
+        let p = dir.path();
+        std::fs::write(p.join(".ignore"), b"excluded.txt\n").unwrap();
+        std::fs::write(p.join("excluded.txt"), b"x").unwrap();
+        std::fs::write(p.join("kept.txt"), b"x").unwrap();
+
+        let mut idx = Index::new();
+        let options = WalkOptions { respect_gitignore: false, include_hidden: true, max_depth: None, cache: None };
+        idx.build_from_paths_with_options(vec![p.to_string_lossy().into_owned()], options, &Arc::new(AtomicBool::new(false)));
+
+        let names = names_of(&idx);
+        assert!(names.contains(&"excluded.txt".to_string()));
+        assert!(names.contains(&"kept.txt".to_string()));
+        // the `.ignore` file itself is a dotfile and a real entry in the
+        // tree, so with hidden files included it's indexed like any other.
+        assert!(names.contains(&".ignore".to_string()));
+    }
 }
@@ -0,0 +1,114 @@
+//! Interning for the parent-directory path shared by every `Entry` in the
+//! same directory, so `Index` can store each entry as a small
+//! `(parent id, file name)` pair instead of a full path `String` per file --
+//! on a tree with thousands of files per directory this avoids repeating
+//! the same directory-prefix text once per file underneath it.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// A contiguous byte buffer strings are bump-allocated into: each `push`
+/// appends to the buffer and hands back a lightweight `(offset, len)`
+/// handle, so interning many distinct directory paths amortizes allocation
+/// instead of each taking its own heap `String`.
+struct StringArena {
+    buf: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StringHandle {
+    offset: u32,
+    len: u32,
+}
+
+impl StringArena {
+    fn new() -> Self {
+        StringArena { buf: String::new() }
+    }
+
+    fn push(&mut self, s: &str) -> StringHandle {
+        let offset = self.buf.len() as u32;
+        self.buf.push_str(s);
+        StringHandle { offset, len: s.len() as u32 }
+    }
+
+    fn get(&self, h: StringHandle) -> &str {
+        &self.buf[h.offset as usize..(h.offset + h.len) as usize]
+    }
+}
+
+/// A directory path interned into a [`PathInterner`]. Cheap to copy and
+/// store on every `Entry`; reconstruct the actual path via
+/// `PathInterner::path_of`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DirId(u32);
+
+/// Deduplicates directory path strings: interning the same path twice
+/// returns the same `DirId` and bump-allocates its bytes only once.
+pub struct PathInterner {
+    arena: StringArena,
+    handles: Vec<StringHandle>,
+    lookup: BTreeMap<String, DirId>,
+}
+
+impl PathInterner {
+    pub fn new() -> Self {
+        PathInterner { arena: StringArena::new(), handles: Vec::new(), lookup: BTreeMap::new() }
+    }
+
+    /// Intern `dir`, returning its existing id if it's already been
+    /// interned, or bump-allocating its bytes and assigning a fresh one.
+    pub fn intern(&mut self, dir: &str) -> DirId {
+        if let Some(&id) = self.lookup.get(dir) {
+            return id;
+        }
+        let handle = self.arena.push(dir);
+        let id = DirId(self.handles.len() as u32);
+        self.handles.push(handle);
+        self.lookup.insert(dir.to_string(), id);
+        id
+    }
+
+    /// The directory path `id` was interned from.
+    pub fn path_of(&self, id: DirId) -> &str {
+        self.arena.get(self.handles[id.0 as usize])
+    }
+}
+
+impl Default for PathInterner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning_the_same_dir_twice_returns_the_same_id() {
+        let mut interner = PathInterner::new();
+        let a = interner.intern("/some/dir");
+        let b = interner.intern("/some/dir");
+        assert_eq!(a, b);
+        assert_eq!(interner.path_of(a), "/some/dir");
+    }
+
+    #[test]
+    fn distinct_dirs_get_distinct_ids() {
+        let mut interner = PathInterner::new();
+        let a = interner.intern("/some/dir");
+        let b = interner.intern("/other/dir");
+        assert_ne!(a, b);
+        assert_eq!(interner.path_of(a), "/some/dir");
+        assert_eq!(interner.path_of(b), "/other/dir");
+    }
+
+    #[test]
+    fn empty_dir_interns_fine_for_top_level_entries() {
+        let mut interner = PathInterner::new();
+        let root = interner.intern("");
+        assert_eq!(interner.path_of(root), "");
+    }
+}
@@ -1,23 +1,59 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
 // Minimal Rust skeleton for fsearch-core with a cxx bridge.
 // Expand modules (index, query, search) here.
+//
+// `std` (on by default) covers everything that needs the OS: filesystem
+// access (`entry::Entry::new`, `index::Index::build_from_paths`), PCRE2
+// (`pcre2_pool`, `pcre2_backend`, and the `query` submodules built on top
+// of them), and the threaded `search` worker. `ffi` (on by default, implies
+// `std`) adds the `cxx::bridge` and the `#[no_mangle]` C-ABI wrappers the
+// Qt client links against. With both off, `entry`, `index`, the lexer/
+// parser/`prefilter` half of `query`, and `fallback_matcher` still build
+// under `#![no_std]` + `extern crate alloc`, so the query parser and index
+// can be embedded in constrained or WASM targets that can't link PCRE2 or
+// the C++ bridge. `matchers` (the `regex`-crate backend) is not part of
+// that build: it is compiled only with `std`.
+
+extern crate alloc;
 
 pub mod entry;
 pub mod index;
+pub mod interner;
 pub mod query;
+#[cfg(feature = "ffi")]
 mod search;
+#[cfg(feature = "std")]
+mod fs_cache;
+#[cfg(feature = "std")]
 pub mod matchers;
+pub mod fallback_matcher;
+pub mod fuzzy_match;
+#[cfg(feature = "std")]
 pub mod pcre2_pool;
+#[cfg(feature = "std")]
 pub mod pcre2_backend;
+#[cfg(feature = "std")]
+mod byte_prefilter;
 pub mod match_engine;
+#[cfg(feature = "std")]
+pub mod stream_matcher;
 
+#[cfg(feature = "ffi")]
 use index::Index;
+#[cfg(feature = "ffi")]
 use parking_lot::Mutex;
+#[cfg(feature = "ffi")]
 use once_cell::sync::Lazy;
+#[cfg(feature = "ffi")]
 use std::sync::Arc;
+#[cfg(feature = "ffi")]
 use search as search_mod;
 
+#[cfg(feature = "ffi")]
 static CURRENT_INDEX: Lazy<Mutex<Option<Arc<Index>>>> = Lazy::new(|| Mutex::new(None));
 
+#[cfg(feature = "ffi")]
 #[cxx::bridge]
 mod ffi {
     struct SearchResult {
@@ -29,6 +65,24 @@ mod ffi {
         highlights: String,
     }
 
+    // Case-sensitivity mode for the substring/regex fallback matcher only;
+    // the compiled-AST and `fz:` fuzzy paths have their own case rules and
+    // ignore this (see `search::MatchOptions`).
+    enum CaseMode {
+        Sensitive,
+        Insensitive,
+        Smart,
+    }
+
+    #[derive(Clone, Copy)]
+    struct MatchOptions {
+        case_mode: CaseMode,
+        /// NFKC-normalize the query and candidate text before comparing,
+        /// so precomposed and decomposed Unicode forms (e.g. `é` vs `e` +
+        /// U+0301) match consistently.
+        unicode_normalize: bool,
+    }
+
     extern "Rust" {
         type Index;
 
@@ -44,28 +98,96 @@ mod ffi {
         fn start_search(query: &str) -> u64;
         fn poll_results(handle: u64) -> Vec<SearchResult>;
         fn cancel_search(handle: u64);
+
+        // ranked top-N search: keeps only the best `limit` results instead
+        // of streaming every match (see `search::start_search_with_index_ranked`).
+        fn start_search_ranked(query: &str, limit: usize) -> u64;
+        fn poll_ranked_results(handle: u64) -> Vec<SearchResult>;
+
+        // incremental re-query on a `start_search` handle, reusing its
+        // channel and debouncing a burst of calls into one restart (see
+        // `search::update_search`).
+        fn update_search(handle: u64, new_query: &str) -> bool;
+
+        // `start_search`/`start_search_ranked` variants that take explicit
+        // case/normalization flags for the fallback matcher instead of
+        // relying on its hardcoded case-insensitive `to_lowercase()` (see
+        // `search::MatchOptions`). Kept separate from `start_search` et al.
+        // so existing callers and the ABI they rely on are untouched.
+        fn start_search_with_options(query: &str, options: MatchOptions) -> u64;
+        fn start_search_ranked_with_options(query: &str, limit: usize, options: MatchOptions) -> u64;
+
+        // first-match / early-termination search: stops once `max_results`
+        // hits have been found instead of scanning the whole index (see
+        // `search::SearchOptions::max_results`). `max_results == 0` means
+        // unlimited. Added as separate entry points for the same ABI-
+        // stability reason as `start_search_with_options` above.
+        fn start_search_first_n(query: &str, max_results: usize) -> u64;
+        fn start_search_first_n_with_options(query: &str, max_results: usize, options: MatchOptions) -> u64;
+
+        // gitignore-aware background index (re)build, cancellable via the
+        // same `cancel_search`/`outcome` handle API as a search (see
+        // `search::register_cancelable`). `max_depth == 0` means unlimited.
+        // `cache_path == ""` means don't use an on-disk cache (see
+        // `fs_cache` and `index::WalkOptions::cache`).
+        fn start_index_build(paths: Vec<String>, respect_gitignore: bool, include_hidden: bool, max_depth: usize, cache_path: String) -> u64;
     }
 }
 
+#[cfg(feature = "ffi")]
 pub fn init() -> bool {
     // Initialize internal state, logging, etc.
     true
 }
 
+#[cfg(feature = "ffi")]
 pub fn index_new() -> Box<Index> {
     Box::new(Index::new())
 }
 
+#[cfg(feature = "ffi")]
 pub fn index_build_from_paths(paths: Vec<String>) -> Box<Index> {
     let mut idx = Index::new();
     idx.build_from_paths(paths);
-    // clone for returning a boxed Index while storing an Arc in the global
-    let idx_clone = idx.clone();
-    let arc = Arc::new(idx);
+    // `Index::clone` is an `Arc` bump, not a deep copy, so stashing one
+    // snapshot in the global while returning another to the caller doesn't
+    // duplicate the entries or interned paths.
+    let arc = Arc::new(idx.clone());
     *CURRENT_INDEX.lock() = Some(arc);
-    Box::new(idx_clone)
+    Box::new(idx)
+}
+
+/// Rebuild the index in the background, honoring gitignore/hidden-file/
+/// depth rules (see `index::WalkOptions`) and cancellable the same way a
+/// search is (see `search::register_cancelable`). `max_depth == 0` means
+/// unlimited, matching the `max_results`/`batch_size` 0-sentinel
+/// convention used elsewhere in this module. `cache_path == ""` means no
+/// on-disk cache; otherwise a previous build over the identical `paths` is
+/// reused verbatim if none of them has changed since (see `fs_cache`). On
+/// normal completion `CURRENT_INDEX` is replaced with the freshly built
+/// index; a build that's cancelled mid-walk leaves `CURRENT_INDEX`
+/// untouched.
+#[cfg(feature = "ffi")]
+pub fn start_index_build(paths: Vec<String>, respect_gitignore: bool, include_hidden: bool, max_depth: usize, cache_path: String) -> u64 {
+    let options = index::WalkOptions {
+        respect_gitignore,
+        include_hidden,
+        max_depth: if max_depth == 0 { None } else { Some(max_depth) },
+        cache: if cache_path.is_empty() { None } else { Some(std::path::PathBuf::from(cache_path)) },
+    };
+    let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let cancel_clone = cancel.clone();
+    let join = std::thread::spawn(move || {
+        let mut idx = Index::new();
+        idx.build_from_paths_with_options(paths, options, &cancel_clone);
+        if !cancel_clone.load(std::sync::atomic::Ordering::SeqCst) {
+            *CURRENT_INDEX.lock() = Some(Arc::new(idx));
+        }
+    });
+    search_mod::register_cancelable(cancel, join)
 }
 
+#[cfg(feature = "ffi")]
 pub fn index_list_entries(index: &Index) -> Vec<ffi::SearchResult> {
     index
         .entries
@@ -73,7 +195,7 @@ pub fn index_list_entries(index: &Index) -> Vec<ffi::SearchResult> {
         .map(|e| ffi::SearchResult {
             id: e.id,
             name: e.name.clone(),
-            path: e.path.clone(),
+            path: index.entry_path(e),
             size: e.size,
             mtime: e.mtime,
             highlights: String::new(),
@@ -81,31 +203,113 @@ pub fn index_list_entries(index: &Index) -> Vec<ffi::SearchResult> {
         .collect()
 }
 
+#[cfg(feature = "ffi")]
 pub fn start_search(_query: &str) -> u64 {
     // start search against the current index snapshot
     if let Some(idx) = &*CURRENT_INDEX.lock() {
-        return search_mod::start_search_with_index(idx.clone(), _query);
+        return search_mod::start_search_with_index(idx.clone(), _query, ffi::MatchOptions::default(), search_mod::SearchOptions::default());
+    }
+    0
+}
+
+#[cfg(feature = "ffi")]
+pub fn start_search_with_options(_query: &str, options: ffi::MatchOptions) -> u64 {
+    if let Some(idx) = &*CURRENT_INDEX.lock() {
+        return search_mod::start_search_with_index(idx.clone(), _query, options, search_mod::SearchOptions::default());
+    }
+    0
+}
+
+/// `max_results == 0` means unlimited, matching the `batch_size` sentinel
+/// `start_search_with_cb` already uses.
+#[cfg(feature = "ffi")]
+fn search_options_from_max_results(max_results: usize) -> search_mod::SearchOptions {
+    search_mod::SearchOptions {
+        max_results: if max_results == 0 { None } else { Some(max_results) },
+        ..Default::default()
+    }
+}
+
+/// First-match / early-termination search, modeled on `hunt`'s `--first`
+/// flag: stops the whole search once `max_results` hits have been found
+/// instead of scanning the rest of the index (see
+/// `search::SearchOptions::max_results`). `max_results == 0` means
+/// unlimited, i.e. behaves like `start_search`.
+#[cfg(feature = "ffi")]
+pub fn start_search_first_n(_query: &str, max_results: usize) -> u64 {
+    if let Some(idx) = &*CURRENT_INDEX.lock() {
+        return search_mod::start_search_with_index(idx.clone(), _query, ffi::MatchOptions::default(), search_options_from_max_results(max_results));
+    }
+    0
+}
+
+/// Like `start_search_first_n`, but with explicit case/normalization flags
+/// for the fallback matcher (see `start_search_with_options`).
+#[cfg(feature = "ffi")]
+pub fn start_search_first_n_with_options(_query: &str, max_results: usize, options: ffi::MatchOptions) -> u64 {
+    if let Some(idx) = &*CURRENT_INDEX.lock() {
+        return search_mod::start_search_with_index(idx.clone(), _query, options, search_options_from_max_results(max_results));
     }
     0
 }
 
+#[cfg(feature = "ffi")]
 pub fn poll_results(_handle: u64) -> Vec<ffi::SearchResult> {
     search_mod::poll_results(_handle)
 }
 
+#[cfg(feature = "ffi")]
 pub fn cancel_search(_handle: u64) {
     search_mod::cancel_search(_handle)
 }
 
+#[cfg(feature = "ffi")]
+pub fn update_search(handle: u64, new_query: &str) -> bool {
+    search_mod::update_search(handle, new_query)
+}
+
+#[cfg(feature = "ffi")]
+pub fn start_search_with_cb(_query: &str, cb: FsearchResultCb, userdata: *mut c_void, batch_size: usize) -> u64 {
+    if let Some(idx) = &*CURRENT_INDEX.lock() {
+        return search_mod::start_search_with_index_and_cb(idx.clone(), _query, cb, userdata, batch_size, ffi::MatchOptions::default());
+    }
+    0
+}
+
+#[cfg(feature = "ffi")]
+pub fn start_search_ranked(_query: &str, _limit: usize) -> u64 {
+    if let Some(idx) = &*CURRENT_INDEX.lock() {
+        return search_mod::start_search_with_index_ranked(idx.clone(), _query, _limit, ffi::MatchOptions::default());
+    }
+    0
+}
+
+#[cfg(feature = "ffi")]
+pub fn start_search_ranked_with_options(_query: &str, _limit: usize, options: ffi::MatchOptions) -> u64 {
+    if let Some(idx) = &*CURRENT_INDEX.lock() {
+        return search_mod::start_search_with_index_ranked(idx.clone(), _query, _limit, options);
+    }
+    0
+}
+
+#[cfg(feature = "ffi")]
+pub fn poll_ranked_results(_handle: u64) -> Vec<ffi::SearchResult> {
+    search_mod::poll_ranked_results(_handle)
+}
+
 // C ABI wrappers for simple interop with a Qt C++ client
+#[cfg(feature = "ffi")]
 use std::ffi::CStr;
+#[cfg(feature = "ffi")]
 use std::os::raw::{c_char, c_void};
 
+#[cfg(feature = "ffi")]
 #[no_mangle]
 pub extern "C" fn fsearch_init() -> bool {
     init()
 }
 
+#[cfg(feature = "ffi")]
 #[no_mangle]
 pub extern "C" fn fsearch_index_build_from_paths_c(paths: *const *const c_char, count: usize) -> *mut Index {
     if paths.is_null() || count == 0 {
@@ -124,6 +328,7 @@ pub extern "C" fn fsearch_index_build_from_paths_c(paths: *const *const c_char,
     Box::into_raw(boxed)
 }
 
+#[cfg(feature = "ffi")]
 #[no_mangle]
 pub extern "C" fn fsearch_index_free(ptr: *mut Index) {
     if ptr.is_null() {
@@ -132,8 +337,10 @@ pub extern "C" fn fsearch_index_free(ptr: *mut Index) {
     unsafe { drop(Box::from_raw(ptr)); }
 }
 
+#[cfg(feature = "ffi")]
 pub type FsearchResultCb = extern "C" fn(u64, *const c_char, *const c_char, u64, u64, *const c_char, *mut c_void);
 
+#[cfg(feature = "ffi")]
 #[no_mangle]
 pub extern "C" fn fsearch_index_list_entries_c(ptr: *mut Index, cb: Option<FsearchResultCb>, userdata: *mut c_void) {
     if ptr.is_null() || cb.is_none() {
@@ -151,6 +358,7 @@ pub extern "C" fn fsearch_index_list_entries_c(ptr: *mut Index, cb: Option<Fsear
     }
 }
 
+#[cfg(feature = "ffi")]
 #[no_mangle]
 pub extern "C" fn fsearch_start_search_c(query: *const c_char) -> u64 {
     if query.is_null() {
@@ -160,6 +368,82 @@ pub extern "C" fn fsearch_start_search_c(query: *const c_char) -> u64 {
     start_search(&q)
 }
 
+/// `case_mode`: `0` = sensitive, `1` = insensitive, anything else = smart
+/// (see `ffi::CaseMode`).
+#[cfg(feature = "ffi")]
+fn match_options_from_c(case_mode: u8, unicode_normalize: bool) -> ffi::MatchOptions {
+    let case_mode = match case_mode {
+        0 => ffi::CaseMode::Sensitive,
+        1 => ffi::CaseMode::Insensitive,
+        _ => ffi::CaseMode::Smart,
+    };
+    ffi::MatchOptions { case_mode, unicode_normalize }
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_start_search_with_options_c(query: *const c_char, case_mode: u8, unicode_normalize: bool) -> u64 {
+    if query.is_null() {
+        return 0;
+    }
+    let q = unsafe { CStr::from_ptr(query).to_string_lossy().into_owned() };
+    start_search_with_options(&q, match_options_from_c(case_mode, unicode_normalize))
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_start_search_ranked_with_options_c(query: *const c_char, limit: usize, case_mode: u8, unicode_normalize: bool) -> u64 {
+    if query.is_null() {
+        return 0;
+    }
+    let q = unsafe { CStr::from_ptr(query).to_string_lossy().into_owned() };
+    start_search_ranked_with_options(&q, limit, match_options_from_c(case_mode, unicode_normalize))
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_start_index_build_c(paths: *const *const c_char, count: usize, respect_gitignore: bool, include_hidden: bool, max_depth: usize, cache_path: *const c_char) -> u64 {
+    if paths.is_null() || count == 0 {
+        return 0;
+    }
+    let slice = unsafe { std::slice::from_raw_parts(paths, count) };
+    let mut vec = Vec::with_capacity(count);
+    for &p in slice.iter() {
+        if p.is_null() {
+            continue;
+        }
+        let s = unsafe { CStr::from_ptr(p).to_string_lossy().into_owned() };
+        vec.push(s);
+    }
+    let cache_path = if cache_path.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(cache_path).to_string_lossy().into_owned() }
+    };
+    start_index_build(vec, respect_gitignore, include_hidden, max_depth, cache_path)
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_start_search_first_n_c(query: *const c_char, max_results: usize) -> u64 {
+    if query.is_null() {
+        return 0;
+    }
+    let q = unsafe { CStr::from_ptr(query).to_string_lossy().into_owned() };
+    start_search_first_n(&q, max_results)
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_start_search_first_n_with_options_c(query: *const c_char, max_results: usize, case_mode: u8, unicode_normalize: bool) -> u64 {
+    if query.is_null() {
+        return 0;
+    }
+    let q = unsafe { CStr::from_ptr(query).to_string_lossy().into_owned() };
+    start_search_first_n_with_options(&q, max_results, match_options_from_c(case_mode, unicode_normalize))
+}
+
+#[cfg(feature = "ffi")]
 #[no_mangle]
 pub extern "C" fn fsearch_poll_results_c(handle: u64, cb: Option<FsearchResultCb>, userdata: *mut c_void) {
     if cb.is_none() {
@@ -175,7 +459,54 @@ pub extern "C" fn fsearch_poll_results_c(handle: u64, cb: Option<FsearchResultCb
     }
 }
 
+#[cfg(feature = "ffi")]
 #[no_mangle]
 pub extern "C" fn fsearch_cancel_search_c(handle: u64) {
     cancel_search(handle)
 }
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_update_search_c(handle: u64, new_query: *const c_char) -> bool {
+    if new_query.is_null() {
+        return false;
+    }
+    let q = unsafe { CStr::from_ptr(new_query).to_string_lossy().into_owned() };
+    update_search(handle, &q)
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_start_search_with_cb_c(query: *const c_char, cb: Option<FsearchResultCb>, userdata: *mut c_void, batch_size: usize) -> u64 {
+    if query.is_null() || cb.is_none() {
+        return 0;
+    }
+    let q = unsafe { CStr::from_ptr(query).to_string_lossy().into_owned() };
+    start_search_with_cb(&q, cb.unwrap(), userdata, batch_size)
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_start_search_ranked_c(query: *const c_char, limit: usize) -> u64 {
+    if query.is_null() {
+        return 0;
+    }
+    let q = unsafe { CStr::from_ptr(query).to_string_lossy().into_owned() };
+    start_search_ranked(&q, limit)
+}
+
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub extern "C" fn fsearch_poll_ranked_results_c(handle: u64, cb: Option<FsearchResultCb>, userdata: *mut c_void) {
+    if cb.is_none() {
+        return;
+    }
+    let cb = cb.unwrap();
+    let list = poll_ranked_results(handle);
+    for r in list {
+        let name_c = std::ffi::CString::new(r.name).unwrap_or_default();
+        let path_c = std::ffi::CString::new(r.path).unwrap_or_default();
+        let highlights_c = std::ffi::CString::new(r.highlights).unwrap_or_default();
+        cb(r.id, name_c.as_ptr(), path_c.as_ptr(), r.size, r.mtime, highlights_c.as_ptr(), userdata);
+    }
+}
@@ -1,9 +1,24 @@
+use crate::query::LiteralPrefilter;
+#[cfg(feature = "std")]
 use crate::pcre2_pool::PatternPool;
+#[cfg(feature = "std")]
 use pcre2::Error as Pcre2Error;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Cheap rejection check to run before any real matcher
+/// (`CompiledPattern::is_match`/`QueryMatcher::is_match`) is invoked:
+/// `false` means `prefilter` has proven `text` can't possibly match the
+/// query it was built from, so the caller can skip straight to the next
+/// candidate. See [`LiteralPrefilter`] for what it can and can't prove.
+pub fn passes_prefilter(prefilter: &LiteralPrefilter, text: &[u8]) -> bool {
+    prefilter.could_match(text)
+}
 
 /// Match text using a pattern from the pool. If `is_regex` is true,
 /// the `pattern` is treated as a regex; otherwise it is treated as a
 /// literal substring (escaped for PCRE2).
+#[cfg(feature = "std")]
 pub fn match_text_pcre2(pool: &PatternPool, pattern: &str, text: &[u8], is_regex: bool) -> Result<Option<Vec<(usize, usize)>>, Pcre2Error> {
     if is_regex {
         let pat = pool.acquire_pcre2(pattern)?;
@@ -15,12 +30,32 @@ pub fn match_text_pcre2(pool: &PatternPool, pattern: &str, text: &[u8], is_regex
     }
 }
 
+/// Match literal text without PCRE2, for builds with the `std` feature (and
+/// the PCRE2 linkage it brings) disabled. `is_regex` queries have no engine
+/// to run against in this configuration, so they simply report no match
+/// rather than guessing at a partial regex implementation.
+#[cfg(not(feature = "std"))]
+pub fn match_text_fallback(pattern: &str, text: &[u8], is_regex: bool) -> Option<Vec<(usize, usize)>> {
+    if is_regex {
+        return None;
+    }
+    let ranges = crate::fallback_matcher::find_ranges(pattern.as_bytes(), text, false);
+    if ranges.is_empty() {
+        None
+    } else {
+        Some(ranges)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[cfg(feature = "std")]
     use crate::pcre2_pool::PatternPool;
 
     #[test]
+    #[cfg(feature = "std")]
     fn engine_literal_match() {
         let pool = PatternPool::new();
         let res = match_text_pcre2(&pool, "foo", b"this is foo bar", false).unwrap();
@@ -30,6 +65,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn engine_regex_match() {
         let pool = PatternPool::new();
         let res = match_text_pcre2(&pool, "ab([0-9]+)", b"xxab123yy", true).unwrap();
@@ -38,4 +74,24 @@ mod tests {
         assert_eq!(caps[0], (2, 7));
         assert_eq!(caps[1], (4, 7));
     }
+
+    #[test]
+    #[cfg(not(feature = "std"))]
+    fn fallback_finds_a_literal_match() {
+        let res = match_text_fallback("foo", b"this is foo bar", false);
+        assert_eq!(res, Some(vec![(8, 11)]));
+        assert_eq!(match_text_fallback("qux", b"this is foo bar", false), None);
+        assert_eq!(match_text_fallback("foo", b"this is foo bar", true), None);
+    }
+
+    #[test]
+    fn prefilter_rejects_candidates_missing_a_required_literal() {
+        use crate::query::parser_rs::Parser;
+        use crate::query::LiteralPrefilter;
+
+        let node = Parser::new("foo AND bar").parse().unwrap();
+        let prefilter = LiteralPrefilter::build(&node).unwrap();
+        assert!(passes_prefilter(&prefilter, b"foo bar baz"));
+        assert!(!passes_prefilter(&prefilter, b"foo only"));
+    }
 }
@@ -1,4 +1,5 @@
-use regex::bytes::Regex;
+use regex::bytes::{Regex, RegexSet};
+use smallvec::SmallVec;
 
 /// A small matcher abstraction. Currently uses Rust `regex` crate.
 /// It is structured so we can swap the internals to PCRE2 later.
@@ -6,17 +7,23 @@ pub struct Matcher {
     re: Regex,
 }
 
+/// The regex source to compile for `pattern`: itself unchanged if it's
+/// already a regex, or escaped so it matches only as a literal substring.
+/// Shared by `Matcher::new` and `MatcherSet::new` so the two never drift on
+/// what "literal" means.
+fn pattern_source(pattern: &str, is_regex: bool) -> String {
+    if is_regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    }
+}
+
 impl Matcher {
     /// Compile a pattern. The caller is responsible for deciding
     /// whether the input is a regex literal (e.g. `/pat/`) or a plain substring.
     pub fn new(pattern: &str, is_regex: bool) -> Result<Self, regex::Error> {
-        let pat = if is_regex {
-            pattern.to_string()
-        } else {
-            // escape plain text to build a regex that matches literal substrings
-            regex::escape(pattern)
-        };
-        let re = Regex::new(&pat)?;
+        let re = Regex::new(&pattern_source(pattern, is_regex))?;
         Ok(Matcher { re })
     }
 
@@ -34,6 +41,51 @@ impl Matcher {
                 .collect()
         })
     }
+
+    /// The capture group index for a named group, if any.
+    pub fn capture_name_index(&self, name: &str) -> Option<usize> {
+        self.re.capture_names().position(|n| n == Some(name))
+    }
+}
+
+/// Many patterns compiled into one combined automaton via `RegexSet`, so a
+/// filename is scanned once no matter how many saved filters or `OR`
+/// clauses it's being checked against, instead of once per pattern. Built
+/// from a slice of `(pattern, is_regex)` pairs, matching `Matcher::new`'s
+/// literal-escaping behavior via the shared `pattern_source`.
+///
+/// `RegexSet` has no PCRE2 equivalent (the `pcre2` crate compiles and scans
+/// one pattern at a time), so `match_text_pcre2` is unaffected -- this is a
+/// `regex`-only fast path for the "which of these clauses fired" query.
+pub struct MatcherSet {
+    set: RegexSet,
+}
+
+impl MatcherSet {
+    /// Compile `patterns` into one `RegexSet`. Each pair's `bool` is the
+    /// same `is_regex` flag `Matcher::new` takes: `true` for a regex,
+    /// `false` for a plain substring (escaped before compiling).
+    pub fn new<'a, I>(patterns: I) -> Result<Self, regex::Error>
+    where
+        I: IntoIterator<Item = (&'a str, bool)>,
+    {
+        let sources: Vec<String> = patterns.into_iter().map(|(p, is_regex)| pattern_source(p, is_regex)).collect();
+        let set = RegexSet::new(&sources)?;
+        Ok(MatcherSet { set })
+    }
+
+    /// Whether any pattern in the set matches `text`, without determining
+    /// which -- cheaper than `matching_indices` when the caller only needs
+    /// a yes/no answer (e.g. a prefilter before ranking per-pattern).
+    pub fn is_match_any(&self, text: &[u8]) -> bool {
+        self.set.is_match(text)
+    }
+
+    /// The index (into the slice `new` was built from) of every pattern
+    /// that matched `text`, found in a single scan over the bytes.
+    pub fn matching_indices(&self, text: &[u8]) -> SmallVec<[usize; 8]> {
+        self.set.matches(text).into_iter().collect()
+    }
 }
 
 #[cfg(test)]
@@ -57,4 +109,20 @@ mod tests {
         assert_eq!(ranges[1], (2, 4)); // group 1
         assert_eq!(ranges[2], (4, 7)); // group 2
     }
+
+    #[test]
+    fn matcher_set_reports_every_matching_index() {
+        let set = MatcherSet::new([("a.rs", false), ("b.toml", false), (r"log[0-9]+", true)]).unwrap();
+        let indices = set.matching_indices(b"log42");
+        assert_eq!(indices.as_slice(), &[2]);
+        assert!(set.is_match_any(b"a.rs"));
+        assert!(!set.is_match_any(b"nothing here"));
+    }
+
+    #[test]
+    fn matcher_set_can_match_more_than_one_pattern_at_once() {
+        let set = MatcherSet::new([("foo", false), ("o", false)]).unwrap();
+        let indices = set.matching_indices(b"foo");
+        assert_eq!(indices.as_slice(), &[0, 1]);
+    }
 }
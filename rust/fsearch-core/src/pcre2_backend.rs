@@ -33,6 +33,20 @@ impl Pcre2Compiled {
             _ => None,
         }
     }
+
+    /// The capture group index for a named group, if any.
+    pub fn capture_name_index(&self, name: &str) -> Option<usize> {
+        self.re.capture_names().position(|n| n == Some(name))
+    }
+
+    /// Every capture group's name in group-index order (group 0, the
+    /// whole match, is always `None`), snapshotted as owned `String`s so
+    /// callers that need this more than once don't have to keep a live
+    /// `Pcre2Compiled` around just to re-walk PCRE2's name table -- see
+    /// `pcre2_pool::PooledPcre2Pattern`.
+    pub(crate) fn capture_names_owned(&self) -> Vec<Option<String>> {
+        self.re.capture_names().map(|n| n.map(str::to_string)).collect()
+    }
 }
 
 #[cfg(test)]
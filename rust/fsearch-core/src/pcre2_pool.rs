@@ -2,70 +2,319 @@ use crate::matchers::Matcher;
 use crate::pcre2_backend::Pcre2Compiled;
 use pcre2::Error as Pcre2Error;
 use std::sync::Arc;
-use std::collections::VecDeque;
-use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::cell::{RefCell, UnsafeCell};
+use std::sync::atomic::{AtomicU64, Ordering};
+use parking_lot::Mutex;
 
-// Use a per-thread pool to reduce contention during heavy matching.
+/// Default number of distinct patterns a thread's cache keeps compiled
+/// before evicting the least-recently-used entry.
+const DEFAULT_CAPACITY: usize = 256;
+
+/// A per-thread, content-keyed LRU cache of compiled patterns: the key is
+/// the exact pattern source a caller compiled (which already encodes flags
+/// like `(?i)` case-insensitivity, since callers bake those into the
+/// pattern text before calling `acquire`/`acquire_pcre2`), so a cache hit
+/// can never hand back a pattern that doesn't match the request.
+struct PatternCache {
+    capacity: usize,
+    entries: HashMap<String, Arc<dyn CompiledPattern>>,
+    /// Key order from least- to most-recently-used.
+    order: VecDeque<String>,
+}
+
+impl PatternCache {
+    fn new(capacity: usize) -> Self {
+        PatternCache { capacity, entries: HashMap::new(), order: VecDeque::new() }
+    }
+
+    /// Look up `key`, promoting it to most-recently-used on a hit.
+    fn get(&mut self, key: &str) -> Option<Arc<dyn CompiledPattern>> {
+        let pat = self.entries.get(key).cloned()?;
+        self.touch(key);
+        Some(pat)
+    }
+
+    /// Insert a freshly compiled `pat` for `key`, evicting the
+    /// least-recently-used entry if the cache is at capacity.
+    fn insert(&mut self, key: String, pat: Arc<dyn CompiledPattern>) {
+        if self.entries.len() >= self.capacity {
+            if let Some(lru_key) = self.order.pop_front() {
+                self.entries.remove(&lru_key);
+            }
+        }
+        self.order.push_back(key.clone());
+        self.entries.insert(key, pat);
+    }
+
+    /// Move `key` to the most-recently-used end of `order`.
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let k = self.order.remove(pos).unwrap();
+            self.order.push_back(k);
+        }
+    }
+
+    /// Shrink/grow the cache's capacity, evicting least-recently-used
+    /// entries immediately if the new capacity is smaller.
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > self.capacity {
+            match self.order.pop_front() {
+                Some(lru_key) => { self.entries.remove(&lru_key); }
+                None => break,
+            }
+        }
+    }
+}
+
+// Use a per-thread cache to reduce contention during heavy matching.
 thread_local! {
-    static TLS_POOL: RefCell<VecDeque<Arc<dyn CompiledPattern>>> = RefCell::new(VecDeque::new());
+    static TLS_CACHE: RefCell<PatternCache> = RefCell::new(PatternCache::new(DEFAULT_CAPACITY));
 }
 
 /// Trait describing a compiled pattern and matching operations.
 pub trait CompiledPattern: Send + Sync {
     fn is_match(&self, text: &[u8]) -> bool;
     fn captures_ranges(&self, text: &[u8]) -> Option<Vec<(usize, usize)>>;
+    /// The capture group index for a named group (e.g. `(?P<year>\d{4})`
+    /// is index 1 in a pattern with no earlier groups), if `name` is
+    /// registered. Used to resolve `${name}` template references in
+    /// `QueryMatcher::replace`.
+    fn capture_name_index(&self, name: &str) -> Option<usize>;
 }
 
 impl CompiledPattern for Matcher {
     fn is_match(&self, text: &[u8]) -> bool { self.is_match(text) }
     fn captures_ranges(&self, text: &[u8]) -> Option<Vec<(usize, usize)>> { self.captures_ranges(text) }
+    fn capture_name_index(&self, name: &str) -> Option<usize> { self.capture_name_index(name) }
 }
 
 impl CompiledPattern for Pcre2Compiled {
     fn is_match(&self, text: &[u8]) -> bool { self.is_match(text) }
     fn captures_ranges(&self, text: &[u8]) -> Option<Vec<(usize, usize)>> { self.captures_ranges(text) }
+    fn capture_name_index(&self, name: &str) -> Option<usize> { self.capture_name_index(name) }
+}
+
+/// A small, dense id for the current thread, for storage in an
+/// [`AtomicU64`] (`std::thread::ThreadId` has no stable integer
+/// conversion). Assigned once per thread, starting at `1`, so
+/// [`ValuePool`] can use `0` as its "unclaimed" sentinel.
+fn current_thread_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static THIS_THREAD_ID: u64 = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    }
+    THIS_THREAD_ID.with(|id| *id)
 }
 
-/// A per-thread pool of compiled patterns. In the final implementation,
-/// this will manage PCRE2 compiled regexes and per-thread match_data.
-#[derive(Clone, Copy, Default)]
-pub struct PatternPool;
+/// Which slot a [`Checkout`] came from, and so where `Drop` returns it.
+enum Slot {
+    /// The single lock-free slot, owned for the pool's lifetime by
+    /// whichever thread first claimed it.
+    Fast,
+    /// The `Mutex`-guarded overflow stack, used by every other thread.
+    Spare,
+}
+
+/// A pool of interchangeable compiled instances of *one* pattern, handed
+/// out by [`ValuePool::checkout`] and returned by [`Checkout`]'s `Drop`.
+///
+/// A single `pcre2::bytes::Regex` synchronizes internally across
+/// concurrent callers (it hands out scratch match-data from its own
+/// pool), so once a compiled pattern is cached and shared -- as
+/// `CompiledNode::Leaf::pat` is, across every `par_iter` worker matching
+/// against it -- calling through one shared instance from many threads
+/// serializes on that internal lock. `ValuePool` sidesteps this the same
+/// way a real object pool would: the first thread to touch a given
+/// pool claims `fast` for itself and reacquires it on every later call
+/// with no locking at all (the common case: one pool, one steady
+/// caller), while any other thread instead pulls a spare from (or -- on
+/// contention -- compiles and pushes onto) `spares`, a small stack
+/// behind a `parking_lot::Mutex` that only ever grows as far as peak
+/// concurrent callers require.
+struct ValuePool {
+    fast_owner: AtomicU64,
+    fast: UnsafeCell<Option<Pcre2Compiled>>,
+    spares: Mutex<Vec<Pcre2Compiled>>,
+}
+
+// SAFETY: `fast` is only ever read or written by the thread recorded in
+// `fast_owner` (see `checkout`), so concurrent access from other threads
+// never touches it -- they go through the mutex-guarded `spares` instead.
+unsafe impl Sync for ValuePool {}
+
+impl ValuePool {
+    /// A pool with `seed` already sitting in the fast slot, so the first
+    /// `checkout` (by whichever thread gets there first) doesn't need to
+    /// compile a fresh instance.
+    fn new(seed: Pcre2Compiled) -> Self {
+        ValuePool { fast_owner: AtomicU64::new(0), fast: UnsafeCell::new(Some(seed)), spares: Mutex::new(Vec::new()) }
+    }
+
+    /// Check out a compiled instance of `pattern` for the current
+    /// thread's exclusive use until the returned `Checkout` drops.
+    fn checkout(&self, pattern: &str) -> Result<Checkout<'_>, Pcre2Error> {
+        let tid = current_thread_id();
+        if self.fast_owner.load(Ordering::Acquire) == tid {
+            // SAFETY: we're the thread `fast_owner` names, and only that
+            // thread ever touches `fast`.
+            if let Some(inst) = unsafe { (*self.fast.get()).take() } {
+                return Ok(Checkout { pool: self, slot: Slot::Fast, inst: Some(inst) });
+            }
+            // Already checked out by a reentrant call on this same
+            // thread -- fall through and take a spare instead.
+        } else if self.fast_owner.compare_exchange(0, tid, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            // SAFETY: we just won the race to become `fast_owner`, and
+            // no other thread touches `fast` once it's claimed.
+            let seeded = unsafe { (*self.fast.get()).take() };
+            let inst = match seeded {
+                Some(inst) => inst,
+                None => Pcre2Compiled::new(pattern)?,
+            };
+            return Ok(Checkout { pool: self, slot: Slot::Fast, inst: Some(inst) });
+        }
+        if let Some(inst) = self.spares.lock().pop() {
+            return Ok(Checkout { pool: self, slot: Slot::Spare, inst: Some(inst) });
+        }
+        Ok(Checkout { pool: self, slot: Slot::Spare, inst: Some(Pcre2Compiled::new(pattern)?) })
+    }
+}
+
+/// A single checked-out `Pcre2Compiled`, returned to the [`ValuePool`] it
+/// came from when dropped.
+struct Checkout<'a> {
+    pool: &'a ValuePool,
+    slot: Slot,
+    inst: Option<Pcre2Compiled>,
+}
+
+impl<'a> std::ops::Deref for Checkout<'a> {
+    type Target = Pcre2Compiled;
+    fn deref(&self) -> &Pcre2Compiled {
+        self.inst.as_ref().expect("Checkout always holds an instance until Drop")
+    }
+}
+
+impl<'a> Drop for Checkout<'a> {
+    fn drop(&mut self) {
+        let inst = self.inst.take().expect("Checkout always holds an instance until Drop");
+        match self.slot {
+            // SAFETY: see the comment in `checkout` -- still our slot.
+            Slot::Fast => unsafe { *self.pool.fast.get() = Some(inst) },
+            Slot::Spare => self.pool.spares.lock().push(inst),
+        }
+    }
+}
+
+/// A [`CompiledPattern`] backed by a [`ValuePool`] of the same pattern
+/// compiled multiple times over, so the single `Arc<dyn CompiledPattern>`
+/// `PatternPool::acquire_pcre2` hands back for a given pattern -- shared,
+/// once compiled, across however many threads end up matching against it
+/// -- doesn't force those threads to serialize on one PCRE2 regex's
+/// internal locking. Capture-group names are snapshotted once at
+/// construction (see `Pcre2Compiled::capture_names_owned`) since they're
+/// the same for every instance in the pool and `capture_name_index` is
+/// called far more often than a pool instance is checked out.
+pub(crate) struct PooledPcre2Pattern {
+    pattern: String,
+    pool: ValuePool,
+    capture_names: Vec<Option<String>>,
+}
+
+impl PooledPcre2Pattern {
+    pub(crate) fn new(pattern: &str) -> Result<Self, Pcre2Error> {
+        let seed = Pcre2Compiled::new(pattern)?;
+        let capture_names = seed.capture_names_owned();
+        Ok(PooledPcre2Pattern { pattern: pattern.to_string(), pool: ValuePool::new(seed), capture_names })
+    }
+}
+
+impl CompiledPattern for PooledPcre2Pattern {
+    fn is_match(&self, text: &[u8]) -> bool {
+        match self.pool.checkout(&self.pattern) {
+            Ok(inst) => inst.is_match(text),
+            Err(_) => false,
+        }
+    }
+
+    fn captures_ranges(&self, text: &[u8]) -> Option<Vec<(usize, usize)>> {
+        self.pool.checkout(&self.pattern).ok()?.captures_ranges(text)
+    }
+
+    fn capture_name_index(&self, name: &str) -> Option<usize> {
+        self.capture_names.iter().position(|n| n.as_deref() == Some(name))
+    }
+}
+
+/// A per-thread, content-keyed cache of compiled patterns. `acquire`/
+/// `acquire_pcre2` key their lookup on the exact pattern requested, so
+/// (unlike the old FIFO pool) a cache hit is guaranteed to actually match
+/// the caller's pattern, not just be "some" previously compiled one. The
+/// cache itself lives in thread-local storage (see `TLS_CACHE`), so its
+/// capacity is effectively thread-wide rather than per-`PatternPool`
+/// instance.
+#[derive(Clone, Copy)]
+pub struct PatternPool {
+    capacity: usize,
+}
+
+impl Default for PatternPool {
+    fn default() -> Self {
+        PatternPool::new()
+    }
+}
 
 impl PatternPool {
-    pub fn new() -> Self { PatternPool }
+    pub fn new() -> Self {
+        PatternPool { capacity: DEFAULT_CAPACITY }
+    }
+
+    /// Build a pool backed by a cache of up to `capacity` distinct
+    /// compiled patterns per thread (the default is 256).
+    pub fn with_capacity(capacity: usize) -> Self {
+        PatternPool { capacity }
+    }
 
-    /// Acquire a compiled pattern for use from the thread-local pool.
-    /// If none is available, call the provided factory.
-    pub fn acquire<F>(&self, factory: F) -> Arc<dyn CompiledPattern>
+    /// Acquire a compiled pattern for `key`, either from the thread-local
+    /// cache (on a hit) or via `factory` (on a miss, after which it's
+    /// inserted into the cache under `key`).
+    pub fn acquire<F>(&self, key: &str, factory: F) -> Arc<dyn CompiledPattern>
     where
         F: FnOnce() -> Arc<dyn CompiledPattern>,
     {
-        TLS_POOL.with(|q| {
-            let mut q = q.borrow_mut();
-            if let Some(p) = q.pop_front() { p } else { factory() }
+        TLS_CACHE.with(|c| {
+            let mut c = c.borrow_mut();
+            c.set_capacity(self.capacity);
+            if let Some(p) = c.get(key) {
+                return p;
+            }
+            let pat = factory();
+            c.insert(key.to_string(), pat.clone());
+            pat
         })
     }
 
-    /// Convenience: acquire a PCRE2-compiled pattern for `pattern`.
-    /// Compiles a new `Pcre2Compiled` if the pool is empty.
+    /// Convenience: acquire a PCRE2-compiled pattern for `pattern`,
+    /// compiling and caching a new [`PooledPcre2Pattern`] on a cache miss.
     pub fn acquire_pcre2(&self, pattern: &str) -> Result<Arc<dyn CompiledPattern>, Pcre2Error> {
-        TLS_POOL.with(|q| {
-            let mut q = q.borrow_mut();
-            if let Some(p) = q.pop_front() {
+        TLS_CACHE.with(|c| {
+            let mut c = c.borrow_mut();
+            c.set_capacity(self.capacity);
+            if let Some(p) = c.get(pattern) {
                 return Ok(p);
             }
-            // otherwise compile a new PCRE2 pattern
-            let pc = Pcre2Compiled::new(pattern)?;
-            Ok(Arc::new(pc))
+            let compiled: Arc<dyn CompiledPattern> = Arc::new(PooledPcre2Pattern::new(pattern)?);
+            let pat = crate::byte_prefilter::wrap(pattern, compiled);
+            c.insert(pattern.to_string(), pat.clone());
+            Ok(pat)
         })
     }
 
-    /// Return a compiled pattern to the thread-local pool for reuse.
-    pub fn release(&self, pat: Arc<dyn CompiledPattern>) {
-        TLS_POOL.with(|q| {
-            q.borrow_mut().push_back(pat);
-        })
-    }
+    /// No-op. Compiled patterns are now cached by content as soon as
+    /// they're acquired, so there's nothing left to hand back; kept for API
+    /// compatibility with the old FIFO pool.
+    pub fn release(&self, _pat: Arc<dyn CompiledPattern>) {}
 }
 
 #[cfg(test)]
@@ -77,11 +326,11 @@ mod tests {
     fn pool_acquire_release() {
         let pool = PatternPool::new();
         let factory = || Arc::new(Matcher::new("foo", false).unwrap()) as Arc<dyn CompiledPattern>;
-        let p = pool.acquire(factory);
+        let p = pool.acquire("foo", factory);
         assert!(p.is_match(b"this is foo"));
         pool.release(p);
-        // acquire again
-        let p2 = pool.acquire(factory);
+        // acquire again, same key: should come back from the cache
+        let p2 = pool.acquire("foo", factory);
         assert!(p2.is_match(b"foo bar"));
     }
 
@@ -93,7 +342,7 @@ mod tests {
         for _ in 0..4 {
             let pool_c = pool.clone();
             let h = thread::spawn(move || {
-                let p = pool_c.acquire(factory);
+                let p = pool_c.acquire("ab[0-9]+", factory);
                 assert!(p.is_match(b"xxab123yy"));
                 pool_c.release(p);
             });
@@ -101,4 +350,77 @@ mod tests {
         }
         for h in handles { h.join().unwrap(); }
     }
+
+    #[test]
+    fn acquire_pcre2_never_returns_the_wrong_pattern() {
+        // Regression test for the old FIFO pool, which could hand back a
+        // pattern compiled for a completely different regex.
+        let pool = PatternPool::new();
+        let foo = pool.acquire_pcre2("foo").unwrap();
+        let bar = pool.acquire_pcre2("bar").unwrap();
+        assert!(foo.is_match(b"foo"));
+        assert!(!foo.is_match(b"bar"));
+        assert!(bar.is_match(b"bar"));
+        assert!(!bar.is_match(b"foo"));
+        // re-acquiring "foo" must hit the cache and still be the foo pattern
+        let foo_again = pool.acquire_pcre2("foo").unwrap();
+        assert!(foo_again.is_match(b"foo"));
+        assert!(!foo_again.is_match(b"bar"));
+    }
+
+    #[test]
+    fn acquire_pcre2_evicts_least_recently_used_over_capacity() {
+        let pool = PatternPool::with_capacity(2);
+        let a = pool.acquire_pcre2("a").unwrap();
+        let _b = pool.acquire_pcre2("b").unwrap();
+        // touch "a" so "b" becomes the least-recently-used entry
+        let _ = pool.acquire_pcre2("a").unwrap();
+        let c = pool.acquire_pcre2("c").unwrap();
+        // "b" should have been evicted; "a" and "c" are still cached
+        assert!(a.is_match(b"a"));
+        assert!(c.is_match(b"c"));
+        TLS_CACHE.with(|cache| {
+            let cache = cache.borrow();
+            assert!(!cache.entries.contains_key("b"));
+            assert!(cache.entries.contains_key("a"));
+            assert!(cache.entries.contains_key("c"));
+        });
+    }
+
+    #[test]
+    fn pooled_pcre2_matches_repeatedly_on_one_thread_via_the_fast_slot() {
+        let pat = PooledPcre2Pattern::new("ab[0-9]+").unwrap();
+        for _ in 0..8 {
+            assert!(pat.is_match(b"xxab123yy"));
+            assert!(!pat.is_match(b"no digits here"));
+        }
+    }
+
+    #[test]
+    fn pooled_pcre2_reports_capture_names_without_checking_out_an_instance() {
+        let pat = PooledPcre2Pattern::new(r"(?P<year>\d{4})-(?P<month>\d{2})").unwrap();
+        assert_eq!(pat.capture_name_index("year"), Some(1));
+        assert_eq!(pat.capture_name_index("month"), Some(2));
+        assert_eq!(pat.capture_name_index("day"), None);
+    }
+
+    #[test]
+    fn pooled_pcre2_scales_across_many_concurrently_matching_threads() {
+        // One shared pattern, many threads hammering it at once: the fast
+        // slot goes to whichever thread gets there first, and the rest
+        // must fall back to (and grow) the spares stack rather than
+        // deadlock or hand back a wrong match.
+        let pat: Arc<dyn CompiledPattern> = Arc::new(PooledPcre2Pattern::new("module[0-9]{3}").unwrap());
+        let mut handles = vec![];
+        for _ in 0..8 {
+            let pat = pat.clone();
+            handles.push(thread::spawn(move || {
+                for _ in 0..50 {
+                    assert!(pat.is_match(b"module007"));
+                    assert!(!pat.is_match(b"no match here"));
+                }
+            }));
+        }
+        for h in handles { h.join().unwrap(); }
+    }
 }
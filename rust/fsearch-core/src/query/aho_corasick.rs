@@ -0,0 +1,197 @@
+//! A minimal Aho-Corasick multi-pattern matcher used as a fast path for
+//! OR-chains of literal terms (see `matcher::CompiledNode::MultiLiteral`).
+//!
+//! This builds a trie of the pattern bytes, adds failure (suffix) links via
+//! a BFS over the trie (each node's failure link points to the longest
+//! proper suffix of its path that is also a trie prefix), and accumulates
+//! "output" pattern indices along failure chains so a single linear scan
+//! over the haystack reports every match.
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const ROOT: usize = 0;
+
+struct Node {
+    children: [Option<usize>; 256],
+    fail: usize,
+    /// Indices into the pattern list that end at this node, directly or
+    /// via a failure-chain suffix.
+    outputs: Vec<usize>,
+}
+
+impl Node {
+    fn new() -> Self {
+        Node { children: [None; 256], fail: ROOT, outputs: Vec::new() }
+    }
+}
+
+/// A compiled multi-pattern literal automaton. Case-insensitive matching is
+/// implemented by lowercasing both the pattern bytes at build time and the
+/// haystack bytes while scanning, mirroring the `icase` modifier handling
+/// the PCRE2-backed leaves use.
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    pattern_lens: Vec<usize>,
+    icase: bool,
+}
+
+impl AhoCorasick {
+    /// Build an automaton matching any of `patterns`. When `icase` is set,
+    /// both the patterns and the text scanned later are ASCII-lowercased
+    /// before matching.
+    pub fn build(patterns: &[String], icase: bool) -> Self {
+        let mut nodes = vec![Node::new()];
+        let mut pattern_lens = Vec::with_capacity(patterns.len());
+        for (idx, pat) in patterns.iter().enumerate() {
+            let bytes: Vec<u8> = if icase {
+                pat.as_bytes().iter().map(|b| b.to_ascii_lowercase()).collect()
+            } else {
+                pat.as_bytes().to_vec()
+            };
+            pattern_lens.push(bytes.len());
+            let mut cur = ROOT;
+            for &b in &bytes {
+                cur = *nodes[cur].children[b as usize].get_or_insert_with(|| {
+                    nodes.push(Node::new());
+                    nodes.len() - 1
+                });
+            }
+            nodes[cur].outputs.push(idx);
+        }
+
+        // BFS over the trie to build failure links and merge each node's
+        // outputs with the outputs reachable along its failure chain.
+        let mut queue = VecDeque::new();
+        for b in 0..256 {
+            if let Some(child) = nodes[ROOT].children[b] {
+                nodes[child].fail = ROOT;
+                queue.push_back(child);
+            }
+        }
+        while let Some(cur) = queue.pop_front() {
+            for b in 0..256 {
+                if let Some(child) = nodes[cur].children[b] {
+                    let mut fail = nodes[cur].fail;
+                    while fail != ROOT && nodes[fail].children[b].is_none() {
+                        fail = nodes[fail].fail;
+                    }
+                    nodes[child].fail = nodes[fail].children[b].unwrap_or(ROOT);
+                    if nodes[child].fail == child {
+                        nodes[child].fail = ROOT;
+                    }
+                    let fail_outputs = nodes[nodes[child].fail].outputs.clone();
+                    nodes[child].outputs.extend(fail_outputs);
+                    queue.push_back(child);
+                }
+            }
+        }
+
+        AhoCorasick { nodes, pattern_lens, icase }
+    }
+
+    fn goto(&self, mut state: usize, b: u8) -> usize {
+        loop {
+            if let Some(next) = self.nodes[state].children[b as usize] {
+                return next;
+            }
+            if state == ROOT {
+                return ROOT;
+            }
+            state = self.nodes[state].fail;
+        }
+    }
+
+    /// Whether any pattern occurs in `text`.
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        let mut state = ROOT;
+        for &b in text {
+            let b = if self.icase { b.to_ascii_lowercase() } else { b };
+            state = self.goto(state, b);
+            if !self.nodes[state].outputs.is_empty() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// A presence bitmap, one entry per pattern passed to `build` (in the
+    /// same order), `true` if that pattern occurs anywhere in `text`. Unlike
+    /// `find_ranges`, this tells callers *which* patterns matched rather
+    /// than just where, which `find_ranges` can't disambiguate when two
+    /// patterns share a length.
+    pub fn match_mask(&self, text: &[u8]) -> Vec<bool> {
+        let mut mask = vec![false; self.pattern_lens.len()];
+        let mut state = ROOT;
+        for &b in text {
+            let b = if self.icase { b.to_ascii_lowercase() } else { b };
+            state = self.goto(state, b);
+            for &pat_idx in &self.nodes[state].outputs {
+                mask[pat_idx] = true;
+            }
+        }
+        mask
+    }
+
+    /// All `(start, end)` byte ranges in `text` where a pattern matched.
+    pub fn find_ranges(&self, text: &[u8]) -> Vec<(usize, usize)> {
+        let mut state = ROOT;
+        let mut ranges = Vec::new();
+        for (i, &b) in text.iter().enumerate() {
+            let b = if self.icase { b.to_ascii_lowercase() } else { b };
+            state = self.goto(state, b);
+            for &pat_idx in &self.nodes[state].outputs {
+                let len = self.pattern_lens[pat_idx];
+                let end = i + 1;
+                ranges.push((end - len, end));
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_any_of_several_literals() {
+        let ac = AhoCorasick::build(&["foo".to_string(), "bar".to_string(), "baz".to_string()], false);
+        assert!(ac.is_match(b"xxbarxx"));
+        assert!(ac.is_match(b"foo"));
+        assert!(!ac.is_match(b"qux"));
+    }
+
+    #[test]
+    fn reports_match_ranges() {
+        let ac = AhoCorasick::build(&["ab".to_string(), "cd".to_string()], false);
+        let ranges = ac.find_ranges(b"xxabxxcdxx");
+        assert_eq!(ranges, vec![(2, 4), (6, 8)]);
+    }
+
+    #[test]
+    fn icase_lowercases_patterns_and_text() {
+        let ac = AhoCorasick::build(&["FoO".to_string()], true);
+        assert!(ac.is_match(b"xxFOOxx"));
+        assert!(ac.is_match(b"xxfooxx"));
+    }
+
+    #[test]
+    fn match_mask_reports_which_patterns_hit() {
+        let ac = AhoCorasick::build(&["foo".to_string(), "bar".to_string(), "qux".to_string()], false);
+        let mask = ac.match_mask(b"xxbarxxfooxx");
+        assert_eq!(mask, vec![true, true, false]);
+    }
+
+    #[test]
+    fn overlapping_suffix_patterns_via_failure_links() {
+        // "he", "she", "hers" share suffixes; failure links must still
+        // surface every one of them.
+        let ac = AhoCorasick::build(&["he".to_string(), "she".to_string(), "hers".to_string()], false);
+        let ranges = ac.find_ranges(b"ushers");
+        assert!(ranges.contains(&(1, 4))); // "she"
+        assert!(ranges.contains(&(2, 4))); // "he"
+        assert!(ranges.contains(&(2, 6))); // "hers"
+    }
+}
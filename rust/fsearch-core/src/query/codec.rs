@@ -0,0 +1,263 @@
+//! Binary (CBOR) encoding for compiled query trees.
+//!
+//! `CompiledNode` holds `Arc<dyn CompiledPattern>`, which can't be
+//! serialized directly. `WireNode` mirrors its shape but stores the PCRE2
+//! pattern source for each leaf instead; decoding re-acquires a compiled
+//! pattern from a `PatternPool` rather than deserializing one. This lets a
+//! long-running search daemon cache compiled queries on disk (or send them
+//! to another process) keyed by their source string, and skip recompiling
+//! PCRE2 patterns on a cache hit.
+
+use crate::pcre2_pool::PatternPool;
+use crate::query::aho_corasick::AhoCorasick;
+use crate::query::matcher::{compile_structural, intern, CompiledNode};
+use crate::query::parser_rs::{Bound, CompareOp};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+enum WireNode {
+    Leaf {
+        pattern: String,
+        negated: bool,
+        field: Option<String>,
+        mods: Vec<String>,
+    },
+    Compare {
+        field: Option<String>,
+        op: CompareOp,
+        value: String,
+    },
+    Range {
+        field: Option<String>,
+        low: Bound,
+        high: Bound,
+    },
+    Function {
+        name: String,
+        args: Vec<String>,
+    },
+    MultiLiteral {
+        literals: Vec<String>,
+        icase: bool,
+        field: Option<String>,
+    },
+    Structural {
+        template: String,
+        icase: bool,
+        negated: bool,
+        field: Option<String>,
+    },
+    And(Box<WireNode>, Box<WireNode>),
+    Or(Box<WireNode>, Box<WireNode>),
+    Not(Box<WireNode>),
+}
+
+/// Error rebuilding or serializing a `CompiledNode`.
+#[derive(Debug)]
+pub enum CodecError {
+    Cbor(serde_cbor::Error),
+    Pattern(pcre2::Error),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Cbor(e) => write!(f, "cbor (de)serialization error: {}", e),
+            CodecError::Pattern(e) => write!(f, "failed to recompile pattern: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<serde_cbor::Error> for CodecError {
+    fn from(e: serde_cbor::Error) -> Self {
+        CodecError::Cbor(e)
+    }
+}
+
+impl From<pcre2::Error> for CodecError {
+    fn from(e: pcre2::Error) -> Self {
+        CodecError::Pattern(e)
+    }
+}
+
+fn to_wire(node: &CompiledNode) -> WireNode {
+    match node {
+        CompiledNode::Leaf { pattern, negated, field, mods, .. } => WireNode::Leaf {
+            pattern: pattern.to_string(),
+            negated: *negated,
+            field: field.as_deref().map(str::to_string),
+            mods: mods.iter().map(|m| m.to_string()).collect(),
+        },
+        CompiledNode::Compare { field, op, value } => WireNode::Compare {
+            field: field.as_deref().map(str::to_string),
+            op: op.clone(),
+            value: value.clone(),
+        },
+        CompiledNode::Range { field, low, high } => WireNode::Range {
+            field: field.as_deref().map(str::to_string),
+            low: low.clone(),
+            high: high.clone(),
+        },
+        CompiledNode::Function { name, args } => WireNode::Function {
+            name: name.clone(),
+            args: args.clone(),
+        },
+        CompiledNode::MultiLiteral { literals, icase, field, .. } => WireNode::MultiLiteral {
+            literals: literals.iter().map(|s| s.to_string()).collect(),
+            icase: *icase,
+            field: field.as_deref().map(str::to_string),
+        },
+        CompiledNode::Structural { template, icase, negated, field, .. } => WireNode::Structural {
+            template: template.to_string(),
+            icase: *icase,
+            negated: *negated,
+            field: field.as_deref().map(str::to_string),
+        },
+        CompiledNode::And(a, b) => WireNode::And(Box::new(to_wire(a)), Box::new(to_wire(b))),
+        CompiledNode::Or(a, b) => WireNode::Or(Box::new(to_wire(a)), Box::new(to_wire(b))),
+        CompiledNode::Not(inner) => WireNode::Not(Box::new(to_wire(inner))),
+    }
+}
+
+fn from_wire(pool: &PatternPool, wire: WireNode) -> Result<CompiledNode, CodecError> {
+    Ok(match wire {
+        WireNode::Leaf { pattern, negated, field, mods } => {
+            let pat = pool.acquire_pcre2(&pattern)?;
+            CompiledNode::Leaf {
+                pat,
+                pattern: intern(&pattern),
+                negated,
+                field: field.as_deref().map(intern),
+                mods: mods.iter().map(|m| intern(m)).collect(),
+            }
+        }
+        WireNode::Compare { field, op, value } => CompiledNode::Compare {
+            field: field.as_deref().map(intern),
+            op,
+            value,
+        },
+        WireNode::Range { field, low, high } => CompiledNode::Range {
+            field: field.as_deref().map(intern),
+            low,
+            high,
+        },
+        WireNode::Function { name, args } => CompiledNode::Function { name, args },
+        WireNode::MultiLiteral { literals, icase, field } => CompiledNode::MultiLiteral {
+            ac: Arc::new(AhoCorasick::build(&literals, icase)),
+            literals: literals.iter().map(|s| intern(s)).collect(),
+            icase,
+            field: field.as_deref().map(intern),
+        },
+        WireNode::Structural { template, icase, negated, field } => {
+            let (body, group_vars) = compile_structural(&template);
+            let pattern = if icase { format!("(?i){}", body) } else { body };
+            let pat = pool.acquire_pcre2(&pattern)?;
+            CompiledNode::Structural {
+                pat,
+                template: intern(&template),
+                group_vars: group_vars.iter().map(|s| intern(s)).collect(),
+                icase,
+                negated,
+                field: field.as_deref().map(intern),
+            }
+        }
+        WireNode::And(a, b) => CompiledNode::And(Box::new(from_wire(pool, *a)?), Box::new(from_wire(pool, *b)?)),
+        WireNode::Or(a, b) => CompiledNode::Or(Box::new(from_wire(pool, *a)?), Box::new(from_wire(pool, *b)?)),
+        WireNode::Not(inner) => CompiledNode::Not(Box::new(from_wire(pool, *inner)?)),
+    })
+}
+
+/// Serialize a compiled query tree to CBOR bytes.
+pub fn encode(node: &CompiledNode) -> Result<Vec<u8>, CodecError> {
+    Ok(serde_cbor::to_vec(&to_wire(node))?)
+}
+
+/// Deserialize a compiled query tree previously produced by `encode`,
+/// re-acquiring each leaf's PCRE2 pattern from `pool`.
+pub fn decode(pool: &PatternPool, bytes: &[u8]) -> Result<CompiledNode, CodecError> {
+    let wire: WireNode = serde_cbor::from_slice(bytes)?;
+    from_wire(pool, wire)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::matcher::QueryMatcher;
+    use crate::query::parser_rs::Parser;
+
+    #[test]
+    fn roundtrip_simple_leaf() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool.clone());
+        let node = Parser::new("name:foo").parse().unwrap();
+        let compiled = qm.compile(&node).unwrap();
+
+        let bytes = encode(&compiled).unwrap();
+        let decoded = decode(&pool, &bytes).unwrap();
+
+        assert!(qm.is_match(&decoded, b"this is foo\n/some/foo"));
+        assert!(!qm.is_match(&decoded, b"nope\n/some/nope"));
+    }
+
+    #[test]
+    fn roundtrip_and_or_not() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool.clone());
+        let node = Parser::new("foo AND (bar OR NOT baz)").parse().unwrap();
+        let compiled = qm.compile(&node).unwrap();
+
+        let bytes = encode(&compiled).unwrap();
+        let decoded = decode(&pool, &bytes).unwrap();
+
+        assert!(qm.is_match(&decoded, b"foo bar"));
+        assert!(qm.is_match(&decoded, b"foo"));
+        assert!(!qm.is_match(&decoded, b"foo baz"));
+    }
+
+    #[test]
+    fn roundtrip_compare_and_range() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool.clone());
+        let node = Parser::new("size:10..20").parse().unwrap();
+        let compiled = qm.compile(&node).unwrap();
+
+        let bytes = encode(&compiled).unwrap();
+        let decoded = decode(&pool, &bytes).unwrap();
+
+        assert!(qm.is_match(&decoded, b"15"));
+        assert!(!qm.is_match(&decoded, b"25"));
+    }
+
+    #[test]
+    fn roundtrip_structural() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool.clone());
+        let node = Parser::new("foo_$x_bar").parse().unwrap();
+        let compiled = qm.compile(&node).unwrap();
+
+        let bytes = encode(&compiled).unwrap();
+        let decoded = decode(&pool, &bytes).unwrap();
+
+        let combined = b"foo_hello_bar\n/some/foo_hello_bar";
+        assert!(qm.is_match(&decoded, combined));
+        assert!(!qm.is_match(&decoded, b"foo_bar\n/some/foo_bar"));
+    }
+
+    #[test]
+    fn roundtrip_multi_literal() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool.clone());
+        let node = Parser::new("foo | bar | baz").parse().unwrap();
+        let compiled = qm.compile(&node).unwrap();
+
+        let bytes = encode(&compiled).unwrap();
+        let decoded = decode(&pool, &bytes).unwrap();
+
+        assert!(qm.is_match(&decoded, b"xxbarxx"));
+        assert!(!qm.is_match(&decoded, b"qux"));
+    }
+}
@@ -0,0 +1,184 @@
+//! Pluggable field extraction over the combined `name + "\n" + path` text
+//! the search pipeline matches against.
+//!
+//! Each registered field maps to an extractor that locates the byte range
+//! of that field's value within the combined text. `QueryMatcher` uses
+//! this to scope a field-qualified leaf's matching/capture ranges without
+//! hardcoding each field name into its match arms.
+
+use crate::query::value_parse::{self, ValueParser};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+
+/// Locates the byte range `[start, end)` of a field's value within the
+/// combined text, or `None` if the field doesn't apply (e.g. `extension`
+/// on a name with no dot).
+pub type FieldExtractor = Arc<dyn Fn(&[u8]) -> Option<(usize, usize)> + Send + Sync>;
+
+/// Registry of field name -> extractor/value-parser. Built-in fields
+/// (`name`, `path`, `dir`, `stem`, `extension`) are registered by
+/// `FieldSchema::new`; callers can add their own via `register` (and
+/// `register_value_parser`) to scope additional metadata columns without
+/// touching `QueryMatcher`.
+#[derive(Clone)]
+pub struct FieldSchema {
+    extractors: BTreeMap<String, FieldExtractor>,
+    value_parsers: BTreeMap<String, ValueParser>,
+}
+
+fn find_newline(text: &[u8]) -> Option<usize> {
+    text.iter().position(|&b| b == b'\n')
+}
+
+/// Field names backed by `value_parse::parse_date_seconds`. `QueryMatcher`
+/// checks against this list before trying its relative-duration fallback
+/// (`mtime:<7d` meaning "modified within the last 7 days") on a `Compare`,
+/// so that a non-time field whose operand merely happens to also parse as
+/// a duration (e.g. `size<7d`) doesn't get misread as a timestamp age.
+pub const TIME_FIELDS: &[&str] = &["modified", "mtime", "ctime"];
+
+impl FieldSchema {
+    /// A schema with the built-in fields the search pipeline's combined
+    /// `name + "\n" + path` layout supports.
+    pub fn new() -> Self {
+        let mut schema = FieldSchema { extractors: BTreeMap::new(), value_parsers: BTreeMap::new() };
+        schema.register_value_parser("size", value_parse::parse_size_bytes);
+        for field in TIME_FIELDS {
+            schema.register_value_parser(field, value_parse::parse_date_seconds);
+        }
+        schema.register("name", |text| {
+            let nl = find_newline(text)?;
+            Some((0, nl))
+        });
+        schema.register("path", |text| {
+            let nl = find_newline(text)?;
+            Some((nl + 1, text.len()))
+        });
+        schema.register("dir", |text| {
+            let nl = find_newline(text)?;
+            let path = &text[nl + 1..];
+            let slash = path.iter().rposition(|&b| b == b'/')?;
+            Some((nl + 1, nl + 1 + slash))
+        });
+        schema.register("stem", |text| {
+            let nl = find_newline(text)?;
+            let name = &text[..nl];
+            let dot = name.iter().rposition(|&b| b == b'.')?;
+            Some((0, dot))
+        });
+        schema.register("extension", |text| {
+            let nl = find_newline(text)?;
+            let name = &text[..nl];
+            let dot = name.iter().rposition(|&b| b == b'.')?;
+            Some((dot + 1, nl))
+        });
+        schema
+    }
+
+    /// Register (or replace) the extractor for `field`.
+    pub fn register<F>(&mut self, field: &str, f: F)
+    where
+        F: Fn(&[u8]) -> Option<(usize, usize)> + Send + Sync + 'static,
+    {
+        self.extractors.insert(field.to_string(), Arc::new(f));
+    }
+
+    /// Whether `field` has a registered extractor.
+    pub fn contains(&self, field: &str) -> bool {
+        self.extractors.contains_key(field)
+    }
+
+    /// Run `field`'s extractor over `text`, if one is registered.
+    pub fn extract(&self, field: &str, text: &[u8]) -> Option<(usize, usize)> {
+        self.extractors.get(field)?(text)
+    }
+
+    /// Register (or replace) the numeric value parser for `field`, used by
+    /// `Compare`/`Range` to interpret both the stored operand and the
+    /// candidate text as a typed number instead of a plain decimal.
+    pub fn register_value_parser<F>(&mut self, field: &str, f: F)
+    where
+        F: Fn(&[u8]) -> Option<f64> + Send + Sync + 'static,
+    {
+        self.value_parsers.insert(field.to_string(), Arc::new(f));
+    }
+
+    /// The value parser registered for `field`, if any.
+    pub fn value_parser(&self, field: &str) -> Option<&ValueParser> {
+        self.value_parsers.get(field)
+    }
+}
+
+impl Default for FieldSchema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMBINED: &[u8] = b"file.txt\n/some/path/file.txt";
+
+    #[test]
+    fn extracts_name_and_path() {
+        let schema = FieldSchema::new();
+        assert_eq!(schema.extract("name", COMBINED), Some((0, 8)));
+        assert_eq!(&COMBINED[0..8], b"file.txt");
+        assert_eq!(schema.extract("path", COMBINED), Some((9, COMBINED.len())));
+        assert_eq!(&COMBINED[9..COMBINED.len()], b"/some/path/file.txt");
+    }
+
+    #[test]
+    fn extracts_dir_stem_extension() {
+        let schema = FieldSchema::new();
+        let (s, e) = schema.extract("dir", COMBINED).unwrap();
+        assert_eq!(&COMBINED[s..e], b"/some/path");
+        let (s, e) = schema.extract("stem", COMBINED).unwrap();
+        assert_eq!(&COMBINED[s..e], b"file");
+        let (s, e) = schema.extract("extension", COMBINED).unwrap();
+        assert_eq!(&COMBINED[s..e], b"txt");
+    }
+
+    #[test]
+    fn extension_absent_without_dot() {
+        let schema = FieldSchema::new();
+        let combined = b"README\n/some/path/README";
+        assert_eq!(schema.extract("extension", combined), None);
+    }
+
+    #[test]
+    fn unknown_field_not_registered() {
+        let schema = FieldSchema::new();
+        assert!(!schema.contains("size"));
+        assert_eq!(schema.extract("size", COMBINED), None);
+    }
+
+    #[test]
+    fn custom_field_can_be_registered() {
+        let mut schema = FieldSchema::new();
+        schema.register("upper_name", |text| {
+            let nl = text.iter().position(|&b| b == b'\n')?;
+            Some((0, nl))
+        });
+        assert!(schema.contains("upper_name"));
+        assert_eq!(schema.extract("upper_name", COMBINED), Some((0, 8)));
+    }
+
+    #[test]
+    fn size_and_modified_have_builtin_value_parsers() {
+        let schema = FieldSchema::new();
+        assert_eq!(schema.value_parser("size").unwrap()(b"1k"), Some(1024.0));
+        assert_eq!(schema.value_parser("modified").unwrap()(b"1970-01-02"), Some(86400.0));
+        assert!(schema.value_parser("name").is_none());
+    }
+
+    #[test]
+    fn custom_value_parser_can_be_registered() {
+        let mut schema = FieldSchema::new();
+        schema.register_value_parser("count", |b| core::str::from_utf8(b).ok()?.parse::<f64>().ok());
+        assert_eq!(schema.value_parser("count").unwrap()(b"42"), Some(42.0));
+    }
+}
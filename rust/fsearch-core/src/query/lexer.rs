@@ -1,4 +1,24 @@
-use std::collections::VecDeque;
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use unicode_normalization::UnicodeNormalization;
+
+/// Whether `c` can start (and be swept into) a bare word/field/glob/regex
+/// token. Beyond `unicode_ident`'s definition of a valid identifier start
+/// (which covers non-ASCII field names like `αβ:value`), this also
+/// accepts the ASCII digits and punctuation the query grammar already
+/// gives meaning to elsewhere (globs, structural `$` metavariables,
+/// ranges, regex literals), so none of that existing syntax regresses.
+/// Only a genuinely unexpected non-ASCII symbol (e.g. an emoji) falls
+/// through to being lexed as a lone single-char word.
+fn is_word_start(c: char) -> bool {
+    unicode_ident::is_xid_start(c) || c.is_ascii_digit() || c.is_ascii_punctuation()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
@@ -19,151 +39,278 @@ pub enum Token {
     Or,
 }
 
-pub struct Lexer {
-    input: String,
+/// A problem encountered while lexing, reported instead of silently
+/// shortening the token stream (an unterminated quote or a trailing
+/// backslash used to just look like an early `Eos`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexError {
+    /// A `"..."` quoted string ran off the end of the input before its
+    /// closing quote. `start` is the byte offset of the opening quote.
+    UnterminatedString { start: usize },
+    /// A bare `\` at the very end of the input, with nothing left to
+    /// escape. `pos` is its byte offset.
+    TrailingBackslash { pos: usize },
+    /// A character the lexer doesn't know how to start a token from.
+    /// `pos` is its byte offset.
+    UnexpectedChar { ch: char, pos: usize },
+}
+
+/// A forward-only walk over the input, one `char` at a time. `rest` is the
+/// not-yet-consumed tail of the input; `bump` advances it via `Chars`
+/// rather than re-slicing `input[pos..]` and re-validating the UTF-8
+/// boundary on every call. `pushback` holds characters that were read via
+/// `bump` and then handed back via `give_back` -- they're replayed before
+/// any fresh character is pulled from `rest`, and `peek`/`peek2` look
+/// through them the same way.
+struct Cursor<'a> {
+    rest: &'a str,
     pos: usize,
     pushback: VecDeque<char>,
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Self {
-        Lexer {
-            input: input.to_string(),
-            pos: 0,
-            pushback: VecDeque::new(),
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Cursor { rest: input, pos: 0, pushback: VecDeque::new() }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        if let Some(c) = self.pushback.pop_front() {
+            return Some(c);
         }
+        let mut chars = self.rest.chars();
+        let c = chars.next()?;
+        self.rest = chars.as_str();
+        self.pos += c.len_utf8();
+        Some(c)
     }
 
-    fn get_next_input_char(&mut self) -> Option<char> {
-        if self.pos < self.input.len() {
-            let ch = self.input[self.pos..].chars().next().unwrap();
-            self.pos += ch.len_utf8();
-            Some(ch)
-        } else {
-            None
+    fn give_back(&mut self, c: char) {
+        self.pushback.push_front(c);
+    }
+
+    /// The next character, without consuming it.
+    fn peek(&self) -> Option<char> {
+        if let Some(&c) = self.pushback.front() {
+            return Some(c);
         }
+        self.rest.chars().next()
     }
 
-    fn get_next_char(&mut self) -> Option<char> {
-        if let Some(c) = self.pushback.pop_front() {
-            Some(c)
+    /// The character after the next one, without consuming either.
+    fn peek2(&self) -> Option<char> {
+        if self.pushback.len() >= 2 {
+            return self.pushback.get(1).copied();
+        }
+        let mut chars = self.rest.chars();
+        if self.pushback.len() == 1 {
+            chars.next()
         } else {
-            self.get_next_input_char()
+            chars.next();
+            chars.next()
         }
     }
 
-    fn give_back_char(&mut self, c: char) {
-        self.pushback.push_front(c);
+    /// Bytes currently sitting in `pushback`, not yet consumed as part of
+    /// whatever token is being lexed -- `self.pos` has already moved past
+    /// them (they were read once, then given back), so this is subtracted
+    /// from `self.pos` to get the byte offset the input was *actually*
+    /// consumed up to.
+    fn pushback_len(&self) -> usize {
+        self.pushback.iter().map(|c| c.len_utf8()).sum()
+    }
+}
+
+pub struct Lexer<'a> {
+    cursor: Cursor<'a>,
+    /// One-token lookahead buffered by `peek_token`, drained by the next
+    /// call to `next_token` instead of re-lexing from a saved/restored
+    /// `pos`/`pushback` snapshot.
+    peeked: Option<Result<(Token, Span, Option<String>), LexError>>,
+    /// Set once the token stream has yielded `Eos` or a `LexError` through
+    /// the `Iterator` impl, so further calls to `next` stop cleanly
+    /// instead of re-lexing past the end of the input.
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer {
+            cursor: Cursor::new(input),
+            peeked: None,
+            done: false,
+        }
     }
 
-    fn parse_quoted_string(&mut self) -> String {
+    /// Parse the body of a `"..."` quoted string, starting just after the
+    /// opening quote at byte offset `start`. `\"` and `\\` decode to a
+    /// literal quote/backslash so a phrase can contain one without ending
+    /// the string early, `\n`/`\t`/`\r` decode to the corresponding
+    /// control character, and any other escaped character (`\x`) is taken
+    /// literally, mirroring the bare `'\\'` escape handling in
+    /// `next_token_inner`. Returns `LexError::UnterminatedString` if the
+    /// input ends before the closing quote.
+    fn parse_quoted_string(&mut self, start: usize) -> Result<String, LexError> {
         let mut out = String::new();
-        while let Some(c) = self.get_next_char() {
-            if c == '"' {
-                break;
+        loop {
+            match self.cursor.bump() {
+                Some('"') => return Ok(out),
+                Some('\\') => match self.cursor.bump() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some(other) => out.push(other),
+                    None => return Err(LexError::UnterminatedString { start }),
+                },
+                Some(c) => out.push(c),
+                None => return Err(LexError::UnterminatedString { start }),
+            }
+        }
+    }
+
+    /// Lex the next token, alongside its `Span` -- the byte-offset range
+    /// `[start, end)` into the original input it was read from. `start`
+    /// is recorded after skipping leading whitespace, so the span covers
+    /// only the token itself; `end` accounts for any lookahead character
+    /// lexing gave back via `Cursor::give_back`, so it isn't counted as
+    /// part of this token's span even though `self.cursor.pos` has
+    /// already moved past it.
+    pub fn next_token(&mut self) -> Result<(Token, Span, Option<String>), LexError> {
+        if let Some(peeked) = self.peeked.take() {
+            return peeked;
+        }
+        while let Some(c) = self.cursor.peek() {
+            if c.is_whitespace() {
+                self.cursor.bump();
             } else {
-                out.push(c);
+                break;
             }
         }
-        out
+        let start = self.cursor.pos - self.cursor.pushback_len();
+        let (tok, text) = self.next_token_inner()?;
+        let end = self.cursor.pos - self.cursor.pushback_len();
+        Ok((tok, Span { start, end }, text))
     }
 
-    pub fn next_token(&mut self) -> (Token, Option<String>) {
+    fn next_token_inner(&mut self) -> Result<(Token, Option<String>), LexError> {
         // skip whitespace
         loop {
-            match self.get_next_char() {
+            match self.cursor.bump() {
                 Some(c) if c.is_whitespace() => continue,
                 Some(c) => {
                     // process
                     match c {
-                        '\0' => return (Token::Eos, None),
-                        '=' => return (Token::Equal, None),
-                        ':' => return (Token::Contains, None),
-                        '<' => {
-                            if let Some('=') = self.get_next_char() {
-                                return (Token::SmallerEq, None);
-                            } else {
-                                return (Token::Smaller, None);
+                        '\0' => return Ok((Token::Eos, None)),
+                        '=' => return Ok((Token::Equal, None)),
+                        ':' => return Ok((Token::Contains, None)),
+                        '<' => match self.cursor.bump() {
+                            Some('=') => return Ok((Token::SmallerEq, None)),
+                            Some(nc) => {
+                                self.cursor.give_back(nc);
+                                return Ok((Token::Smaller, None));
                             }
-                        }
-                        '>' => {
-                            if let Some('=') = self.get_next_char() {
-                                return (Token::GreaterEq, None);
-                            } else {
-                                return (Token::Greater, None);
+                            None => return Ok((Token::Smaller, None)),
+                        },
+                        '>' => match self.cursor.bump() {
+                            Some('=') => return Ok((Token::GreaterEq, None)),
+                            Some(nc) => {
+                                self.cursor.give_back(nc);
+                                return Ok((Token::Greater, None));
                             }
-                        }
-                        '!' => return (Token::Not, None),
-                        '(' => return (Token::BracketOpen, None),
-                        ')' => return (Token::BracketClose, None),
+                            None => return Ok((Token::Greater, None)),
+                        },
+                        '!' => return Ok((Token::Not, None)),
+                        '(' => return Ok((Token::BracketOpen, None)),
+                        ')' => return Ok((Token::BracketClose, None)),
                         '"' => {
-                            let s = self.parse_quoted_string();
-                            return (Token::Word(s.clone()), Some(s));
+                            let start = self.cursor.pos - 1;
+                            let raw = self.parse_quoted_string(start)?;
+                            let s = raw.nfc().collect::<String>();
+                            return Ok((Token::Word(s.clone()), Some(s)));
                         }
                         '\\' => {
-                            if let Some(next) = self.get_next_char() {
-                                let mut s = String::new();
-                                s.push(next);
-                                return (Token::Word(s.clone()), Some(s));
+                            if let Some(next) = self.cursor.bump() {
+                                let mut raw = String::new();
+                                raw.push(next);
+                                let s = raw.nfc().collect::<String>();
+                                return Ok((Token::Word(s.clone()), Some(s)));
                             }
+                            return Err(LexError::TrailingBackslash { pos: self.cursor.pos - 1 });
                         }
                         other => {
                             // start reading token until whitespace or reserved char
                             let mut s = String::new();
                             s.push(other);
-                            while let Some(nc) = self.get_next_char() {
-                                if nc.is_whitespace() || ":=<>()\"\\".contains(nc) {
-                                    self.give_back_char(nc);
-                                    break;
+                            if is_word_start(other) {
+                                while let Some(nc) = self.cursor.bump() {
+                                    if nc.is_whitespace() || ":=<>()\"\\".contains(nc) {
+                                        self.cursor.give_back(nc);
+                                        break;
+                                    }
+                                    s.push(nc);
                                 }
-                                s.push(nc);
                             }
+                            // normalize to NFC so visually identical but differently
+                            // composed Unicode (e.g. precomposed e-acute vs e + combining
+                            // acute) produce the same Word/Field/FieldEmpty payload
+                            let s = s.nfc().collect::<String>();
                             // reserved words
                             if s == "NOT" {
-                                return (Token::Not, None);
+                                return Ok((Token::Not, None));
                             }
                             if s == "AND" || s == "&&" {
-                                return (Token::And, None);
+                                return Ok((Token::And, None));
                             }
                             if s == "OR" || s == "||" {
-                                return (Token::Or, None);
+                                return Ok((Token::Or, None));
                             }
                             // if next is ':' then it's a field
-                            if let Some(next_c) = self.get_next_char() {
-                                if next_c == ':' {
-                                    // check if next is whitespace or eos
-                                    if let Some(peek) = self.get_next_char() {
-                                        if peek.is_whitespace() {
-                                            return (Token::FieldEmpty(s.clone()), Some(s));
-                                        } else {
-                                            self.give_back_char(peek);
-                                            return (Token::Field(s.clone()), Some(s));
-                                        }
-                                    } else {
-                                        return (Token::FieldEmpty(s.clone()), Some(s));
+                            if self.cursor.peek() == Some(':') {
+                                match self.cursor.peek2() {
+                                    Some(next_c) if !next_c.is_whitespace() => {
+                                        self.cursor.bump();
+                                        return Ok((Token::Field(s.clone()), Some(s)));
+                                    }
+                                    _ => {
+                                        self.cursor.bump();
+                                        return Ok((Token::FieldEmpty(s.clone()), Some(s)));
                                     }
-                                } else {
-                                    self.give_back_char(next_c);
                                 }
                             }
-                            return (Token::Word(s.clone()), Some(s));
+                            return Ok((Token::Word(s.clone()), Some(s)));
                         }
                     }
                 }
-                None => return (Token::Eos, None),
+                None => return Ok((Token::Eos, None)),
             }
         }
     }
 
-    pub fn peek_token(&mut self) -> (Token, Option<String>) {
-        // save state
-        let old_pos = self.pos;
-        let old_push = self.pushback.clone();
-        let res = self.next_token();
-        // restore
-        self.pos = old_pos;
-        self.pushback = old_push;
-        res
+    pub fn peek_token(&mut self) -> Result<(Token, Span, Option<String>), LexError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next_token());
+        }
+        self.peeked.clone().expect("just populated above")
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<(Token, Span, Option<String>), LexError>;
+
+    /// Yields tokens until `Eos` (inclusive) or a `LexError` (inclusive),
+    /// then stops, so the stream can be driven with `by_ref`/`take_while`/
+    /// `collect` instead of a manual `loop { match next_token() ... }`.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.next_token();
+        match &item {
+            Ok((Token::Eos, _, _)) | Err(_) => self.done = true,
+            _ => {}
+        }
+        Some(item)
     }
 }
 
@@ -174,21 +321,179 @@ mod tests {
     #[test]
     fn lexer_basic_tokens() {
         let mut lx = Lexer::new("name:foo AND bar OR (baz)");
-        let (t1, _) = lx.next_token();
+        let (t1, _, _) = lx.next_token().unwrap();
         assert_eq!(t1, Token::Field("name".into()));
-        let (t2, _) = lx.next_token();
+        let (t2, _, _) = lx.next_token().unwrap();
         assert_eq!(t2, Token::Word("foo".into()));
-        let (t3, _) = lx.next_token();
+        let (t3, _, _) = lx.next_token().unwrap();
         assert_eq!(t3, Token::And);
-        let (t4, _) = lx.next_token();
+        let (t4, _, _) = lx.next_token().unwrap();
         assert_eq!(t4, Token::Word("bar".into()));
-        let (t5, _) = lx.next_token();
+        let (t5, _, _) = lx.next_token().unwrap();
         assert_eq!(t5, Token::Or);
-        let (t6, _) = lx.next_token();
+        let (t6, _, _) = lx.next_token().unwrap();
         assert_eq!(t6, Token::BracketOpen);
-        let (t7, _) = lx.next_token();
+        let (t7, _, _) = lx.next_token().unwrap();
         assert_eq!(t7, Token::Word("baz".into()));
-        let (t8, _) = lx.next_token();
+        let (t8, _, _) = lx.next_token().unwrap();
         assert_eq!(t8, Token::BracketClose);
     }
+
+    #[test]
+    fn spans_cover_only_the_token_excluding_leading_whitespace() {
+        let mut lx = Lexer::new("  foo bar");
+        let (tok, span, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("foo".into()));
+        assert_eq!(span, Span { start: 2, end: 5 });
+        let (tok, span, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("bar".into()));
+        assert_eq!(span, Span { start: 6, end: 9 });
+    }
+
+    #[test]
+    fn span_excludes_lookahead_characters_given_back() {
+        // the lexer peeks one char past "foo" to check for a reserved
+        // word/field colon, then gives it back; the span must stop at the
+        // end of "foo", not include the space it peeked at.
+        let mut lx = Lexer::new("foo bar");
+        let (tok, span, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("foo".into()));
+        assert_eq!(span, Span { start: 0, end: 3 });
+    }
+
+    #[test]
+    fn field_span_covers_name_and_colon() {
+        let mut lx = Lexer::new("name:foo");
+        let (tok, span, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Field("name".into()));
+        assert_eq!(span, Span { start: 0, end: 5 });
+    }
+
+    #[test]
+    fn quoted_string_decodes_escaped_quote_and_backslash() {
+        let mut lx = Lexer::new(r#""she said \"hi\"" next"#);
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word(r#"she said "hi""#.into()));
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("next".into()));
+
+        let mut lx = Lexer::new(r#""back\\slash""#);
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word(r"back\slash".into()));
+    }
+
+    #[test]
+    fn quoted_string_decodes_control_char_escapes() {
+        let mut lx = Lexer::new(r#""a\nb\tc\rd""#);
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("a\nb\tc\rd".into()));
+    }
+
+    #[test]
+    fn quoted_string_unknown_escape_keeps_following_char_literal() {
+        let mut lx = Lexer::new(r#""a\xb""#);
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("axb".into()));
+    }
+
+    #[test]
+    fn peek_token_does_not_advance_past_the_peeked_span() {
+        let mut lx = Lexer::new("foo bar");
+        let (peeked, peeked_span, _) = lx.peek_token().unwrap();
+        assert_eq!(peeked, Token::Word("foo".into()));
+        let (tok, span, _) = lx.next_token().unwrap();
+        assert_eq!(tok, peeked);
+        assert_eq!(span, peeked_span);
+    }
+
+    #[test]
+    fn unterminated_quoted_string_reports_an_error() {
+        let mut lx = Lexer::new(r#"name:"foo"#);
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Field("name".into()));
+        let err = lx.next_token().unwrap_err();
+        assert_eq!(err, LexError::UnterminatedString { start: 5 });
+    }
+
+    #[test]
+    fn trailing_backslash_reports_an_error() {
+        let mut lx = Lexer::new(r"foo \");
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("foo".into()));
+        let err = lx.next_token().unwrap_err();
+        assert_eq!(err, LexError::TrailingBackslash { pos: 4 });
+    }
+
+    #[test]
+    fn iterator_yields_tokens_up_to_and_including_eos() {
+        let lx = Lexer::new("foo bar");
+        let toks: Vec<Token> = lx.map(|r| r.unwrap().0).collect();
+        assert_eq!(toks, vec![Token::Word("foo".into()), Token::Word("bar".into()), Token::Eos]);
+    }
+
+    #[test]
+    fn iterator_stops_after_yielding_a_lex_error() {
+        let lx = Lexer::new(r"foo \");
+        let items: Vec<_> = lx.collect();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert_eq!(items[1], Err(LexError::TrailingBackslash { pos: 4 }));
+    }
+
+    #[test]
+    fn peek_token_buffers_instead_of_re_lexing() {
+        let mut lx = Lexer::new("foo bar");
+        assert_eq!(lx.peek_token().unwrap().0, Token::Word("foo".into()));
+        assert_eq!(lx.peek_token().unwrap().0, Token::Word("foo".into()));
+        assert_eq!(lx.next_token().unwrap().0, Token::Word("foo".into()));
+        assert_eq!(lx.next_token().unwrap().0, Token::Word("bar".into()));
+    }
+
+    #[test]
+    fn field_lookahead_uses_cursor_peek_without_consuming() {
+        // exercises Cursor::peek2 on the colon-lookahead path: "name:" at
+        // end of input (no char after the colon) still yields FieldEmpty.
+        let mut lx = Lexer::new("name:");
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::FieldEmpty("name".into()));
+    }
+
+    #[test]
+    fn bare_word_normalizes_to_nfc() {
+        // "e\u{0301}" is 'e' followed by a combining acute accent; NFC
+        // composes it to the single precomposed code point "\u{e9}" (é).
+        let decomposed = "e\u{0301}";
+        let mut lx = Lexer::new(decomposed);
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("\u{e9}".into()));
+    }
+
+    #[test]
+    fn quoted_string_normalizes_to_nfc() {
+        let mut lx = Lexer::new("comment:\"caf\u{65}\u{0301}\"");
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Field("comment".into()));
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("caf\u{e9}".into()));
+    }
+
+    #[test]
+    fn non_ascii_field_name_tokenizes_via_is_xid_start() {
+        let mut lx = Lexer::new("\u{3b1}\u{3b2}:value");
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Field("\u{3b1}\u{3b2}".into()));
+    }
+
+    #[test]
+    fn stray_non_identifier_symbol_becomes_a_single_char_word() {
+        // an emoji isn't a valid identifier start, isn't an ASCII digit,
+        // and isn't ASCII punctuation that the grammar gives meaning to,
+        // so it's lexed as its own one-character word rather than
+        // swallowing whatever non-whitespace text follows it.
+        let mut lx = Lexer::new("\u{1f600}bar");
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("\u{1f600}".into()));
+        let (tok, _, _) = lx.next_token().unwrap();
+        assert_eq!(tok, Token::Word("bar".into()));
+    }
 }
@@ -1,27 +1,61 @@
 use crate::query::Node;
 use crate::pcre2_pool::PatternPool;
 use crate::pcre2_pool::CompiledPattern;
-use crate::query::parser_rs::{CompareOp, Bound};
+use crate::query::aho_corasick::AhoCorasick;
+use crate::query::field_schema::FieldSchema;
+use crate::query::parser_rs::{CompareOp, Bound, is_glob_pattern, is_structural_pattern};
+use crate::query::type_registry::TypeRegistry;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+/// Process-wide interner for field names and modifier strings. `compile`
+/// looks up or inserts into this map so that every occurrence of the same
+/// field/modifier across a compiled query tree (and across separate
+/// `compile` calls) shares a single allocation, instead of each
+/// `CompiledNode::Leaf` cloning its own `String`.
+static STR_INTERNER: Lazy<Mutex<HashMap<String, Arc<str>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub(crate) fn intern(s: &str) -> Arc<str> {
+    let mut map = STR_INTERNER.lock();
+    if let Some(existing) = map.get(s) {
+        return existing.clone();
+    }
+    let arc: Arc<str> = Arc::from(s);
+    map.insert(s.to_string(), arc.clone());
+    arc
+}
+
+fn intern_mods(mods: &[String]) -> Vec<Arc<str>> {
+    mods.iter().map(|m| intern(m)).collect()
+}
+
 /// A compiled representation of a query node. This is intentionally
 /// lightweight and keeps compiled PCRE2 patterns (or other compiled
 /// patterns) inside `Arc<dyn CompiledPattern>` so matching is cheap.
+/// `field` and `mods` are interned (`Arc<str>`) so cloning a node while
+/// walking the tree for field inheritance or metadata never allocates.
 #[derive(Clone)]
 pub enum CompiledNode {
     Leaf {
         pat: Arc<dyn CompiledPattern>,
+        /// The final PCRE2 pattern source the leaf was compiled from
+        /// (after modifiers such as `(?i)` / anchoring were applied).
+        /// Kept around so the node can be re-serialized without access
+        /// to the original `Node` it came from.
+        pattern: Arc<str>,
         negated: bool,
-        field: Option<String>,
-        mods: Vec<String>,
+        field: Option<Arc<str>>,
+        mods: Vec<Arc<str>>,
     },
     Compare {
-        field: Option<String>,
+        field: Option<Arc<str>>,
         op: CompareOp,
         value: String,
     },
     Range {
-        field: Option<String>,
+        field: Option<Arc<str>>,
         low: Bound,
         high: Bound,
     },
@@ -29,13 +63,45 @@ pub enum CompiledNode {
         name: String,
         args: Vec<String>,
     },
+    /// A fast path lowered from an `Or` subtree consisting solely of
+    /// non-negated literal leaves that share the same (or no) field. A
+    /// single Aho-Corasick pass replaces one PCRE2 scan per literal.
+    MultiLiteral {
+        ac: Arc<AhoCorasick>,
+        /// The literal strings the automaton was built from, kept around
+        /// (like `Leaf`'s `pattern`) so the node can be re-serialized
+        /// without rebuilding it from the original `Node` tree.
+        literals: Vec<Arc<str>>,
+        icase: bool,
+        field: Option<Arc<str>>,
+    },
+    /// A structural pattern (e.g. `foo_$x_bar`) lowered into a regex with
+    /// one lazy capture group per metavariable occurrence. A match is only
+    /// accepted once `structural_bindings` confirms every repeated use of
+    /// the same metavariable captured identical bytes.
+    Structural {
+        pat: Arc<dyn CompiledPattern>,
+        /// The original structural pattern source, kept around (like
+        /// `MultiLiteral`'s `literals`) so the node can be re-serialized
+        /// and its regex rebuilt without the original `Node::Structural`.
+        template: Arc<str>,
+        /// Metavariable name for each capture group in order: group 1's
+        /// name is `group_vars[0]`, group 2's is `group_vars[1]`, etc.
+        group_vars: Vec<Arc<str>>,
+        icase: bool,
+        negated: bool,
+        field: Option<Arc<str>>,
+    },
     And(Box<CompiledNode>, Box<CompiledNode>),
     Or(Box<CompiledNode>, Box<CompiledNode>),
     Not(Box<CompiledNode>),
 }
 
+#[derive(Clone)]
 pub struct QueryMatcher {
     pool: PatternPool,
+    schema: FieldSchema,
+    types: TypeRegistry,
 }
 
 /// Normalize capture ranges: sort, remove duplicates, and merge overlaps/adjacent.
@@ -57,21 +123,154 @@ fn normalize_ranges(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
 }
 
 /// Collect a representative field name from the compiled node subtree, if any.
-fn collect_field_from_compiled(node: &CompiledNode) -> Option<String> {
+fn collect_field_from_compiled(node: &CompiledNode) -> Option<Arc<str>> {
     match node {
         CompiledNode::Leaf { field, .. } => field.clone(),
         CompiledNode::Compare { field, .. } => field.clone(),
         CompiledNode::Range { field, .. } => field.clone(),
         CompiledNode::Function { .. } => None,
+        CompiledNode::MultiLiteral { field, .. } => field.clone(),
+        CompiledNode::Structural { field, .. } => field.clone(),
         CompiledNode::Not(inner) => collect_field_from_compiled(inner),
         CompiledNode::Or(a, b) => collect_field_from_compiled(a).or_else(|| collect_field_from_compiled(b)),
         CompiledNode::And(a, b) => collect_field_from_compiled(a).or_else(|| collect_field_from_compiled(b)),
     }
 }
 
+/// Flatten a subtree of `Or`/`Group` nodes down to its leaf literal terms,
+/// as long as every leaf is a non-negated `Word` or a `Field` with a plain
+/// (non-regex) term and all fields present agree. Returns `None` if the
+/// subtree doesn't qualify for the `MultiLiteral` fast path.
+fn flatten_literal_or_chain(node: &Node) -> Option<(Vec<String>, Option<String>)> {
+    fn walk(node: &Node, literals: &mut Vec<String>, field: &mut Option<Option<String>>) -> bool {
+        match node {
+            Node::Or(a, b) => walk(a, literals, field) && walk(b, literals, field),
+            Node::Group(inner) => walk(inner, literals, field),
+            Node::Word(s) => {
+                match field {
+                    None => *field = Some(None),
+                    Some(None) => {}
+                    Some(Some(_)) => return false,
+                }
+                literals.push(s.clone());
+                true
+            }
+            Node::Field(name, term) => {
+                if term.len() >= 2 && term.starts_with('/') && term.ends_with('/') {
+                    return false;
+                }
+                match field {
+                    None => *field = Some(Some(name.clone())),
+                    Some(Some(f)) if f == name => {}
+                    _ => return false,
+                }
+                literals.push(term.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+    let mut literals = Vec::new();
+    let mut field: Option<Option<String>> = None;
+    if walk(node, &mut literals, &mut field) && literals.len() >= 2 {
+        Some((literals, field.flatten()))
+    } else {
+        None
+    }
+}
+
+/// Group a structural match's capture ranges (as returned by
+/// `CompiledPattern::captures_ranges`, index 0 being the whole match) by
+/// metavariable name in first-occurrence order, rejecting the match
+/// (`None`) if two occurrences of the same metavariable captured different
+/// bytes. `group_vars[i]` is the name of capture group `i + 1`.
+fn structural_bindings(group_vars: &[Arc<str>], ranges: &[(usize, usize)], text: &[u8]) -> Option<Vec<(Arc<str>, (usize, usize))>> {
+    let mut bindings: Vec<(Arc<str>, (usize, usize))> = Vec::new();
+    for (i, name) in group_vars.iter().enumerate() {
+        let range = match ranges.get(i + 1) {
+            Some(&r) => r,
+            None => return None,
+        };
+        match bindings.iter().find(|(n, _)| n == name) {
+            Some((_, existing)) => {
+                if text[existing.0..existing.1] != text[range.0..range.1] {
+                    return None;
+                }
+            }
+            None => bindings.push((name.clone(), range)),
+        }
+    }
+    Some(bindings)
+}
+
 impl QueryMatcher {
     pub fn new(pool: PatternPool) -> Self {
-        QueryMatcher { pool }
+        QueryMatcher { pool, schema: FieldSchema::new(), types: TypeRegistry::new() }
+    }
+
+    /// Build a matcher with a custom field-extraction schema, e.g. to add
+    /// fields for externally supplied metadata columns.
+    pub fn with_schema(pool: PatternPool, schema: FieldSchema) -> Self {
+        QueryMatcher { pool, schema, types: TypeRegistry::new() }
+    }
+
+    /// Build a matcher with a custom field-extraction schema and file-type
+    /// alias registry.
+    pub fn with_schema_and_types(pool: PatternPool, schema: FieldSchema, types: TypeRegistry) -> Self {
+        QueryMatcher { pool, schema, types }
+    }
+
+    /// Register (or replace) a `type:` alias, e.g. `add_type("proto", &["proto"])`.
+    pub fn add_type(&mut self, name: &str, extensions: &[&str]) {
+        self.types.add(name, extensions);
+    }
+
+    /// Expand `type:<name>` into an `Or` of `extension:<ext>` nodes for
+    /// every extension the alias covers. Unknown aliases fall back to
+    /// matching the alias name itself as a literal extension, so
+    /// `type:foo` behaves like `extension:foo` until `foo` is registered.
+    fn expand_type_alias(&self, name: &str) -> Node {
+        let extensions = self
+            .types
+            .extensions(name)
+            .map(|exts| exts.to_vec())
+            .unwrap_or_else(|| vec![name.to_string()]);
+        let mut iter = extensions.into_iter();
+        let first = iter.next().unwrap_or_else(|| name.to_string());
+        let mut expanded = Node::Field("extension".to_string(), first);
+        for ext in iter {
+            expanded = Node::Or(Box::new(expanded), Box::new(Node::Field("extension".to_string(), ext)));
+        }
+        expanded
+    }
+
+    /// The typed numeric parser `Compare`/`Range` should use for `field`:
+    /// the schema's registered parser when there is one, otherwise a plain
+    /// decimal/float parser.
+    fn value_parser_for(&self, field: &Option<Arc<str>>) -> impl Fn(&[u8]) -> Option<f64> + '_ {
+        move |b: &[u8]| {
+            if let Some(f) = field {
+                if let Some(parser) = self.schema.value_parser(f) {
+                    return parser(b);
+                }
+            }
+            crate::query::value_parse::parse_plain_number(b)
+        }
+    }
+
+    /// Compile a structural pattern (`foo_$x_bar`) into a `CompiledNode::Structural`.
+    fn build_structural(&self, pat: &str, field: Option<Arc<str>>, icase: bool, negated: bool) -> Result<CompiledNode, pcre2::Error> {
+        let (body, group_vars) = compile_structural(pat);
+        let pattern = build_pattern_for_structural(&body, icase);
+        let arc = self.pool.acquire_pcre2(&pattern)?;
+        Ok(CompiledNode::Structural {
+            pat: arc,
+            template: intern(pat),
+            group_vars: group_vars.iter().map(|s| intern(s)).collect(),
+            icase,
+            negated,
+            field,
+        })
     }
 
     /// Compile a `Node` into a `CompiledNode` using PCRE2 for both
@@ -83,40 +282,69 @@ impl QueryMatcher {
                 let empty: Vec<String> = Vec::new();
                 let pattern = build_pattern_for_literal(s, &empty);
                 let arc = self.pool.acquire_pcre2(&pattern)?;
-                Ok(CompiledNode::Leaf { pat: arc, negated: false, field: None, mods: vec![] })
+                Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated: false, field: None, mods: vec![] })
             }
             Node::Regex(pat) => {
                 let empty: Vec<String> = Vec::new();
                 let pattern = build_pattern_for_regex(pat, &empty);
                 let arc = self.pool.acquire_pcre2(&pattern)?;
-                Ok(CompiledNode::Leaf { pat: arc, negated: false, field: None, mods: vec![] })
+                Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated: false, field: None, mods: vec![] })
+            }
+            Node::Glob(pat) => {
+                let empty: Vec<String> = Vec::new();
+                let pattern = build_pattern_for_glob(pat, &empty);
+                let arc = self.pool.acquire_pcre2(&pattern)?;
+                Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated: false, field: glob_default_field(pat), mods: vec![] })
             }
+            Node::Structural(pat) => self.build_structural(pat, structural_default_field(pat), false, false),
             Node::Not(inner) => {
                 let c = self.compile(inner)?;
                 Ok(CompiledNode::Not(Box::new(c)))
             }
             Node::And(a, b) => Ok(CompiledNode::And(Box::new(self.compile(a)?), Box::new(self.compile(b)?))),
-            Node::Or(a, b) => Ok(CompiledNode::Or(Box::new(self.compile(a)?), Box::new(self.compile(b)?))),
+            Node::Or(a, b) => {
+                if let Some((literals, field)) = flatten_literal_or_chain(node) {
+                    return Ok(CompiledNode::MultiLiteral {
+                        ac: Arc::new(AhoCorasick::build(&literals, false)),
+                        literals: literals.iter().map(|s| intern(s)).collect(),
+                        icase: false,
+                        field: field.as_deref().map(intern),
+                    });
+                }
+                Ok(CompiledNode::Or(Box::new(self.compile(a)?), Box::new(self.compile(b)?)))
+            }
             Node::Group(inner) => Ok(self.compile(inner)?),
+            Node::Field(name, term) if name == "type" => {
+                // `type:rust` etc. expand at compile time into an `Or` of
+                // `extension:` matchers, so captures_meta still reports
+                // ranges over the matched extension bytes like a direct
+                // `extension:` query would.
+                self.compile(&self.expand_type_alias(term))
+            }
             Node::Field(name, term) => {
+                let empty: Vec<String> = Vec::new();
                 if term.len() >= 2 && term.starts_with('/') && term.ends_with('/') {
                     let pat = term[1..term.len()-1].to_string();
-                    let empty: Vec<String> = Vec::new();
                     let pattern = build_pattern_for_regex(&pat, &empty);
                     let arc = self.pool.acquire_pcre2(&pattern)?;
-                    Ok(CompiledNode::Leaf { pat: arc, negated: false, field: Some(name.clone()), mods: vec![] })
+                    Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated: false, field: Some(intern(name)), mods: vec![] })
+                } else if is_structural_pattern(term) {
+                    self.build_structural(term, Some(intern(name)), false, false)
+                } else if is_glob_pattern(term) {
+                    let pattern = build_pattern_for_glob(term, &empty);
+                    let arc = self.pool.acquire_pcre2(&pattern)?;
+                    Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated: false, field: Some(intern(name)), mods: vec![] })
                 } else {
-                    let empty: Vec<String> = Vec::new();
                     let pattern = build_pattern_for_literal(term, &empty);
                     let arc = self.pool.acquire_pcre2(&pattern)?;
-                    Ok(CompiledNode::Leaf { pat: arc, negated: false, field: Some(name.clone()), mods: vec![] })
+                    Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated: false, field: Some(intern(name)), mods: vec![] })
                 }
             }
             Node::Compare(field, op, val) => {
-                return Ok(CompiledNode::Compare { field: Some(field.clone()), op: op.clone(), value: val.clone() });
+                return Ok(CompiledNode::Compare { field: Some(intern(field)), op: op.clone(), value: val.clone() });
             }
             Node::Range(field_name, low, high) => {
-                return Ok(CompiledNode::Range { field: Some(field_name.clone()), low: low.clone(), high: high.clone() });
+                return Ok(CompiledNode::Range { field: Some(intern(field_name)), low: low.clone(), high: high.clone() });
             }
             Node::Modified(inner, mods) => {
                 // Apply modifiers to the inner term when compiling.
@@ -125,30 +353,61 @@ impl QueryMatcher {
                     Node::Word(s) => {
                         let pattern = build_pattern_for_literal(s, mods);
                         let arc = self.pool.acquire_pcre2(&pattern)?;
-                        Ok(CompiledNode::Leaf { pat: arc, negated, field: None, mods: mods.clone() })
+                        Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: None, mods: intern_mods(mods) })
                     }
                     Node::Regex(pat) => {
                         let pattern = build_pattern_for_regex(pat, mods);
                         let arc = self.pool.acquire_pcre2(&pattern)?;
-                        Ok(CompiledNode::Leaf { pat: arc, negated, field: None, mods: mods.clone() })
+                        Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: None, mods: intern_mods(mods) })
                     }
                     Node::Field(name, term) => {
                         if term.len() >= 2 && term.starts_with('/') && term.ends_with('/') {
                             let pat = term[1..term.len()-1].to_string();
                             let pattern = build_pattern_for_regex(&pat, mods);
                             let arc = self.pool.acquire_pcre2(&pattern)?;
-                            Ok(CompiledNode::Leaf { pat: arc, negated, field: Some(name.clone()), mods: mods.clone() })
+                            Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: Some(intern(name)), mods: intern_mods(mods) })
+                        } else if is_structural_pattern(term) {
+                            let icase = mods.iter().any(|m| m.eq_ignore_ascii_case("i") || m.eq_ignore_ascii_case("icase") || m.eq_ignore_ascii_case("ignorecase"));
+                            self.build_structural(term, Some(intern(name)), icase, negated)
+                        } else if is_glob_pattern(term) {
+                            let pattern = build_pattern_for_glob(term, mods);
+                            let arc = self.pool.acquire_pcre2(&pattern)?;
+                            Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: Some(intern(name)), mods: intern_mods(mods) })
                         } else {
                             let pattern = build_pattern_for_literal(term, mods);
                             let arc = self.pool.acquire_pcre2(&pattern)?;
-                            Ok(CompiledNode::Leaf { pat: arc, negated, field: Some(name.clone()), mods: mods.clone() })
+                            Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: Some(intern(name)), mods: intern_mods(mods) })
                         }
                     }
+                    Node::Glob(pat) => {
+                        let pattern = build_pattern_for_glob(pat, mods);
+                        let arc = self.pool.acquire_pcre2(&pattern)?;
+                        Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: glob_default_field(pat), mods: intern_mods(mods) })
+                    }
+                    Node::Structural(pat) => {
+                        let icase = mods.iter().any(|m| m.eq_ignore_ascii_case("i") || m.eq_ignore_ascii_case("icase") || m.eq_ignore_ascii_case("ignorecase"));
+                        self.build_structural(pat, structural_default_field(pat), icase, negated)
+                    }
+                    Node::Or(_, _) if !negated => {
+                        if let Some((literals, field)) = flatten_literal_or_chain(inner) {
+                            let icase = mods.iter().any(|m| m.eq_ignore_ascii_case("icase") || m.eq_ignore_ascii_case("i"));
+                            return Ok(CompiledNode::MultiLiteral {
+                                ac: Arc::new(AhoCorasick::build(&literals, icase)),
+                                literals: literals.iter().map(|s| intern(s)).collect(),
+                                icase,
+                                field: field.as_deref().map(intern),
+                            });
+                        }
+                        let s = format!("{:?}", inner);
+                        let pattern = build_pattern_for_literal(&s, mods);
+                        let arc = self.pool.acquire_pcre2(&pattern)?;
+                        Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: None, mods: intern_mods(mods) })
+                    }
                     other => {
                         let s = format!("{:?}", other);
                         let pattern = build_pattern_for_literal(&s, mods);
                         let arc = self.pool.acquire_pcre2(&pattern)?;
-                        Ok(CompiledNode::Leaf { pat: arc, negated, field: None, mods: mods.clone() })
+                        Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated, field: None, mods: intern_mods(mods) })
                     }
                 }
             }
@@ -158,7 +417,7 @@ impl QueryMatcher {
                 let empty: Vec<String> = Vec::new();
                 let pattern = build_pattern_for_literal(&s, &empty);
                 let arc = self.pool.acquire_pcre2(&pattern)?;
-                Ok(CompiledNode::Leaf { pat: arc, negated: false, field: None, mods: vec![] })
+                Ok(CompiledNode::Leaf { pat: arc, pattern: intern(&pattern), negated: false, field: None, mods: vec![] })
             }
         }
     }
@@ -167,36 +426,61 @@ impl QueryMatcher {
     pub fn is_match(&self, compiled: &CompiledNode, text: &[u8]) -> bool {
         match compiled {
             CompiledNode::Leaf { pat, negated, field, .. } => {
-                // If this leaf is targeted at a specific field, scope
-                // the matching to that field. Currently we special-case
-                // the `extension` field to match only the file extension
-                // of the `name` portion. The combined text format used by
-                // the search pipeline is `name + "\n" + path`.
+                // If this leaf is targeted at a specific field, scope the
+                // matching to the byte range the field's schema extractor
+                // reports within the combined `name + "\n" + path` text.
+                // A field with no registered extractor falls back to
+                // matching the whole combined text.
                 let res = if let Some(f) = field {
-                    if f.eq("extension") {
-                        // extract name (before first newline)
-                        if let Ok(s) = std::str::from_utf8(text) {
-                            let name = s.splitn(2, '\n').next().unwrap_or("");
-                            if let Some(dot_idx) = name.rfind('.') {
-                                let ext = &name[dot_idx+1..];
-                                pat.is_match(ext.as_bytes())
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        }
-                    } else {
-                        pat.is_match(text)
+                    match self.schema.extract(f, text) {
+                        Some((start, end)) => pat.is_match(&text[start..end]),
+                        None if self.schema.contains(f) => false,
+                        None => pat.is_match(text),
                     }
                 } else {
                     pat.is_match(text)
                 };
                 if *negated { !res } else { res }
             }
-            CompiledNode::Compare { field: _, op, value } => {
-                // Try numeric comparison first
-                if let (Ok(lhs), Ok(rhs)) = (parse_number_from_bytes(text), value.parse::<i128>()) {
+            CompiledNode::MultiLiteral { ac, field, .. } => {
+                if let Some(f) = field {
+                    match self.schema.extract(f, text) {
+                        Some((start, end)) => ac.is_match(&text[start..end]),
+                        None if self.schema.contains(f) => false,
+                        None => ac.is_match(text),
+                    }
+                } else {
+                    ac.is_match(text)
+                }
+            }
+            CompiledNode::Structural { pat, group_vars, field, negated, .. } => {
+                let scoped: Option<&[u8]> = if let Some(f) = field {
+                    match self.schema.extract(f, text) {
+                        Some((start, end)) => Some(&text[start..end]),
+                        None if self.schema.contains(f) => None,
+                        None => Some(text),
+                    }
+                } else {
+                    Some(text)
+                };
+                let res = match scoped {
+                    Some(t) => match pat.captures_ranges(t) {
+                        Some(ranges) => structural_bindings(group_vars, &ranges, t).is_some(),
+                        None => false,
+                    },
+                    None => false,
+                };
+                if *negated { !res } else { res }
+            }
+            CompiledNode::Compare { field, op, value } => {
+                // Parse both sides through the field's typed value parser
+                // (e.g. `size` as bytes, `modified`/`mtime`/`ctime` as a
+                // timestamp) before falling back to lexicographic string
+                // comparison.
+                let parser = self.value_parser_for(field);
+                let lhs = parser(text);
+                let rhs = parser(value.as_bytes());
+                if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
                     match op {
                         CompareOp::Eq => lhs == rhs,
                         CompareOp::Contains => lhs.to_string().contains(&rhs.to_string()),
@@ -205,6 +489,32 @@ impl QueryMatcher {
                         CompareOp::Greater => lhs > rhs,
                         CompareOp::GreaterEq => lhs >= rhs,
                     }
+                } else if let (Some(lhs), Some(dur)) = (
+                    lhs.filter(|_| field.as_deref().is_some_and(|f| crate::query::field_schema::TIME_FIELDS.contains(&f))),
+                    crate::query::value_parse::parse_duration_seconds(value.as_bytes()),
+                ) {
+                    // `value` isn't an absolute date the field parser
+                    // understood, but it is a relative duration like `7d` —
+                    // compare the candidate's age (now - lhs) against it, so
+                    // `mtime:<7d` reads as "modified within the last 7 days".
+                    // Gated to time-like fields so e.g. `size<7d` (lhs parses
+                    // as bytes, not a timestamp) falls through to the
+                    // lexicographic comparison below instead of treating a
+                    // byte count as a Unix timestamp.
+                    match crate::query::value_parse::now_epoch_seconds() {
+                        Some(now) => {
+                            let age = now - lhs;
+                            match op {
+                                CompareOp::Eq => (age - dur).abs() < 0.5,
+                                CompareOp::Contains => age.to_string().contains(&dur.to_string()),
+                                CompareOp::Smaller => age < dur,
+                                CompareOp::SmallerEq => age <= dur,
+                                CompareOp::Greater => age > dur,
+                                CompareOp::GreaterEq => age >= dur,
+                            }
+                        }
+                        None => false,
+                    }
                     } else {
                     // Fallback to substring/lexicographic comparisons
                     let s = String::from_utf8_lossy(text);
@@ -219,14 +529,15 @@ impl QueryMatcher {
                     }
                 }
             }
-            CompiledNode::Range { field: _, low, high } => {
-                // Numeric-aware range check
-                if let Ok(v) = parse_number_from_bytes(text) {
+            CompiledNode::Range { field, low, high } => {
+                // Numeric-aware range check, via the field's typed parser.
+                let parser = self.value_parser_for(field);
+                if let Some(v) = parser(text) {
                     let mut ok = true;
-                    if let Bound::Inclusive(ref s) = low { if let Ok(lv) = s.parse::<i128>() { ok &= v >= lv; } }
-                    if let Bound::Exclusive(ref s) = low { if let Ok(lv) = s.parse::<i128>() { ok &= v > lv; } }
-                    if let Bound::Inclusive(ref s) = high { if let Ok(hv) = s.parse::<i128>() { ok &= v <= hv; } }
-                    if let Bound::Exclusive(ref s) = high { if let Ok(hv) = s.parse::<i128>() { ok &= v < hv; } }
+                    if let Bound::Inclusive(ref s) = low { if let Some(lv) = parser(s.as_bytes()) { ok &= v >= lv; } }
+                    if let Bound::Exclusive(ref s) = low { if let Some(lv) = parser(s.as_bytes()) { ok &= v > lv; } }
+                    if let Bound::Inclusive(ref s) = high { if let Some(hv) = parser(s.as_bytes()) { ok &= v <= hv; } }
+                    if let Bound::Exclusive(ref s) = high { if let Some(hv) = parser(s.as_bytes()) { ok &= v < hv; } }
                     ok
                 } else {
                     // Lexicographic fallback using UTF-8 text
@@ -267,31 +578,58 @@ impl QueryMatcher {
     pub fn captures(&self, compiled: &CompiledNode, text: &[u8]) -> Vec<(usize, usize)> {
         match compiled {
             CompiledNode::Leaf { pat, field, .. } => {
-                // For field-scoped leaves, adjust captured ranges to the
-                // combined text layout. Special-case `extension` to map
-                // captures into the `name` portion's extension bytes.
+                // For field-scoped leaves, run the pattern over the byte
+                // range the field's schema extractor reports, then shift
+                // the resulting capture ranges back to be relative to the
+                // combined text's start.
                 if let Some(f) = field {
-                    if f.eq("extension") {
-                        if let Ok(s) = std::str::from_utf8(text) {
-                            let name = s.splitn(2, '\n').next().unwrap_or("");
-                            if let Some(dot_idx) = name.rfind('.') {
-                                let ext = &name[dot_idx+1..];
-                                if let Some(mut ranges) = pat.captures_ranges(ext.as_bytes()) {
-                                    // shift ranges by dot_idx+1 to be relative to
-                                    // the combined text start
-                                    for r in ranges.iter_mut() {
-                                        r.0 += dot_idx + 1;
-                                        r.1 += dot_idx + 1;
-                                    }
-                                    return ranges;
-                                }
+                    if let Some((start, end)) = self.schema.extract(f, text) {
+                        if let Some(mut ranges) = pat.captures_ranges(&text[start..end]) {
+                            for r in ranges.iter_mut() {
+                                r.0 += start;
+                                r.1 += start;
                             }
+                            return ranges;
                         }
                         return vec![];
+                    } else if self.schema.contains(f) {
+                        return vec![];
                     }
                 }
                 pat.captures_ranges(text).unwrap_or_default()
             }
+            CompiledNode::MultiLiteral { ac, field, .. } => {
+                if let Some(f) = field {
+                    if let Some((start, end)) = self.schema.extract(f, text) {
+                        return ac
+                            .find_ranges(&text[start..end])
+                            .into_iter()
+                            .map(|(s, e)| (s + start, e + start))
+                            .collect();
+                    } else if self.schema.contains(f) {
+                        return vec![];
+                    }
+                }
+                ac.find_ranges(text)
+            }
+            CompiledNode::Structural { pat, group_vars, field, .. } => {
+                let (scoped_text, offset): (&[u8], usize) = if let Some(f) = field {
+                    match self.schema.extract(f, text) {
+                        Some((start, end)) => (&text[start..end], start),
+                        None if self.schema.contains(f) => return vec![],
+                        None => (text, 0),
+                    }
+                } else {
+                    (text, 0)
+                };
+                match pat.captures_ranges(scoped_text) {
+                    Some(ranges) => match structural_bindings(group_vars, &ranges, scoped_text) {
+                        Some(bindings) => bindings.into_iter().map(|(_, (s, e))| (s + offset, e + offset)).collect(),
+                        None => vec![],
+                    },
+                    None => vec![],
+                }
+            }
             CompiledNode::Compare { .. } => vec![],
             CompiledNode::Range { .. } => vec![],
                 CompiledNode::Function { name, args } => {
@@ -325,7 +663,7 @@ impl QueryMatcher {
 /// Metadata about a match suitable for UI highlighting.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MatchMeta {
-    pub field: Option<String>,
+    pub field: Option<Arc<str>>,
     pub ranges: Vec<(usize, usize)>,
 }
 
@@ -342,12 +680,12 @@ impl QueryMatcher {
                 // an additional `extension` field entry with empty ranges to
                 // indicate extension-column highlighting (parity with C).
                 if let Some(f) = field {
-                    if f.eq("extension") {
+                    if f.as_ref() == "extension" {
                         let mut ranges = self.captures(compiled, text);
                         ranges = normalize_ranges(ranges);
                         if ranges.is_empty() { return vec![]; }
-                        let name_meta = MatchMeta { field: Some("name".to_string()), ranges: ranges.clone() };
-                        let ext_meta = MatchMeta { field: Some("extension".to_string()), ranges: vec![] };
+                        let name_meta = MatchMeta { field: Some(intern("name")), ranges: ranges.clone() };
+                        let ext_meta = MatchMeta { field: Some(intern("extension")), ranges: vec![] };
                         return vec![name_meta, ext_meta];
                     }
                 }
@@ -356,6 +694,18 @@ impl QueryMatcher {
                 if ranges.is_empty() { return vec![]; }
                 vec![MatchMeta { field: field.clone(), ranges }]
             }
+            CompiledNode::MultiLiteral { field, .. } => {
+                let mut ranges = self.captures(compiled, text);
+                ranges = normalize_ranges(ranges);
+                if ranges.is_empty() { return vec![]; }
+                vec![MatchMeta { field: field.clone(), ranges }]
+            }
+            CompiledNode::Structural { field, .. } => {
+                let mut ranges = self.captures(compiled, text);
+                ranges = normalize_ranges(ranges);
+                if ranges.is_empty() { return vec![]; }
+                vec![MatchMeta { field: field.clone(), ranges }]
+            }
             CompiledNode::Compare { field, .. } => vec![MatchMeta { field: field.clone(), ranges: vec![] }],
             CompiledNode::Range { field, .. } => vec![MatchMeta { field: field.clone(), ranges: vec![] }],
             CompiledNode::Function { .. } => {
@@ -394,12 +744,122 @@ impl QueryMatcher {
             }
         }
     }
+
+    /// Expand `template` against the capture groups `compiled` finds in
+    /// `text`, regex-crate style: `$1`/`${1}` for a positional group (group
+    /// 0 is the whole match), `${name}` for a named group (only resolvable
+    /// when `compiled` is a single `Leaf`, since that's the only case with
+    /// one pattern's names to look up), `$$` for a literal `$`, and any
+    /// other or non-participating reference expanding to empty. Useful for
+    /// bulk-rename / result-rewriting, e.g. `(\d{4})-(\d{2})` with template
+    /// `${2}/${1}`.
+    pub fn replace(&self, compiled: &CompiledNode, text: &[u8], template: &str) -> Vec<u8> {
+        let ranges = self.captures(compiled, text);
+        let name_index = |name: &str| match compiled {
+            CompiledNode::Leaf { pat, .. } => pat.capture_name_index(name),
+            _ => None,
+        };
+        expand_template(template, text, &ranges, name_index)
+    }
+
+    /// Serialize `compiled` to CBOR bytes suitable for an on-disk cache.
+    /// See [`crate::query::codec`] for the wire format.
+    pub fn encode(&self, compiled: &CompiledNode) -> Result<Vec<u8>, crate::query::codec::CodecError> {
+        crate::query::codec::encode(compiled)
+    }
+
+    /// Deserialize bytes produced by [`QueryMatcher::encode`], re-acquiring
+    /// each leaf's PCRE2 pattern from this matcher's `PatternPool`.
+    pub fn decode(&self, bytes: &[u8]) -> Result<CompiledNode, crate::query::codec::CodecError> {
+        crate::query::codec::decode(&self.pool, bytes)
+    }
+
+    /// Compile `pattern` into a [`crate::stream_matcher::StreamMatcher`] for
+    /// content search: unlike `compile`, which matches the already-in-memory
+    /// text `captures`/`is_match` are given, this is for scanning a file's
+    /// *contents* without reading the whole thing into memory first. Kept
+    /// separate from `CompiledNode` because it targets a practical subset
+    /// of regex syntax (see the module docs), not the full PCRE2 grammar
+    /// `compile`'s leaves support.
+    pub fn compile_stream(&self, pattern: &str) -> Result<crate::stream_matcher::StreamMatcher, crate::stream_matcher::PatternError> {
+        crate::stream_matcher::StreamMatcher::new(pattern)
+    }
+
+    /// Scan `reader`'s content for matches to `matcher`, yielding each hit's
+    /// line number and byte range as the underlying `Read` is consumed.
+    pub fn search_stream<'a, R: std::io::Read>(
+        &self,
+        matcher: &'a crate::stream_matcher::StreamMatcher,
+        reader: R,
+    ) -> crate::stream_matcher::StreamSearch<'a, R> {
+        matcher.search_stream(reader)
+    }
+}
+
+/// Resolve a `$name` / `${name}` reference to a capture group index: a
+/// purely-numeric name is a positional group, otherwise it's looked up via
+/// `name_index` (named groups, only available for a single compiled
+/// pattern). Appends the referenced group's bytes to `out`, or nothing if
+/// the reference doesn't resolve to a participating group.
+fn append_group(out: &mut Vec<u8>, name: &str, text: &[u8], ranges: &[(usize, usize)], name_index: &impl Fn(&str) -> Option<usize>) {
+    let idx = name.parse::<usize>().ok().or_else(|| name_index(name));
+    if let Some(i) = idx {
+        if let Some(&(s, e)) = ranges.get(i) {
+            out.extend_from_slice(&text[s..e]);
+        }
+    }
 }
 
-fn parse_number_from_bytes(b: &[u8]) -> Result<i128, std::num::ParseIntError> {
-    let s = String::from_utf8_lossy(b);
-    // accept decimal integers only for now
-    s.trim().parse::<i128>()
+/// Expand `$1`, `${1}`, `${name}`, and `$$` references in `template` against
+/// `ranges` (capture group index -> byte range within `text`), following
+/// the same rules as `regex::Captures::expand`.
+fn expand_template(template: &str, text: &[u8], ranges: &[(usize, usize)], name_index: impl Fn(&str) -> Option<usize>) -> Vec<u8> {
+    let bytes = template.as_bytes();
+    let mut out = Vec::with_capacity(template.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'$' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        if i + 1 >= bytes.len() {
+            out.push(b'$');
+            i += 1;
+            continue;
+        }
+        match bytes[i + 1] {
+            b'$' => {
+                out.push(b'$');
+                i += 2;
+            }
+            b'{' => match template[i + 2..].find('}') {
+                Some(close) => {
+                    let name = &template[i + 2..i + 2 + close];
+                    append_group(&mut out, name, text, ranges, &name_index);
+                    i = i + 2 + close + 1;
+                }
+                None => {
+                    out.push(b'$');
+                    i += 1;
+                }
+            },
+            c if c.is_ascii_alphanumeric() || c == b'_' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_') {
+                    end += 1;
+                }
+                append_group(&mut out, &template[start..end], text, ranges, &name_index);
+                i = end;
+            }
+            _ => {
+                out.push(b'$');
+                i += 1;
+            }
+        }
+    }
+    out
 }
 
 fn escape_literal(s: &str) -> String {
@@ -433,6 +893,171 @@ fn build_pattern_for_literal(s: &str, mods: &Vec<String>) -> String {
     pat
 }
 
+/// The field a bare (unscoped) `Node::Glob` should be scoped to: `name` for
+/// a plain glob like `*.tmp` (globset's `*` never crosses `/`, so it can
+/// only ever match within the filename), or unscoped (the whole combined
+/// `name + "\n" + path` text) once the pattern contains a literal `/`,
+/// since then it needs to match across path separators.
+fn glob_default_field(pat: &str) -> Option<Arc<str>> {
+    if pat.contains('/') {
+        None
+    } else {
+        Some(intern("name"))
+    }
+}
+
+/// Translate glob syntax into an anchored PCRE2 pattern, following globset
+/// semantics: `*` matches any run of bytes other than `/`, `**` also
+/// crosses `/` (and swallows one trailing `/` so `**/foo` matches `foo`
+/// itself as well as any number of directories above it), `?` matches a
+/// single non-`/` byte, and `[...]`/`[!...]` character classes pass through
+/// almost verbatim (PCRE2 uses `^` rather than `!` for negation). The whole
+/// pattern is wrapped in a capturing group so captures/captures_meta report
+/// the full matched span, the same way `escape_literal` does for literals.
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut body = String::with_capacity(chars.len() * 2);
+    let mut saw_double_star = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                if i + 1 < chars.len() && chars[i + 1] == '*' {
+                    saw_double_star = true;
+                    body.push_str(".*");
+                    i += 2;
+                    if i < chars.len() && chars[i] == '/' {
+                        i += 1;
+                    }
+                } else {
+                    body.push_str("[^/]*");
+                    i += 1;
+                }
+            }
+            '?' => {
+                body.push_str("[^/]");
+                i += 1;
+            }
+            '[' => {
+                body.push('[');
+                i += 1;
+                if i < chars.len() && (chars[i] == '!' || chars[i] == '^') {
+                    body.push('^');
+                    i += 1;
+                }
+                while i < chars.len() && chars[i] != ']' {
+                    body.push(chars[i]);
+                    i += 1;
+                }
+                if i < chars.len() {
+                    body.push(']');
+                    i += 1;
+                }
+            }
+            c => {
+                if "\\.+()^$|{}".contains(c) {
+                    body.push('\\');
+                }
+                body.push(c);
+                i += 1;
+            }
+        }
+    }
+    // `**` needs `.` to cross the combined text's `name\npath` separator
+    // too, so turn on PCRE2's dotall ("(?s)") whenever it's used; a plain
+    // `*`/`?`/class never needs it since those are built from negated
+    // character classes, which already match newlines.
+    let mut out = String::with_capacity(body.len() + 8);
+    if saw_double_star {
+        out.push_str("(?s)");
+    }
+    out.push('^');
+    out.push('(');
+    out.push_str(&body);
+    out.push(')');
+    out.push('$');
+    out
+}
+
+fn build_pattern_for_glob(glob: &str, mods: &Vec<String>) -> String {
+    let mut pat = String::new();
+    if mods.iter().any(|m| m.eq_ignore_ascii_case("i") || m.eq_ignore_ascii_case("icase") || m.eq_ignore_ascii_case("ignorecase")) {
+        pat.push_str("(?i)");
+    }
+    pat.push_str(&glob_to_regex(glob));
+    pat
+}
+
+/// The field a bare (unscoped) `Node::Structural` pattern should be scoped
+/// to: like `Node::Glob`, a pattern with no literal `/` can only ever
+/// describe a filename, so it scopes to `name`; once it contains `/` it
+/// needs to match across the whole combined text.
+fn structural_default_field(pat: &str) -> Option<Arc<str>> {
+    if pat.contains('/') {
+        None
+    } else {
+        Some(intern("name"))
+    }
+}
+
+/// Lower a structural pattern like `foo_$x_bar` into an anchored PCRE2
+/// regex with one lazy `(.*?)` capture group per `$name` occurrence (true
+/// backreferences aren't available, so repeated uses of the same
+/// metavariable are instead checked for byte-equality afterwards, in
+/// `structural_bindings`), plus the metavariable name for each capture
+/// group in order (group 1's name is the first entry, etc). The whole
+/// pattern is anchored (`^...$`) so a metavariable's lazy group is forced
+/// to actually bind the run of bytes between its neighboring literals,
+/// rather than degenerating to an empty match anywhere in the text; dotall
+/// is enabled when the pattern contains a literal `/`, since that implies
+/// it's scoped to the whole combined `name + "\n" + path` text (see
+/// `structural_default_field`) and a metavariable may need to cross that
+/// embedded newline.
+pub(crate) fn compile_structural(pattern: &str) -> (String, Vec<String>) {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut body = String::with_capacity(chars.len() * 2);
+    let mut group_vars = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '$' && i + 1 < chars.len() && (chars[i + 1].is_alphabetic() || chars[i + 1] == '_') {
+            let start = i + 1;
+            // The name is its leading letter/`_` plus any letters and
+            // digits after it; a later `_` ends it, so `foo_$x_bar` binds
+            // `$x` between the literals `foo_` and `_bar`.
+            let mut end = start + 1;
+            while end < chars.len() && chars[end].is_alphanumeric() {
+                end += 1;
+            }
+            group_vars.push(chars[start..end].iter().collect());
+            body.push_str("(.*?)");
+            i = end;
+        } else {
+            let c = chars[i];
+            if "\\.+*?()^$|{}[]".contains(c) {
+                body.push('\\');
+            }
+            body.push(c);
+            i += 1;
+        }
+    }
+    let mut out = String::with_capacity(body.len() + 6);
+    if pattern.contains('/') {
+        out.push_str("(?s)");
+    }
+    out.push('^');
+    out.push_str(&body);
+    out.push('$');
+    (out, group_vars)
+}
+
+fn build_pattern_for_structural(body: &str, icase: bool) -> String {
+    if icase {
+        format!("(?i){}", body)
+    } else {
+        body.to_string()
+    }
+}
+
 fn build_pattern_for_regex(s: &str, mods: &Vec<String>) -> String {
     let mut pat = String::new();
     if mods.iter().any(|m| m.eq_ignore_ascii_case("i") || m.eq_ignore_ascii_case("icase") || m.eq_ignore_ascii_case("ignorecase")) {
@@ -494,7 +1119,7 @@ mod tests {
         // leaf literal (use Regex node to validate capture metadata)
         let pool2 = PatternPool::new();
         let pat = pool2.acquire_pcre2("foo").unwrap();
-        let comp = CompiledNode::Leaf { pat, negated: false, field: None, mods: vec![] };
+        let comp = CompiledNode::Leaf { pat, pattern: intern("foo"), negated: false, field: None, mods: vec![] };
         let metas = qm.captures_meta(&comp, b"this is foo");
         // Some backends may not produce capture groups for simple literals.
         // If metadata is present, assert the captured bytes match "foo".
@@ -509,13 +1134,13 @@ mod tests {
         // extension field should produce name+extension meta entries
         let pool3 = PatternPool::new();
         let pat2 = pool3.acquire_pcre2("txt").unwrap();
-        let comp_ext = CompiledNode::Leaf { pat: pat2, negated: false, field: Some("extension".to_string()), mods: vec![] };
+        let comp_ext = CompiledNode::Leaf { pat: pat2, pattern: intern("txt"), negated: false, field: Some(intern("extension")), mods: vec![] };
         let metas_ext = qm.captures_meta(&comp_ext, b"file.txt\n/some/path/file.txt");
         // expect two metas: name with ranges, extension with empty ranges
         assert_eq!(metas_ext.len(), 2);
-        assert_eq!(metas_ext[0].field, Some("name".to_string()));
+        assert_eq!(metas_ext[0].field.as_deref(), Some("name"));
         assert!(!metas_ext[0].ranges.is_empty());
-        assert_eq!(metas_ext[1].field, Some("extension".to_string()));
+        assert_eq!(metas_ext[1].field.as_deref(), Some("extension"));
         assert!(metas_ext[1].ranges.is_empty());
 
         // compare matches produce a field entry with no ranges
@@ -523,7 +1148,7 @@ mod tests {
         let comp2 = qm.compile(&node2).unwrap();
         let metas2 = qm.captures_meta(&comp2, b"100");
         assert_eq!(metas2.len(), 1);
-        assert_eq!(metas2[0].field, Some("size".to_string()));
+        assert_eq!(metas2[0].field.as_deref(), Some("size"));
         assert!(metas2[0].ranges.is_empty());
 
         // function contains => ranges
@@ -589,4 +1214,382 @@ mod tests {
         let expected_e = name.len();
         assert_eq!((s,e), (expected_s, expected_e));
     }
+
+    #[test]
+    fn matcher_field_scoping_is_schema_generic() {
+        // extension was previously the only hardcoded field; name/path/dir/stem
+        // used to fall through to whole-text matching instead of being scoped.
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let combined = b"file.txt\n/some/path/file.txt";
+
+        let name_node = Node::Field("name".to_string(), "file".to_string());
+        let compiled = qm.compile(&name_node).unwrap();
+        assert!(qm.is_match(&compiled, combined));
+
+        let dir_node = Node::Field("dir".to_string(), "path".to_string());
+        let compiled = qm.compile(&dir_node).unwrap();
+        assert!(qm.is_match(&compiled, combined));
+        // "path" does not appear in the name, so a dir-scoped match must not
+        // be satisfied by matching the whole combined text instead.
+        let name_only_node = Node::Field("name".to_string(), "path".to_string());
+        let compiled = qm.compile(&name_only_node).unwrap();
+        assert!(!qm.is_match(&compiled, combined));
+
+        let stem_node = Node::Field("stem".to_string(), "^file$".to_string());
+        let compiled = qm.compile(&stem_node).unwrap();
+        assert!(qm.is_match(&compiled, combined));
+    }
+
+    #[test]
+    fn matcher_with_schema_allows_custom_fields() {
+        let pool = PatternPool::new();
+        let mut schema = FieldSchema::new();
+        schema.register("size_col", |text| {
+            let nl = text.iter().position(|&b| b == b'\n')?;
+            Some((nl + 1, text.len()))
+        });
+        let qm = QueryMatcher::with_schema(pool, schema);
+        let node = Node::Field("size_col".to_string(), "path".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"file.txt\n/some/path/file.txt"));
+    }
+
+    #[test]
+    fn matcher_or_chain_of_literals_lowers_to_multi_literal() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Or(
+            Box::new(Node::Or(
+                Box::new(Node::Word("foo".to_string())),
+                Box::new(Node::Word("bar".to_string())),
+            )),
+            Box::new(Node::Word("baz".to_string())),
+        );
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::MultiLiteral { .. }));
+        assert!(qm.is_match(&compiled, b"xxbarxx"));
+        assert!(qm.is_match(&compiled, b"foo"));
+        assert!(!qm.is_match(&compiled, b"qux"));
+        let caps = qm.captures(&compiled, b"xxbazxx");
+        assert_eq!(caps, vec![(2, 5)]);
+    }
+
+    #[test]
+    fn matcher_or_chain_with_mixed_field_stays_as_or_tree() {
+        // Mixing a plain word with a field-scoped term doesn't share a
+        // single field, so this must not be lowered to MultiLiteral.
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Or(
+            Box::new(Node::Word("foo".to_string())),
+            Box::new(Node::Field("extension".to_string(), "txt".to_string())),
+        );
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::Or(..)));
+    }
+
+    #[test]
+    fn matcher_or_chain_respects_icase_modifier() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Modified(
+            Box::new(Node::Or(
+                Box::new(Node::Word("Foo".to_string())),
+                Box::new(Node::Word("Bar".to_string())),
+            )),
+            vec!["icase".to_string()],
+        );
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::MultiLiteral { .. }));
+        assert!(qm.is_match(&compiled, b"this has foo in it"));
+    }
+
+    #[test]
+    fn matcher_or_chain_field_scoped_literals() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Or(
+            Box::new(Node::Field("name".to_string(), "report".to_string())),
+            Box::new(Node::Field("name".to_string(), "invoice".to_string())),
+        );
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::MultiLiteral { .. }));
+        let combined = b"invoice.pdf\n/docs/invoice.pdf";
+        assert!(qm.is_match(&compiled, combined));
+        // neither literal appears in the path-only portion
+        let combined2 = b"summary.pdf\n/docs/invoice/summary.pdf";
+        assert!(!qm.is_match(&compiled, combined2));
+    }
+
+    #[test]
+    fn compare_size_field_understands_unit_suffixes() {
+        use crate::query::parser_rs::CompareOp;
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Compare("size".to_string(), CompareOp::Greater, "1mb".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"2000000"));
+        assert!(!qm.is_match(&compiled, b"500000"));
+    }
+
+    #[test]
+    fn range_size_field_understands_unit_suffixes() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Range(
+            "size".to_string(),
+            Bound::Inclusive("1k".to_string()),
+            Bound::Inclusive("1mb".to_string()),
+        );
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"2048"));
+        assert!(!qm.is_match(&compiled, b"10"));
+        assert!(!qm.is_match(&compiled, b"2000000"));
+    }
+
+    #[test]
+    fn compare_modified_field_understands_dates() {
+        use crate::query::parser_rs::CompareOp;
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Compare("modified".to_string(), CompareOp::Greater, "2024-01-01".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"2024-06-15"));
+        assert!(!qm.is_match(&compiled, b"2023-01-01"));
+    }
+
+    #[test]
+    fn compare_mtime_accepts_iso_dates_like_modified() {
+        use crate::query::parser_rs::CompareOp;
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Compare("mtime".to_string(), CompareOp::Greater, "2024-01-01".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"2024-06-15"));
+        assert!(!qm.is_match(&compiled, b"2023-01-01"));
+    }
+
+    #[test]
+    fn compare_mtime_accepts_relative_duration_as_age() {
+        use crate::query::parser_rs::CompareOp;
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let now = crate::query::value_parse::now_epoch_seconds().unwrap();
+
+        // "mtime:<7d" means "modified within the last 7 days", i.e. age < 7d.
+        let node = Node::Compare("mtime".to_string(), CompareOp::Smaller, "7d".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        let one_day_ago = format!("{}", now - 86_400.0);
+        let thirty_days_ago = format!("{}", now - 30.0 * 86_400.0);
+        let one_hour_ago = format!("{}", now - 3_600.0);
+        assert!(qm.is_match(&compiled, one_day_ago.as_bytes()));
+        assert!(!qm.is_match(&compiled, thirty_days_ago.as_bytes()));
+
+        // "mtime:>3h" means "older than 3 hours".
+        let node2 = Node::Compare("mtime".to_string(), CompareOp::Greater, "3h".to_string());
+        let compiled2 = qm.compile(&node2).unwrap();
+        assert!(qm.is_match(&compiled2, thirty_days_ago.as_bytes()));
+        assert!(!qm.is_match(&compiled2, one_hour_ago.as_bytes()));
+    }
+
+    #[test]
+    fn compare_size_field_does_not_misread_a_non_size_operand_as_a_duration() {
+        use crate::query::parser_rs::CompareOp;
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        // "size" isn't a time field, so an operand that merely happens to
+        // also parse as a duration (like "7d") must not be reinterpreted as
+        // an age against the candidate's byte size -- it should fall
+        // through to the plain lexicographic comparison instead.
+        let node = Node::Compare("size".to_string(), CompareOp::Smaller, "7d".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"100"));
+    }
+
+    #[test]
+    fn compare_size_accepts_iec_binary_suffix() {
+        use crate::query::parser_rs::CompareOp;
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Compare("size".to_string(), CompareOp::Greater, "1GiB".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"2000000000"));
+        assert!(!qm.is_match(&compiled, b"500000000"));
+    }
+
+    #[test]
+    fn compare_unscoped_field_still_falls_back_to_plain_numbers() {
+        use crate::query::parser_rs::CompareOp;
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Compare("count".to_string(), CompareOp::Greater, "10".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"20"));
+        assert!(!qm.is_match(&compiled, b"5"));
+    }
+
+    #[test]
+    fn type_alias_expands_to_extension_multi_literal() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Field("type".to_string(), "cpp".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::MultiLiteral { .. }));
+        assert!(qm.is_match(&compiled, b"main.cpp\n/src/main.cpp"));
+        assert!(qm.is_match(&compiled, b"header.hpp\n/src/header.hpp"));
+        assert!(!qm.is_match(&compiled, b"readme.md\n/readme.md"));
+    }
+
+    #[test]
+    fn type_alias_with_single_extension_compiles_to_leaf() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Field("type".to_string(), "rust".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::Leaf { .. }));
+        assert!(qm.is_match(&compiled, b"main.rs\n/src/main.rs"));
+    }
+
+    #[test]
+    fn unknown_type_alias_falls_back_to_literal_extension() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Field("type".to_string(), "zzz".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"file.zzz\n/some/file.zzz"));
+    }
+
+    #[test]
+    fn add_type_registers_a_custom_alias() {
+        let pool = PatternPool::new();
+        let mut qm = QueryMatcher::new(pool);
+        qm.add_type("proto", &["proto"]);
+        let node = Node::Field("type".to_string(), "proto".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"service.proto\n/api/service.proto"));
+    }
+
+    #[test]
+    fn bare_glob_star_does_not_cross_path_separator() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Glob("*.tmp".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::Leaf { .. }));
+        let combined = b"scratch.tmp\n/var/tmp/scratch.tmp";
+        assert!(qm.is_match(&compiled, combined));
+        let caps = qm.captures(&compiled, combined);
+        // group 0 (the whole match) and the glob's wrapping group both
+        // span the full name.
+        assert_eq!(caps, vec![(0, 11), (0, 11)]);
+        // the directory segment "tmp" alone must not satisfy a *.tmp glob
+        assert!(!qm.is_match(&compiled, b"scratch\n/var/tmp.tmp/scratch"));
+    }
+
+    #[test]
+    fn double_star_glob_crosses_path_separators() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Glob("**/*.rs".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        let combined = b"main.rs\n/src/nested/main.rs";
+        assert!(qm.is_match(&compiled, combined));
+        // also matches with zero intervening directories
+        let combined2 = b"main.rs\nmain.rs";
+        assert!(qm.is_match(&compiled, combined2));
+        assert!(!qm.is_match(&compiled, b"main.py\n/src/main.py"));
+    }
+
+    #[test]
+    fn glob_question_mark_and_char_class() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Glob("file?.[tc]xt".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"file1.txt\n/docs/file1.txt"));
+        assert!(qm.is_match(&compiled, b"fileA.cxt\n/docs/fileA.cxt"));
+        assert!(!qm.is_match(&compiled, b"file12.txt\n/docs/file12.txt"));
+    }
+
+    #[test]
+    fn replace_expands_positional_groups() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Regex(r"(\d{4})-(\d{2})".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        let out = qm.replace(&compiled, b"2024-06", "${2}/${1}");
+        assert_eq!(out, b"06/2024");
+        // $1 without braces works too, and $$ is a literal dollar
+        let out2 = qm.replace(&compiled, b"2024-06", "$$$1");
+        assert_eq!(out2, b"$2024");
+    }
+
+    #[test]
+    fn replace_expands_named_groups() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Regex(r"(?P<year>\d{4})-(?P<month>\d{2})".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        let out = qm.replace(&compiled, b"2024-06", "${month}/${year}");
+        assert_eq!(out, b"06/2024");
+    }
+
+    #[test]
+    fn replace_unknown_reference_expands_to_empty() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Regex(r"(\d+)".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        let out = qm.replace(&compiled, b"42", "[${nonexistent}]$5");
+        assert_eq!(out, b"[]");
+    }
+
+    #[test]
+    fn structural_pattern_matches_and_binds_metavariables() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        // A bare structural pattern (no `/`) scopes to the `name` field,
+        // like a bare glob does.
+        let node = Node::Structural("foo_$x_bar".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        let combined = b"foo_hello_bar\n/some/path/foo_hello_bar";
+        assert!(qm.is_match(&compiled, combined));
+        assert!(!qm.is_match(&compiled, b"foo_bar\n/some/path/foo_bar"));
+        let caps = qm.captures(&compiled, combined);
+        assert_eq!(caps, vec![(4, 9)]);
+    }
+
+    #[test]
+    fn structural_pattern_rejects_mismatched_repeated_metavariable() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        // `$x` used twice must bind to the same bytes both times.
+        let node = Node::Structural("$x_vs_$x".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(qm.is_match(&compiled, b"foo_vs_foo\n/p/foo_vs_foo"));
+        assert!(!qm.is_match(&compiled, b"foo_vs_bar\n/p/foo_vs_bar"));
+    }
+
+    #[test]
+    fn structural_pattern_field_scoped() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Field("name".to_string(), "report_$year_final".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::Structural { field: Some(_), .. }));
+        assert!(qm.is_match(&compiled, b"report_2024_final\n/docs/report_2024_final"));
+        assert!(!qm.is_match(&compiled, b"summary_2024_final\n/docs/summary_2024_final"));
+    }
+
+    #[test]
+    fn field_scoped_glob_matches_like_extension_wildcard() {
+        let pool = PatternPool::new();
+        let qm = QueryMatcher::new(pool);
+        let node = Node::Field("name".to_string(), "*.log".to_string());
+        let compiled = qm.compile(&node).unwrap();
+        assert!(matches!(compiled, CompiledNode::Leaf { field: Some(_), .. }));
+        assert!(qm.is_match(&compiled, b"server.log\n/var/log/server.log"));
+        assert!(!qm.is_match(&compiled, b"server.txt\n/var/log/server.txt"));
+    }
 }
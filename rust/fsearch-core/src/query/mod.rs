@@ -1,7 +1,25 @@
 pub mod lexer;
 pub mod parser_rs;
+#[cfg(feature = "std")]
 pub mod matcher;
+#[cfg(feature = "std")]
+pub mod codec;
+pub mod field_schema;
+pub mod aho_corasick;
+pub mod value_parse;
+#[cfg(feature = "std")]
+pub mod multi;
+pub mod type_registry;
+pub mod prefilter;
 
-pub use lexer::Token;
+pub use lexer::{Span, Token};
 pub use parser_rs::{Node, Parser};
+#[cfg(feature = "std")]
 pub use matcher::QueryMatcher;
+#[cfg(feature = "std")]
+pub use codec::CodecError;
+pub use field_schema::FieldSchema;
+#[cfg(feature = "std")]
+pub use multi::CompiledSet;
+pub use type_registry::TypeRegistry;
+pub use prefilter::LiteralPrefilter;
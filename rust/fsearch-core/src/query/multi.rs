@@ -0,0 +1,228 @@
+//! Batch matching of many compiled queries against one file in a single
+//! pass, for saved-filter workloads (highlight rules, smart folders) that
+//! otherwise call [`QueryMatcher::is_match`] once per query per file.
+//!
+//! [`CompiledSet`] unions every query's unscoped, non-negated literal/regex
+//! leaves into one [`regex::RegexSet`] so the text is scanned once no
+//! matter how many queries are loaded; each query's `Compare`/`Range`/
+//! `Function` nodes and field-scoped leaves (e.g. `extension:`) aren't
+//! text-pattern leaves in that sense and are still evaluated per query,
+//! same as today.
+
+use crate::query::matcher::{CompiledNode, MatchMeta, QueryMatcher};
+use crate::query::Node;
+use smallvec::SmallVec;
+
+struct CompiledQuery {
+    node: CompiledNode,
+    /// One entry per `Leaf` node encountered during a left-to-right,
+    /// depth-first walk of `node` (see `collect_leaf_patterns`): `Some(i)`
+    /// if that leaf joined the shared `RegexSet` at index `i`, `None` if it
+    /// has to be evaluated on its own (negated, field-scoped, or a pattern
+    /// the `regex` crate can't parse).
+    leaf_regex_ids: Vec<Option<usize>>,
+}
+
+/// A batch of compiled queries matched against text in a single pass. Build
+/// with [`CompiledSet::compile`], then call [`CompiledSet::matches`] (or
+/// [`CompiledSet::matches_meta`]) per candidate file.
+pub struct CompiledSet {
+    matcher: QueryMatcher,
+    queries: Vec<CompiledQuery>,
+    regex_set: regex::RegexSet,
+}
+
+/// Walk `node`'s leaves in a fixed, repeatable order, recording which ones
+/// are eligible to join the shared `RegexSet` (unscoped, non-negated, and
+/// parseable by the `regex` crate) and appending their pattern source to
+/// `patterns`. Must visit leaves in the same order `eval_node` does, since
+/// `eval_node` re-derives the same sequence to line its cursor up with
+/// `leaf_regex_ids`.
+fn collect_leaf_patterns(node: &CompiledNode, patterns: &mut Vec<String>, leaf_ids: &mut Vec<Option<usize>>) {
+    match node {
+        CompiledNode::Leaf { pattern, negated, field, .. } => {
+            if !*negated && field.is_none() && regex::Regex::new(pattern).is_ok() {
+                leaf_ids.push(Some(patterns.len()));
+                patterns.push(pattern.to_string());
+            } else {
+                leaf_ids.push(None);
+            }
+        }
+        CompiledNode::Not(inner) => collect_leaf_patterns(inner, patterns, leaf_ids),
+        CompiledNode::And(a, b) | CompiledNode::Or(a, b) => {
+            collect_leaf_patterns(a, patterns, leaf_ids);
+            collect_leaf_patterns(b, patterns, leaf_ids);
+        }
+        CompiledNode::MultiLiteral { .. }
+        | CompiledNode::Structural { .. }
+        | CompiledNode::Compare { .. }
+        | CompiledNode::Range { .. }
+        | CompiledNode::Function { .. } => {}
+    }
+}
+
+/// Evaluate `node` against `text`, using `set_matches` for any leaf that
+/// joined the shared `RegexSet` and falling back to `QueryMatcher::is_match`
+/// on the subtree for everything else (field-scoped/negated leaves,
+/// `MultiLiteral`, `Structural`, `Compare`, `Range`, `Function`).
+fn eval_node(
+    node: &CompiledNode,
+    leaf_ids: &[Option<usize>],
+    cursor: &mut usize,
+    set_matches: &regex::SetMatches,
+    matcher: &QueryMatcher,
+    text: &[u8],
+) -> bool {
+    match node {
+        CompiledNode::Leaf { .. } => {
+            let id = leaf_ids[*cursor];
+            *cursor += 1;
+            match id {
+                Some(idx) => set_matches.matched(idx),
+                None => matcher.is_match(node, text),
+            }
+        }
+        CompiledNode::Not(inner) => !eval_node(inner, leaf_ids, cursor, set_matches, matcher, text),
+        CompiledNode::And(a, b) => {
+            let a_ok = eval_node(a, leaf_ids, cursor, set_matches, matcher, text);
+            let b_ok = eval_node(b, leaf_ids, cursor, set_matches, matcher, text);
+            a_ok && b_ok
+        }
+        CompiledNode::Or(a, b) => {
+            let a_ok = eval_node(a, leaf_ids, cursor, set_matches, matcher, text);
+            let b_ok = eval_node(b, leaf_ids, cursor, set_matches, matcher, text);
+            a_ok || b_ok
+        }
+        CompiledNode::MultiLiteral { .. }
+        | CompiledNode::Structural { .. }
+        | CompiledNode::Compare { .. }
+        | CompiledNode::Range { .. }
+        | CompiledNode::Function { .. } => matcher.is_match(node, text),
+    }
+}
+
+impl CompiledSet {
+    /// Compile every `Node` in `nodes` with `matcher` and union their
+    /// eligible leaves into one `RegexSet`.
+    pub fn compile(matcher: &QueryMatcher, nodes: &[Node]) -> Result<Self, pcre2::Error> {
+        let mut patterns: Vec<String> = Vec::new();
+        let mut queries = Vec::with_capacity(nodes.len());
+        for n in nodes {
+            let node = matcher.compile(n)?;
+            let mut leaf_regex_ids = Vec::new();
+            collect_leaf_patterns(&node, &mut patterns, &mut leaf_regex_ids);
+            queries.push(CompiledQuery { node, leaf_regex_ids });
+        }
+        let regex_set = match regex::RegexSet::new(&patterns) {
+            Ok(set) => set,
+            Err(_) => {
+                // The union itself failed to build (e.g. a resource limit);
+                // degrade to evaluating every leaf individually rather than
+                // failing the whole batch.
+                for q in queries.iter_mut() {
+                    for id in q.leaf_regex_ids.iter_mut() {
+                        *id = None;
+                    }
+                }
+                regex::RegexSet::empty()
+            }
+        };
+        Ok(CompiledSet { matcher: matcher.clone(), queries, regex_set })
+    }
+
+    /// Indices (into the slice passed to `compile`) of every query that
+    /// matches `text`, computed with a single scan of `text` for the
+    /// shared literal/regex union.
+    pub fn matches(&self, text: &[u8]) -> SmallVec<[usize; 8]> {
+        let text_str = String::from_utf8_lossy(text);
+        let set_matches = self.regex_set.matches(text_str.as_ref());
+        self.queries
+            .iter()
+            .enumerate()
+            .filter(|(_, q)| {
+                let mut cursor = 0;
+                eval_node(&q.node, &q.leaf_regex_ids, &mut cursor, &set_matches, &self.matcher, text)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Capture metadata for every query that matches `text`, as
+    /// `(query_index, metas)` pairs in the same index space as `matches`.
+    pub fn matches_meta(&self, text: &[u8]) -> SmallVec<[(usize, Vec<MatchMeta>); 8]> {
+        self.matches(text)
+            .into_iter()
+            .map(|i| (i, self.matcher.captures_meta(&self.queries[i].node, text)))
+            .collect()
+    }
+
+    /// The number of queries in this set.
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcre2_pool::PatternPool;
+    use crate::query::parser_rs::Parser;
+
+    fn node(src: &str) -> Node {
+        Parser::new(src).parse().unwrap()
+    }
+
+    #[test]
+    fn matches_reports_every_hit_in_one_pass() {
+        let matcher = QueryMatcher::new(PatternPool::new());
+        let nodes = vec![node("foo"), node("bar"), node("qux")];
+        let set = CompiledSet::compile(&matcher, &nodes).unwrap();
+
+        let hits = set.matches(b"this has foo and bar in it");
+        assert_eq!(hits.as_slice(), &[0usize, 1usize]);
+    }
+
+    #[test]
+    fn matches_handles_and_or_not_trees() {
+        let matcher = QueryMatcher::new(PatternPool::new());
+        let nodes = vec![node("foo AND bar"), node("foo AND NOT bar"), node("foo OR qux")];
+        let set = CompiledSet::compile(&matcher, &nodes).unwrap();
+
+        let hits = set.matches(b"foo bar");
+        assert_eq!(hits.as_slice(), &[0usize, 2usize]);
+    }
+
+    #[test]
+    fn matches_falls_back_for_field_scoped_and_compare_leaves() {
+        let matcher = QueryMatcher::new(PatternPool::new());
+        let nodes = vec![
+            Node::Field("extension".to_string(), "txt".to_string()),
+            Node::Compare(
+                "size".to_string(),
+                crate::query::parser_rs::CompareOp::Greater,
+                "100".to_string(),
+            ),
+        ];
+        let set = CompiledSet::compile(&matcher, &nodes).unwrap();
+
+        assert!(set.matches(b"file.txt\n/some/file.txt").contains(&0));
+        assert!(set.matches(b"200").contains(&1));
+        assert!(!set.matches(b"50").contains(&1));
+    }
+
+    #[test]
+    fn matches_meta_returns_ranges_for_matching_queries_only() {
+        let matcher = QueryMatcher::new(PatternPool::new());
+        let nodes = vec![node("foo"), node("qux")];
+        let set = CompiledSet::compile(&matcher, &nodes).unwrap();
+
+        let metas = set.matches_meta(b"has foo in it");
+        assert_eq!(metas.len(), 1);
+        assert_eq!(metas[0].0, 0);
+        assert!(!metas[0].1.is_empty());
+    }
+}
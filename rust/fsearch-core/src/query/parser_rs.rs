@@ -0,0 +1,390 @@
+use crate::query::lexer::{Lexer, Span, Token};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Word(String),
+    Field(String, String),
+    Compare(String, CompareOp, String),
+    Range(String, Bound, Bound),
+    Modified(Box<Node>, Vec<String>),
+    Function(String, Vec<String>),
+    Regex(String),
+    Glob(String),
+    /// A structural pattern containing `$name` metavariables (e.g.
+    /// `foo_$x_bar`) that bind to arbitrary runs of bytes; repeated uses of
+    /// the same metavariable must bind identical bytes.
+    Structural(String),
+    Group(Box<Node>),
+}
+
+/// Whether `s` contains glob metacharacters (`*`, `?`, or a `[...]` class)
+/// and should be parsed as a [`Node::Glob`] rather than a literal word.
+pub(crate) fn is_glob_pattern(s: &str) -> bool {
+    if s.contains('*') || s.contains('?') {
+        return true;
+    }
+    if let Some(start) = s.find('[') {
+        return s[start..].contains(']');
+    }
+    false
+}
+
+/// Whether `s` contains a `$name` metavariable reference and should be
+/// parsed as a [`Node::Structural`] rather than a literal word.
+pub(crate) fn is_structural_pattern(s: &str) -> bool {
+    let chars: Vec<char> = s.chars().collect();
+    for i in 0..chars.len() {
+        if chars[i] == '$' && i + 1 < chars.len() && (chars[i + 1].is_alphabetic() || chars[i + 1] == '_') {
+            return true;
+        }
+    }
+    false
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CompareOp {
+    Eq,
+    Contains,
+    Smaller,
+    SmallerEq,
+    Greater,
+    GreaterEq,
+}
+
+/// One side of a `Range` node. `Unbounded` lets a range be open on one end,
+/// e.g. `size:..1000` (anything up to and including 1000).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Bound {
+    Inclusive(String),
+    Exclusive(String),
+    Unbounded,
+}
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    cur: Token,
+    /// Byte-offset span of `cur` in the original query, so a future error
+    /// reporter can point at exactly which `Field`/`Word` a problem came
+    /// from.
+    cur_span: Span,
+    cur_text: Option<String>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        let mut lx = Lexer::new(input);
+        let (cur, cur_span, cur_text) = match lx.next_token() {
+            Ok((tok, span, text)) => (tok, span, text),
+            Err(_) => (Token::Eos, Span { start: 0, end: 0 }, None),
+        };
+        Parser { lexer: lx, cur, cur_span, cur_text }
+    }
+
+    /// Advance to the next token. A lexing error (unterminated quote,
+    /// trailing backslash, ...) is treated the same way running out of
+    /// input is: the parser just sees `Eos` and stops, rather than
+    /// threading a second error type through `parse`'s `Option<Node>`
+    /// surface.
+    fn advance(&mut self) {
+        match self.lexer.next_token() {
+            Ok((tok, span, text)) => {
+                self.cur = tok;
+                self.cur_span = span;
+                self.cur_text = text;
+            }
+            Err(_) => {
+                self.cur = Token::Eos;
+                self.cur_text = None;
+            }
+        }
+    }
+
+    /// The byte-offset span of the token the parser is currently looking
+    /// at, into the original query string.
+    pub fn cur_span(&self) -> Span {
+        self.cur_span
+    }
+
+    pub fn parse(&mut self) -> Option<Node> {
+        self.parse_or()
+    }
+
+    fn parse_or(&mut self) -> Option<Node> {
+        let mut node = self.parse_and()?;
+        while let Token::Or = self.cur {
+            self.advance();
+            let right = self.parse_and()?;
+            node = Node::Or(Box::new(node), Box::new(right));
+        }
+        Some(node)
+    }
+
+    fn parse_and(&mut self) -> Option<Node> {
+        let mut node = self.parse_unary()?;
+        while let Token::And = self.cur {
+            self.advance();
+            let right = self.parse_unary()?;
+            node = Node::And(Box::new(node), Box::new(right));
+        }
+        Some(node)
+    }
+
+    fn parse_unary(&mut self) -> Option<Node> {
+        match &self.cur {
+            Token::Not => {
+                self.advance();
+                let inner = self.parse_unary()?;
+                Some(Node::Not(Box::new(inner)))
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Option<Node> {
+        match &self.cur {
+            Token::BracketOpen => {
+                self.advance();
+                let inner = self.parse()?;
+                if let Token::BracketClose = self.cur {
+                    self.advance();
+                }
+                Some(Node::Group(Box::new(inner)))
+            }
+            Token::Field(name) => {
+                let field_name = name.clone();
+                self.advance();
+                match &self.cur {
+                    Token::Word(w) => {
+                        let term = w.clone();
+                        if let Some(node) = self.try_parse_range(&field_name, &term) {
+                            self.advance();
+                            return Some(node);
+                        }
+                        self.advance();
+                        Some(Node::Field(field_name, term))
+                    }
+                    // `field:<op>value` -- e.g. `size:>10M`, `mtime:<7d` --
+                    // the colon has already been consumed into `Token::Field`,
+                    // so the operator is sitting directly in `self.cur`
+                    // rather than in the lexer's peek buffer the way it is
+                    // for the non-colon form below (see `parse_compare`).
+                    Token::Smaller => self.parse_compare_value(field_name, CompareOp::Smaller),
+                    Token::SmallerEq => self.parse_compare_value(field_name, CompareOp::SmallerEq),
+                    Token::Greater => self.parse_compare_value(field_name, CompareOp::Greater),
+                    Token::GreaterEq => self.parse_compare_value(field_name, CompareOp::GreaterEq),
+                    Token::Equal => self.parse_compare_value(field_name, CompareOp::Eq),
+                    _ => None,
+                }
+            }
+            Token::FieldEmpty(name) => {
+                let field_name = name.clone();
+                self.advance();
+                Some(Node::Field(field_name, String::new()))
+            }
+            Token::Word(name) => {
+                let name_clone = name.clone();
+                let next_tok = match self.lexer.peek_token() {
+                    Ok((tok, _span, _text)) => tok,
+                    Err(_) => Token::Eos,
+                };
+                match next_tok {
+                    Token::Smaller => self.parse_compare(name_clone, CompareOp::Smaller),
+                    Token::SmallerEq => self.parse_compare(name_clone, CompareOp::SmallerEq),
+                    Token::Greater => self.parse_compare(name_clone, CompareOp::Greater),
+                    Token::GreaterEq => self.parse_compare(name_clone, CompareOp::GreaterEq),
+                    Token::Equal => self.parse_compare(name_clone, CompareOp::Eq),
+                    Token::Contains => self.parse_compare(name_clone, CompareOp::Contains),
+                    _ => {
+                        let s = name_clone.clone();
+                        if s.len() >= 2 && s.starts_with('/') && s.ends_with('/') {
+                            let pat = s[1..s.len() - 1].to_string();
+                            self.advance();
+                            return Some(Node::Regex(pat));
+                        }
+                        if is_structural_pattern(&s) {
+                            self.advance();
+                            return Some(Node::Structural(s));
+                        }
+                        if is_glob_pattern(&s) {
+                            self.advance();
+                            return Some(Node::Glob(s));
+                        }
+                        self.advance();
+                        Some(Node::Word(s))
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_compare(&mut self, field: String, op: CompareOp) -> Option<Node> {
+        // `self.cur` is still the field name here; `self.advance()` consumes
+        // it and moves onto the operator token that was peeked in
+        // `parse_primary`, then `parse_compare_value` takes it from there.
+        self.advance();
+        self.parse_compare_value(field, op)
+    }
+
+    /// Consume the value following a comparison operator that's already
+    /// `self.cur`, producing `Node::Compare(field, op, value)`.
+    fn parse_compare_value(&mut self, field: String, op: CompareOp) -> Option<Node> {
+        self.advance();
+        if let Token::Word(v) = &self.cur {
+            let val = v.clone();
+            self.advance();
+            return Some(Node::Compare(field, op, val));
+        }
+        None
+    }
+
+    /// Recognize `field:low..high` range syntax inside a field term that the
+    /// lexer has already collected as a single `Word`. Either side may be
+    /// empty to leave that end of the range unbounded.
+    fn try_parse_range(&self, field: &str, term: &str) -> Option<Node> {
+        let _ = field;
+        let idx = term.find("..")?;
+        let (low, high) = (&term[..idx], &term[idx + 2..]);
+        let low_bound = if low.is_empty() { Bound::Unbounded } else { Bound::Inclusive(low.to_string()) };
+        let high_bound = if high.is_empty() { Bound::Unbounded } else { Bound::Inclusive(high.to_string()) };
+        Some(Node::Range(field.to_string(), low_bound, high_bound))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_basic_expression() {
+        let mut p = Parser::new("name:foo AND (bar OR baz)");
+        let ast = p.parse();
+        assert!(ast.is_some());
+        match ast.unwrap() {
+            Node::And(left, right) => {
+                match *left {
+                    Node::Field(ref n, ref v) => {
+                        assert_eq!(n, "name");
+                        assert_eq!(v, "foo");
+                    }
+                    _ => panic!("left not field"),
+                }
+                match *right {
+                    Node::Group(boxed) => match *boxed {
+                        Node::Or(a, b) => {
+                            match *a {
+                                Node::Word(ref w) => assert_eq!(w, "bar"),
+                                _ => panic!("expected bar"),
+                            }
+                            match *b {
+                                Node::Word(ref w) => assert_eq!(w, "baz"),
+                                _ => panic!("expected baz"),
+                            }
+                        }
+                        _ => panic!("expected OR inside group"),
+                    },
+                    _ => panic!("right not group"),
+                }
+            }
+            _ => panic!("expected AND at top"),
+        }
+    }
+
+    #[test]
+    fn parse_compare_without_colon() {
+        let mut p = Parser::new("size<1000");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Compare(ref field, ref op, ref val) => {
+                assert_eq!(field, "size");
+                assert_eq!(val, "1000");
+                assert_eq!(*op, CompareOp::Smaller);
+            }
+            _ => panic!("expected Compare node"),
+        }
+    }
+
+    #[test]
+    fn parse_regex_literal() {
+        let mut p = Parser::new("/ab[0-9]+/");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Regex(pat) => assert_eq!(pat, "ab[0-9]+"),
+            _ => panic!("expected regex node"),
+        }
+    }
+
+    #[test]
+    fn parse_bare_glob() {
+        let mut p = Parser::new("*.rs");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Glob(pat) => assert_eq!(pat, "*.rs"),
+            _ => panic!("expected Glob node"),
+        }
+    }
+
+    #[test]
+    fn parse_double_star_glob() {
+        let mut p = Parser::new("**/*.rs");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Glob(pat) => assert_eq!(pat, "**/*.rs"),
+            _ => panic!("expected Glob node"),
+        }
+    }
+
+    #[test]
+    fn parse_structural_pattern() {
+        let mut p = Parser::new("foo_$x_bar");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Structural(pat) => assert_eq!(pat, "foo_$x_bar"),
+            _ => panic!("expected Structural node"),
+        }
+    }
+
+    #[test]
+    fn parse_compare_with_colon() {
+        let mut p = Parser::new("size:>10M");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Compare(ref field, ref op, ref val) => {
+                assert_eq!(field, "size");
+                assert_eq!(val, "10M");
+                assert_eq!(*op, CompareOp::Greater);
+            }
+            _ => panic!("expected Compare node"),
+        }
+
+        let mut p = Parser::new("mtime:<7d");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Compare(ref field, ref op, ref val) => {
+                assert_eq!(field, "mtime");
+                assert_eq!(val, "7d");
+                assert_eq!(*op, CompareOp::Smaller);
+            }
+            _ => panic!("expected Compare node"),
+        }
+    }
+
+    #[test]
+    fn parse_field_range() {
+        let mut p = Parser::new("size:10..20");
+        let ast = p.parse();
+        match ast.unwrap() {
+            Node::Range(field, low, high) => {
+                assert_eq!(field, "size");
+                assert_eq!(low, Bound::Inclusive("10".to_string()));
+                assert_eq!(high, Bound::Inclusive("20".to_string()));
+            }
+            _ => panic!("expected Range node"),
+        }
+    }
+}
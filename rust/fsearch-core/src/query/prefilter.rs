@@ -0,0 +1,234 @@
+//! A cheap rejection stage built from the literal substrings a query's AST
+//! *requires* to be present, so `start_search` can skip PCRE2/`QueryMatcher`
+//! evaluation entirely for candidates that can't possibly match.
+//!
+//! [`LiteralPrefilter`] walks the parsed [`Node`] tree (before compilation)
+//! and collects two kinds of constraints: literals that must all be present
+//! (anything joined by `And`/`Group`) and "OR groups" of literals where at
+//! least one member must be present (a chain of literal leaves joined by
+//! `Or`). Anything it can't reduce to a plain literal (a regex, glob,
+//! structural pattern, `Compare`/`Range`/`Function` node, or anything under
+//! `Not`/a negated `Modified`) is simply left out of the constraints rather
+//! than guessed at, so the prefilter can only under-constrain, never
+//! over-constrain, a real match: every text `QueryMatcher::is_match` would
+//! accept also passes `LiteralPrefilter::could_match`, but not vice versa.
+//!
+//! Matching is case-insensitive regardless of the query's own case
+//! sensitivity, for the same reason: it only relaxes what counts as
+//! "present", which keeps the over-approximation sound.
+
+use crate::query::aho_corasick::AhoCorasick;
+use crate::query::parser_rs::Node;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+fn is_negating_mod(m: &str) -> bool {
+    m.eq_ignore_ascii_case("not") || m.eq_ignore_ascii_case("invert") || m.eq_ignore_ascii_case("neg")
+}
+
+fn is_plain_literal_field_term(term: &str) -> bool {
+    use crate::query::parser_rs::{is_glob_pattern, is_structural_pattern};
+    !term.is_empty()
+        && !is_glob_pattern(term)
+        && !is_structural_pattern(term)
+        && !(term.len() >= 2 && term.starts_with('/') && term.ends_with('/'))
+}
+
+/// If every leaf of the `Or`/`Group` chain rooted at `node` is a plain
+/// literal (directly, or wrapped in a non-negating `Modified`), return all
+/// of their literal strings flattened into one list. Returns `None` the
+/// moment any leaf isn't a plain literal, since a partial extraction would
+/// under-represent the group and could reject a text that actually matches.
+fn flatten_literal_chain(node: &Node) -> Option<Vec<String>> {
+    match node {
+        Node::Or(a, b) => {
+            let mut lits = flatten_literal_chain(a)?;
+            lits.extend(flatten_literal_chain(b)?);
+            Some(lits)
+        }
+        Node::Group(inner) => flatten_literal_chain(inner),
+        Node::Word(s) => Some(vec![s.clone()]),
+        Node::Field(name, term) if name != "type" && is_plain_literal_field_term(term) => Some(vec![term.clone()]),
+        Node::Modified(inner, mods) if !mods.iter().any(|m| is_negating_mod(m)) => match &**inner {
+            Node::Word(s) => Some(vec![s.clone()]),
+            Node::Field(name, term) if name != "type" && is_plain_literal_field_term(term) => Some(vec![term.clone()]),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Walk `node`, appending every literal that's unconditionally required
+/// (reachable only through `And`/`Group`) to `required`, and every `Or`
+/// chain of plain literals to `groups` as one alternative-group entry.
+fn collect(node: &Node, required: &mut Vec<String>, groups: &mut Vec<Vec<String>>) {
+    match node {
+        Node::And(a, b) => {
+            collect(a, required, groups);
+            collect(b, required, groups);
+        }
+        Node::Group(inner) => collect(inner, required, groups),
+        Node::Or(_, _) => {
+            if let Some(lits) = flatten_literal_chain(node) {
+                if !lits.is_empty() {
+                    groups.push(lits);
+                }
+            }
+        }
+        Node::Word(s) => required.push(s.clone()),
+        Node::Field(name, term) if name != "type" && is_plain_literal_field_term(term) => required.push(term.clone()),
+        Node::Modified(inner, mods) => {
+            if mods.iter().any(|m| is_negating_mod(m)) {
+                return;
+            }
+            match &**inner {
+                Node::Word(s) => required.push(s.clone()),
+                Node::Field(name, term) if name != "type" && is_plain_literal_field_term(term) => required.push(term.clone()),
+                Node::Or(_, _) => {
+                    if let Some(lits) = flatten_literal_chain(inner) {
+                        if !lits.is_empty() {
+                            groups.push(lits);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        // `Not`, `Compare`, `Range`, `Function`, `Regex`, `Glob`,
+        // `Structural`, `type:` aliases (expanded to `extension:` matchers
+        // at compile time, so the alias name itself needn't appear), and
+        // any `Field` with a non-literal term contribute no constraint: we
+        // simply don't know enough to require anything.
+        _ => {}
+    }
+}
+
+/// A fast rejection stage for a single query, built once per search and
+/// checked per candidate before the real `QueryMatcher` is invoked.
+pub struct LiteralPrefilter {
+    ac: AhoCorasick,
+    required_count: usize,
+    /// `(start, end)` index ranges into `ac`'s pattern list, one per `Or`
+    /// group: at least one pattern in each range must be present.
+    groups: Vec<(usize, usize)>,
+}
+
+impl LiteralPrefilter {
+    /// Build a prefilter from `node`. Returns `None` if the query's AST
+    /// yields no usable literal constraints at all (e.g. a bare regex or
+    /// `size:>100`), in which case callers should skip prefiltering and
+    /// fall straight through to the real matcher.
+    pub fn build(node: &Node) -> Option<Self> {
+        let mut required = Vec::new();
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        collect(node, &mut required, &mut groups);
+        if required.is_empty() && groups.is_empty() {
+            return None;
+        }
+
+        let mut patterns = required.clone();
+        let mut group_ranges = Vec::with_capacity(groups.len());
+        for g in &groups {
+            let start = patterns.len();
+            patterns.extend(g.iter().cloned());
+            group_ranges.push((start, patterns.len()));
+        }
+
+        Some(LiteralPrefilter {
+            ac: AhoCorasick::build(&patterns, true),
+            required_count: required.len(),
+            groups: group_ranges,
+        })
+    }
+
+    /// Whether `text` could possibly satisfy the query this prefilter was
+    /// built from. `false` means the real matcher would definitely reject
+    /// `text` too, so it can be skipped; `true` doesn't guarantee a match,
+    /// only that the real matcher still needs to be consulted.
+    pub fn could_match(&self, text: &[u8]) -> bool {
+        let mask = self.ac.match_mask(text);
+        if mask[..self.required_count].iter().any(|present| !present) {
+            return false;
+        }
+        for &(start, end) in &self.groups {
+            if !mask[start..end].iter().any(|present| *present) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parser_rs::Parser;
+
+    fn node(src: &str) -> Node {
+        Parser::new(src).parse().unwrap()
+    }
+
+    #[test]
+    fn no_constraints_for_pure_regex_or_compare() {
+        assert!(LiteralPrefilter::build(&node("/ab[0-9]+/")).is_none());
+        assert!(LiteralPrefilter::build(&node("size>100")).is_none());
+    }
+
+    #[test]
+    fn and_chain_requires_every_literal() {
+        let pf = LiteralPrefilter::build(&node("foo AND bar")).unwrap();
+        assert!(pf.could_match(b"has foo and bar"));
+        assert!(!pf.could_match(b"has only foo"));
+        assert!(!pf.could_match(b"has only bar"));
+    }
+
+    #[test]
+    fn or_chain_requires_at_least_one_member() {
+        let pf = LiteralPrefilter::build(&node("foo OR bar OR baz")).unwrap();
+        assert!(pf.could_match(b"xxbarxx"));
+        assert!(pf.could_match(b"xxbazxx"));
+        assert!(!pf.could_match(b"qux"));
+    }
+
+    #[test]
+    fn not_and_non_literal_subtrees_contribute_no_constraint() {
+        // "foo AND NOT bar" must not require "bar" to be absent via the
+        // literal prefilter (that's the real matcher's job) -- it should
+        // only require "foo".
+        let pf = LiteralPrefilter::build(&node("foo AND NOT bar")).unwrap();
+        assert!(pf.could_match(b"foo and bar both present"));
+        assert!(pf.could_match(b"just foo"));
+        assert!(!pf.could_match(b"just bar"));
+    }
+
+    #[test]
+    fn mixed_or_branch_with_non_literal_is_dropped_entirely() {
+        // One branch of the OR is a regex, so extracting just "baz" as a
+        // required-group member would wrongly reject texts that only match
+        // via the regex branch; the whole group (and here, the whole
+        // query) must yield no constraint at all.
+        assert!(LiteralPrefilter::build(&node("baz OR /ab[0-9]+/")).is_none());
+    }
+
+    #[test]
+    fn mixed_or_branch_inside_a_larger_and_only_drops_that_group() {
+        // The `(baz OR /ab[0-9]+/)` group can't contribute a constraint,
+        // but the `foo` the query AND's it with still must.
+        let pf = LiteralPrefilter::build(&node("foo AND (baz OR /ab[0-9]+/)")).unwrap();
+        assert!(pf.could_match(b"foo ab123"));
+        assert!(!pf.could_match(b"ab123 only, no foo"));
+    }
+
+    #[test]
+    fn is_case_insensitive() {
+        let pf = LiteralPrefilter::build(&node("FOO")).unwrap();
+        assert!(pf.could_match(b"has foo lowercase"));
+    }
+
+    #[test]
+    fn field_scoped_literal_is_required() {
+        let pf = LiteralPrefilter::build(&node("extension:rs")).unwrap();
+        assert!(pf.could_match(b"main.rs\n/src/main.rs"));
+        assert!(!pf.could_match(b"main.py\n/src/main.py"));
+    }
+}
@@ -0,0 +1,103 @@
+//! Built-in, user-extendable registry mapping a file-type alias (`rust`,
+//! `cpp`, `web`, ...) to the set of extensions it covers, mirroring
+//! ripgrep's `--type` definitions. `QueryMatcher::compile` expands
+//! `Node::Field("type", name)` into an `Or` of `extension:` matchers at
+//! compile time using this registry.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Name -> extension list. Seeded with a handful of common aliases;
+/// extend with `add` or `load_overrides`.
+#[derive(Clone)]
+pub struct TypeRegistry {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl TypeRegistry {
+    /// A registry seeded with a handful of common type aliases.
+    pub fn new() -> Self {
+        let mut reg = TypeRegistry { types: BTreeMap::new() };
+        reg.add("rust", &["rs"]);
+        reg.add("cpp", &["cc", "cpp", "cxx", "h", "hpp"]);
+        reg.add("c", &["c", "h"]);
+        reg.add("python", &["py", "pyi"]);
+        reg.add("web", &["html", "css", "js"]);
+        reg.add("markdown", &["md", "markdown"]);
+        reg.add("json", &["json"]);
+        reg
+    }
+
+    /// Register (or replace) the extension list for `name`.
+    pub fn add(&mut self, name: &str, extensions: &[&str]) {
+        self.types
+            .insert(name.to_string(), extensions.iter().map(|s| s.to_string()).collect());
+    }
+
+    /// The extensions registered for `name`, if any.
+    pub fn extensions(&self, name: &str) -> Option<&[String]> {
+        self.types.get(name).map(|v| v.as_slice())
+    }
+
+    /// Load overrides from a small config format: one `name=ext1,ext2,...`
+    /// per line; blank lines and lines starting with `#` are ignored.
+    /// Existing entries for a name are replaced, new names are added.
+    pub fn load_overrides(&mut self, config: &str) {
+        for line in config.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((name, exts)) = line.split_once('=') {
+                let extensions: Vec<&str> = exts
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                self.add(name.trim(), &extensions);
+            }
+        }
+    }
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_aliases_are_registered() {
+        let reg = TypeRegistry::new();
+        assert_eq!(reg.extensions("rust"), Some(&["rs".to_string()][..]));
+        assert_eq!(
+            reg.extensions("cpp"),
+            Some(&["cc".to_string(), "cpp".to_string(), "cxx".to_string(), "h".to_string(), "hpp".to_string()][..])
+        );
+        assert!(reg.extensions("nonexistent").is_none());
+    }
+
+    #[test]
+    fn add_registers_a_custom_alias() {
+        let mut reg = TypeRegistry::new();
+        reg.add("proto", &["proto"]);
+        assert_eq!(reg.extensions("proto"), Some(&["proto".to_string()][..]));
+    }
+
+    #[test]
+    fn load_overrides_parses_name_equals_csv_lines() {
+        let mut reg = TypeRegistry::new();
+        reg.load_overrides(
+            "# comment\n\
+             proto=proto\n\
+             rust=rs,rlib\n",
+        );
+        assert_eq!(reg.extensions("proto"), Some(&["proto".to_string()][..]));
+        assert_eq!(reg.extensions("rust"), Some(&["rs".to_string(), "rlib".to_string()][..]));
+    }
+}
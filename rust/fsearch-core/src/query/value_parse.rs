@@ -0,0 +1,207 @@
+//! Typed numeric parsing for `Compare`/`Range` operands and the candidate
+//! text they're checked against, so e.g. `size:>1mb` or
+//! `modified:>2024-01-01` compare as sizes/timestamps instead of falling
+//! back to lexicographic string comparison.
+
+use alloc::sync::Arc;
+
+/// Parses a field's textual value into a normalized `f64` key that numeric
+/// comparisons can be done on directly. Returns `None` when the text isn't
+/// in a shape the parser understands, signalling the caller should fall
+/// back to lexicographic comparison.
+pub type ValueParser = Arc<dyn Fn(&[u8]) -> Option<f64> + Send + Sync>;
+
+/// Parse a plain decimal integer or floating-point number. This is the
+/// parser used for fields with no more specific one registered.
+pub fn parse_plain_number(b: &[u8]) -> Option<f64> {
+    core::str::from_utf8(b).ok()?.trim().parse::<f64>().ok()
+}
+
+/// Parse a human-readable size such as `1.5mb`, `512k`, `2gib`, or `2gb`
+/// into a byte count. A bare unit letter (`k`/`m`/`g`/`t`) or an explicit
+/// IEC suffix (`kib`/`mib`/`gib`/`tib`) is binary (1024-based); an explicit
+/// decimal `b` suffix (`kb`/`mb`/`gb`/`tb`) is 1000-based. Plain numbers
+/// with no unit (including a trailing lone `b`) pass through unchanged.
+/// Matching is case-insensitive, so `10K`, `1.5MB`, and `2GiB` all work.
+pub fn parse_size_bytes(b: &[u8]) -> Option<f64> {
+    let s = core::str::from_utf8(b).ok()?.trim();
+    if s.is_empty() {
+        return None;
+    }
+    let lower = s.to_ascii_lowercase();
+    let (digits, multiplier) = if let Some(n) = lower.strip_suffix("tib") {
+        (n, 1024f64.powi(4))
+    } else if let Some(n) = lower.strip_suffix("gib") {
+        (n, 1024f64.powi(3))
+    } else if let Some(n) = lower.strip_suffix("mib") {
+        (n, 1024f64.powi(2))
+    } else if let Some(n) = lower.strip_suffix("kib") {
+        (n, 1024f64)
+    } else if let Some(n) = lower.strip_suffix("tb") {
+        (n, 1000f64.powi(4))
+    } else if let Some(n) = lower.strip_suffix("gb") {
+        (n, 1000f64.powi(3))
+    } else if let Some(n) = lower.strip_suffix("mb") {
+        (n, 1000f64.powi(2))
+    } else if let Some(n) = lower.strip_suffix("kb") {
+        (n, 1000f64)
+    } else if let Some(n) = lower.strip_suffix('t') {
+        (n, 1024f64.powi(4))
+    } else if let Some(n) = lower.strip_suffix('g') {
+        (n, 1024f64.powi(3))
+    } else if let Some(n) = lower.strip_suffix('m') {
+        (n, 1024f64.powi(2))
+    } else if let Some(n) = lower.strip_suffix('k') {
+        (n, 1024f64)
+    } else if let Some(n) = lower.strip_suffix('b') {
+        (n, 1f64)
+    } else {
+        (lower.as_str(), 1f64)
+    };
+    let value: f64 = digits.trim().parse().ok()?;
+    Some(value * multiplier)
+}
+
+/// Parse `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM:SS` (an optional trailing `Z` is
+/// ignored), or a plain decimal Unix timestamp into epoch seconds.
+pub fn parse_date_seconds(b: &[u8]) -> Option<f64> {
+    let s = core::str::from_utf8(b).ok()?.trim();
+    if let Ok(n) = s.parse::<f64>() {
+        return Some(n);
+    }
+    let (date_part, time_part) = match s.split_once('T').or_else(|| s.split_once(' ')) {
+        Some((d, t)) => (d, Some(t.trim_end_matches('Z'))),
+        None => (s, None),
+    };
+    let mut date_fields = date_part.splitn(3, '-');
+    let year: i64 = date_fields.next()?.parse().ok()?;
+    let month: i64 = date_fields.next()?.parse().ok()?;
+    let day: i64 = date_fields.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut seconds = days_from_civil(year, month, day) * 86400;
+    if let Some(t) = time_part {
+        let mut time_fields = t.splitn(3, ':');
+        let h: i64 = time_fields.next().unwrap_or("0").parse().ok()?;
+        let m: i64 = time_fields.next().unwrap_or("0").parse().ok()?;
+        let s: f64 = time_fields.next().unwrap_or("0").parse().ok()?;
+        seconds += h * 3600 + m * 60;
+        return Some(seconds as f64 + s);
+    }
+    Some(seconds as f64)
+}
+
+/// Parse a relative duration such as `7d`, `3h`, `30m`, `45s`, or `2w` into
+/// a second count, for `mtime`/`ctime` operands like `mtime:<7d` ("modified
+/// within the last 7 days"). Returns `None` for anything without one of
+/// these unit suffixes, so callers can tell a duration apart from an
+/// absolute date/timestamp operand.
+pub fn parse_duration_seconds(b: &[u8]) -> Option<f64> {
+    let s = core::str::from_utf8(b).ok()?.trim();
+    if s.is_empty() {
+        return None;
+    }
+    let lower = s.to_ascii_lowercase();
+    let (digits, multiplier) = if let Some(n) = lower.strip_suffix('w') {
+        (n, 604_800f64)
+    } else if let Some(n) = lower.strip_suffix('d') {
+        (n, 86_400f64)
+    } else if let Some(n) = lower.strip_suffix('h') {
+        (n, 3_600f64)
+    } else if let Some(n) = lower.strip_suffix('m') {
+        (n, 60f64)
+    } else if let Some(n) = lower.strip_suffix('s') {
+        (n, 1f64)
+    } else {
+        return None;
+    };
+    let value: f64 = digits.trim().parse().ok()?;
+    Some(value * multiplier)
+}
+
+/// Seconds since the Unix epoch, right now. Used to resolve `mtime`/`ctime`
+/// relative-duration operands (`<7d`) against the actual wall clock. Needs
+/// the `std` feature: no_std targets have no wall clock to read.
+#[cfg(feature = "std")]
+pub fn now_epoch_seconds() -> Option<f64> {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs_f64())
+}
+
+/// Days since the Unix epoch (1970-01-01) for a Gregorian calendar date,
+/// using Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400; // [0, 399]
+    let mp = (m + 9) % 12; // [0, 11]
+    let doy = (153 * mp + 2) / 5 + d - 1; // [0, 365]
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy; // [0, 146096]
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_numbers() {
+        assert_eq!(parse_plain_number(b"100"), Some(100.0));
+        assert_eq!(parse_plain_number(b"1.5"), Some(1.5));
+        assert_eq!(parse_plain_number(b"nope"), None);
+    }
+
+    #[test]
+    fn parses_binary_size_suffixes() {
+        assert_eq!(parse_size_bytes(b"1k"), Some(1024.0));
+        assert_eq!(parse_size_bytes(b"2g"), Some(2.0 * 1024.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_size_bytes(b"512"), Some(512.0));
+    }
+
+    #[test]
+    fn parses_decimal_size_suffixes() {
+        assert_eq!(parse_size_bytes(b"1kb"), Some(1000.0));
+        assert_eq!(parse_size_bytes(b"1.5mb"), Some(1_500_000.0));
+        assert_eq!(parse_size_bytes(b"1GB"), Some(1_000_000_000.0));
+    }
+
+    #[test]
+    fn parses_iso_date_to_epoch_seconds() {
+        assert_eq!(parse_date_seconds(b"1970-01-01"), Some(0.0));
+        assert_eq!(parse_date_seconds(b"1970-01-02"), Some(86400.0));
+        assert_eq!(parse_date_seconds(b"1970-01-01T00:00:30Z"), Some(30.0));
+    }
+
+    #[test]
+    fn parses_plain_epoch_timestamp_as_date() {
+        assert_eq!(parse_date_seconds(b"86400"), Some(86400.0));
+    }
+
+    #[test]
+    fn parses_iec_binary_size_suffixes() {
+        assert_eq!(parse_size_bytes(b"2GiB"), Some(2.0 * 1024.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_size_bytes(b"1kib"), Some(1024.0));
+    }
+
+    #[test]
+    fn parses_relative_durations() {
+        assert_eq!(parse_duration_seconds(b"7d"), Some(604_800.0));
+        assert_eq!(parse_duration_seconds(b"3h"), Some(10_800.0));
+        assert_eq!(parse_duration_seconds(b"30m"), Some(1_800.0));
+        assert_eq!(parse_duration_seconds(b"2w"), Some(1_209_600.0));
+        assert_eq!(parse_duration_seconds(b"2024-01-01"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn now_epoch_seconds_is_a_recent_unix_timestamp() {
+        // Sanity bound rather than an exact value: anything after 2020-01-01
+        // and before a wildly-in-the-future date is a sane wall clock.
+        let now = now_epoch_seconds().unwrap();
+        assert!(now > 1_577_836_800.0);
+        assert!(now < 4_102_444_800.0);
+    }
+}
@@ -1,6 +1,11 @@
+use crate::ffi::CaseMode;
+use crate::ffi::MatchOptions;
 use crate::ffi::SearchResult as FfiSearchResult;
+use crate::fuzzy_match;
 use crate::query::Parser;
 use crate::query::QueryMatcher;
+use crate::query::LiteralPrefilter;
+use crate::match_engine::passes_prefilter;
 use crate::pcre2_pool::PatternPool;
 use crate::index::Index;
 use crossbeam_channel::{unbounded, Receiver, Sender};
@@ -9,48 +14,314 @@ use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use rayon::prelude::*;
 use regex::Regex;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+use unicode_normalization::UnicodeNormalization;
+
+impl Default for CaseMode {
+    fn default() -> Self {
+        CaseMode::Smart
+    }
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions { case_mode: CaseMode::Smart, unicode_normalize: true }
+    }
+}
+
+/// Search-wide behavior flags orthogonal to match semantics (see
+/// `MatchOptions`): an optional early-termination cap, modeled on `hunt`'s
+/// `--first` flag, plus the gitignore/hidden-file/depth/cache rules a
+/// `start_index_build` walk should apply while (re)populating the index
+/// these searches run against (see `index::WalkOptions`, which mirrors
+/// these four fields -- `index` can't depend on this `ffi`-gated module,
+/// so `start_index_build` translates between the two).
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    /// Once this many results have been found, cooperatively stop the
+    /// search (via the handle's existing cancel flag -- see
+    /// `cancel_search`) instead of scanning the rest of the index. `None`
+    /// scans everything, matching `start_search_with_index`'s behavior
+    /// before this option existed.
+    pub max_results: Option<usize>,
+    /// Skip paths excluded by `.gitignore`/`.ignore`/global git excludes
+    /// while walking, ripgrep-style.
+    pub respect_gitignore: bool,
+    /// Include dotfiles and dot-directories in the walk. `ignore`'s own
+    /// `WalkBuilder` excludes them by default; this mirrors that default
+    /// when `false`.
+    pub include_hidden: bool,
+    /// Maximum descent depth from each walk root, or `None` for unlimited.
+    pub max_depth: Option<usize>,
+    /// Reuse a previous walk's result from this on-disk cache instead of
+    /// re-walking, as long as the root set and each root's mtime are
+    /// unchanged (see `fs_cache`). `None` always walks live.
+    pub cache: Option<std::path::PathBuf>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions { max_results: None, respect_gitignore: false, include_hidden: true, max_depth: None, cache: None }
+    }
+}
+
+/// Whether the fallback matcher should treat this search as case-sensitive,
+/// per `case_mode`. `Smart` mirrors ripgrep: case-sensitive iff `pattern`
+/// contains an uppercase letter.
+fn is_case_sensitive(case_mode: CaseMode, pattern: &str) -> bool {
+    match case_mode {
+        CaseMode::Sensitive => true,
+        CaseMode::Insensitive => false,
+        CaseMode::Smart => pattern.chars().any(|c| c.is_uppercase()),
+        _ => false,
+    }
+}
+
+/// Fold `text` for fallback-matcher comparison: NFKC-normalize it (if
+/// `unicode_normalize`, so precomposed and decomposed Unicode forms compare
+/// equal) and case-fold it (if `!case_sensitive`, via `entry::case_fold` --
+/// the same folding `Entry::normalized` uses, so e.g. `STRASSE` matches
+/// `straße`). `Entry::normalized` already has both folds applied
+/// unconditionally, so the common case (case-insensitive, normalized --
+/// the default) can reuse it directly instead of calling this; see
+/// callers.
+fn fold_for_match(text: &str, case_sensitive: bool, unicode_normalize: bool) -> String {
+    let s: String = if unicode_normalize { text.nfkc().collect() } else { text.to_string() };
+    if case_sensitive { s } else { crate::entry::case_fold(&s) }
+}
 
 type HandleId = u64;
 
+/// A result paired with the relevance score it was ranked by, ordered by
+/// `score` alone so it can sit in a [`BinaryHeap`] (see `RankedState`).
+struct ScoredResult {
+    score: i64,
+    result: FfiSearchResult,
+}
+
+impl PartialEq for ScoredResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score
+    }
+}
+impl Eq for ScoredResult {}
+impl PartialOrd for ScoredResult {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for ScoredResult {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.score.cmp(&other.score)
+    }
+}
+
+/// Bookkeeping for a ranked search (see `start_search_with_index_ranked`): a
+/// bounded min-heap of the best `limit` results seen so far, keyed off
+/// `Reverse` so the *worst* kept score sits on top and gets evicted first.
+struct RankedState {
+    limit: usize,
+    heap: Arc<Mutex<BinaryHeap<std::cmp::Reverse<ScoredResult>>>>,
+}
+
+/// Bookkeeping that lets `update_search` restart a channel-based handle (see
+/// `start_search_with_index`) in place: the same index snapshot and sender
+/// are reused so no new handle or channel is allocated, `generation` is
+/// bumped on every restart so `poll_results` can drop stale in-flight
+/// results, and `pending` is a monotonic ticket counter `update_search` uses
+/// to debounce a burst of calls down to a single restart.
+#[derive(Clone)]
+struct RestartState {
+    idx: Arc<Index>,
+    sender: Sender<(u64, FfiSearchResult)>,
+    generation: Arc<AtomicU64>,
+    pending: Arc<AtomicU64>,
+    /// Carried across restarts unchanged -- `update_search` only replaces
+    /// the query text, not the case/normalization flags the handle was
+    /// opened with.
+    options: MatchOptions,
+    /// Carried across restarts unchanged -- same rationale as `options`.
+    search_options: SearchOptions,
+    /// Carried across restarts unchanged -- see `SearchContext::completion_listeners`.
+    completion_listeners: Arc<Mutex<Vec<Sender<bool>>>>,
+}
+
 pub struct SearchContext {
-    pub receiver: Receiver<FfiSearchResult>,
+    pub receiver: Receiver<(u64, FfiSearchResult)>,
     pub cancel_flag: Arc<AtomicBool>,
     pub join_handle: Option<std::thread::JoinHandle<()>>,
+    ranked: Option<RankedState>,
+    restart: Option<RestartState>,
+    /// Senders a `SearchSet` registers via `insert` to be notified (with
+    /// whether the search was cancelled) once this handle's worker thread
+    /// exits. Only meaningful for channel-based handles; other entry points
+    /// leave it empty.
+    completion_listeners: Arc<Mutex<Vec<Sender<bool>>>>,
 }
 
-static HANDLE_MAP: Lazy<Mutex<HashMap<HandleId, SearchContext>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// Push `result` into a bounded ranked heap, evicting the current
+/// worst-scoring entry once `limit` is exceeded.
+fn push_ranked(heap: &Mutex<BinaryHeap<std::cmp::Reverse<ScoredResult>>>, limit: usize, score: i64, result: FfiSearchResult) {
+    if limit == 0 {
+        return;
+    }
+    let mut h = heap.lock();
+    if h.len() < limit {
+        h.push(std::cmp::Reverse(ScoredResult { score, result }));
+    } else if let Some(std::cmp::Reverse(worst)) = h.peek() {
+        if score > worst.score {
+            h.pop();
+            h.push(std::cmp::Reverse(ScoredResult { score, result }));
+        }
+    }
+}
+
+/// A relevance heuristic for non-fuzzy matches: a hit in `name` outranks one
+/// only found in `path`, and shallower paths outrank deeper ones. Fuzzy
+/// matches use their own fzf-style score instead (see `fuzzy_match`).
+fn heuristic_score(path: &str, matched_name: bool) -> i64 {
+    let depth = path.matches('/').count() as i64;
+    let base = if matched_name { 1000 } else { 500 };
+    base - depth
+}
+
+/// Number of independent shards in `HandleMap` (see below).
+const SHARD_COUNT: usize = 16;
+
+/// Fold `id` down to a shard index with a cheap Fibonacci hash. `HandleId`s
+/// are already unique, sequentially-assigned integers (see
+/// `next_handle_id`), so spreading them across shards doesn't need a
+/// full-strength hash. This only picks the shard: the shard's own
+/// `HashMap` still hashes `id` again with its default hasher on every
+/// lookup.
+fn shard_index(id: HandleId) -> usize {
+    let hash = id.wrapping_mul(0x9E3779B97F4A7C15);
+    (hash >> 48) as usize % SHARD_COUNT
+}
+
+/// A concurrent handle registry, sharded across `SHARD_COUNT` independent
+/// mutexes (see `shard_index`) so unrelated handles' spawn/cancel/poll
+/// traffic doesn't serialize through one lock the way a plain
+/// `Mutex<HashMap<..>>` would under an interactive search box firing
+/// overlapping queries.
+struct HandleMap {
+    shards: [Mutex<HashMap<HandleId, SearchContext>>; SHARD_COUNT],
+}
+
+impl HandleMap {
+    fn new() -> Self {
+        HandleMap { shards: std::array::from_fn(|_| Mutex::new(HashMap::new())) }
+    }
+
+    fn insert(&self, id: HandleId, ctx: SearchContext) {
+        self.shards[shard_index(id)].lock().insert(id, ctx);
+    }
+
+    /// Remove and return `id`'s context, if present.
+    fn remove(&self, id: HandleId) -> Option<SearchContext> {
+        self.shards[shard_index(id)].lock().remove(&id)
+    }
+
+    /// Run `f` with a shared reference to `id`'s context (if any), holding
+    /// only that one handle's shard lock for the duration.
+    fn with<R>(&self, id: HandleId, f: impl FnOnce(Option<&SearchContext>) -> R) -> R {
+        let shard = self.shards[shard_index(id)].lock();
+        f(shard.get(&id))
+    }
+
+    /// Like `with`, but with a mutable reference.
+    fn with_mut<R>(&self, id: HandleId, f: impl FnOnce(Option<&mut SearchContext>) -> R) -> R {
+        let mut shard = self.shards[shard_index(id)].lock();
+        f(shard.get_mut(&id))
+    }
+
+    /// Snapshot every handle currently registered, across all shards, so
+    /// `shutdown_all` can cancel/join each one without holding any shard
+    /// lock while it does.
+    fn keys_snapshot(&self) -> Vec<HandleId> {
+        self.shards.iter().flat_map(|s| s.lock().keys().copied().collect::<Vec<_>>()).collect()
+    }
+}
+
+static HANDLE_MAP: Lazy<HandleMap> = Lazy::new(HandleMap::new);
 
 fn next_handle_id() -> u64 {
-    use std::sync::atomic::AtomicU64;
     static H: Lazy<AtomicU64> = Lazy::new(|| AtomicU64::new(1));
     H.fetch_add(1, Ordering::SeqCst)
 }
 
-/// Start a search over the provided index (snapshot) using a simple query language.
-/// For now: plain substring (case-insensitive) or regex if query starts with `re:`.
-pub fn start_search_with_index(idx: Arc<Index>, query: &str) -> u64 {
-    let (s, r): (Sender<FfiSearchResult>, Receiver<FfiSearchResult>) = unbounded();
-    let cancel = Arc::new(AtomicBool::new(false));
+/// Bump `found`'s count after a match is sent and, once `max_results` is
+/// reached, cooperatively stop the rest of this search by flipping
+/// `cancel` -- every sibling worker on the same `par_iter` polls that same
+/// flag and unwinds promptly instead of scanning the rest of the index. A
+/// later `update_search` restart clears the flag and counts from zero
+/// again. See `SearchOptions::max_results`.
+fn record_match(found: &AtomicUsize, max_results: Option<usize>, cancel: &AtomicBool) {
+    if let Some(max) = max_results {
+        if found.fetch_add(1, Ordering::SeqCst) + 1 >= max {
+            cancel.store(true, Ordering::SeqCst);
+        }
+    }
+}
 
+/// Spawn the worker thread that matches `query` against `idx` and streams
+/// hits into `sender`, stamped with `generation` so a later `update_search`
+/// restart can mark them stale. Shared by `start_search_with_index` (which
+/// starts the first generation) and `update_search` (which restarts later
+/// ones against the same channel). `completion_listeners` is notified with
+/// whether the search was cancelled once this worker exits, for `SearchSet`.
+/// `search_options.max_results`, if set, stops the search early (see
+/// `record_match`); note a search that stops this way still notifies
+/// `completion_listeners` with `cancelled = true`, the same as an explicit
+/// `cancel_search`.
+fn spawn_channel_worker(idx: Arc<Index>, query: &str, cancel: Arc<AtomicBool>, sender: Sender<(u64, FfiSearchResult)>, generation: u64, options: MatchOptions, completion_listeners: Arc<Mutex<Vec<Sender<bool>>>>, search_options: SearchOptions) -> std::thread::JoinHandle<()> {
     let q = query.to_string();
-    let cancel_clone = cancel.clone();
-    // spawn a thread to run the search and stream results into sender
-    let join = std::thread::spawn(move || {
+    std::thread::spawn(move || {
+        // Fires `notify_completion` exactly once, on every exit path out of
+        // this closure -- including a panic unwinding straight through it.
+        // Without this as a `Drop` guard, a panic anywhere in the match
+        // loop below (e.g. inside `idx.entries.par_iter().for_each`) used to
+        // skip the inline `notify_completion` call entirely, leaving every
+        // `SearchSet::insert` blocked on `done_rx.recv()` forever instead of
+        // just mislabeling the outcome.
+        struct NotifyOnDrop {
+            listeners: Arc<Mutex<Vec<Sender<bool>>>>,
+            cancel: Arc<AtomicBool>,
+        }
+        impl Drop for NotifyOnDrop {
+            fn drop(&mut self) {
+                let cancelled = self.cancel.load(Ordering::SeqCst) || std::thread::panicking();
+                notify_completion(&self.listeners, cancelled);
+            }
+        }
+        let _notify_on_drop = NotifyOnDrop { listeners: completion_listeners, cancel: cancel.clone() };
+
         if idx.entries.is_empty() {
-            drop(s);
+            drop(sender);
             return;
         }
 
+        // `fz:` is a dedicated fuzzy-matching mode (see `fuzzy_match`); it
+        // takes priority over the compiled-AST path so a fuzzy pattern
+        // containing a bare colon doesn't get misparsed as `Field("fz", ..)`.
+        let is_fuzzy = q.starts_with("fz:");
+        let fuzzy_pattern = if is_fuzzy { q[3..].to_string() } else { String::new() };
+
         // Try to parse query into AST; if successful compile via QueryMatcher
         let mut compiled_opt: Option<crate::query::matcher::CompiledNode> = None;
+        let mut prefilter_opt: Option<LiteralPrefilter> = None;
         let pool = PatternPool::new();
-        if let Ok(mut parser) = std::panic::catch_unwind(|| Parser::new(&q)) {
-            if let Some(node) = parser.parse() {
-                if let Ok(comp) = QueryMatcher::new(pool.clone()).compile(&node) {
-                    compiled_opt = Some(comp);
+        if !is_fuzzy {
+            if let Ok(mut parser) = std::panic::catch_unwind(|| Parser::new(&q)) {
+                if let Some(node) = parser.parse() {
+                    prefilter_opt = LiteralPrefilter::build(&node);
+                    if let Ok(comp) = QueryMatcher::new(pool.clone()).compile(&node) {
+                        compiled_opt = Some(comp);
+                    }
                 }
             }
         }
@@ -58,18 +329,30 @@ pub fn start_search_with_index(idx: Arc<Index>, query: &str) -> u64 {
         // prepare legacy regex/substring fallback
         let is_regex = q.starts_with("re:");
         let pattern = if is_regex { q[3..].to_string() } else { q.clone() };
-        let regex: Option<Regex> = if is_regex { Regex::new(&pattern).ok() } else { None };
-        let lower_pat = pattern.to_lowercase();
+        let case_sensitive = is_case_sensitive(options.case_mode, &pattern);
+        let regex: Option<Regex> = if is_regex {
+            regex::RegexBuilder::new(&pattern).case_insensitive(!case_sensitive).build().ok()
+        } else {
+            None
+        };
+        let folded_pat = fold_for_match(&pattern, case_sensitive, options.unicode_normalize);
+        let found = AtomicUsize::new(0);
 
         // parallel iterate entries
         idx.entries.par_iter().for_each(|e| {
-            if cancel_clone.load(Ordering::SeqCst) {
+            if cancel.load(Ordering::SeqCst) {
                 return;
             }
+            let path = idx.entry_path(e);
             // If we have a compiled query, use it for matching and metadata
             if let Some(compiled) = &compiled_opt {
-                let text = format!("{}\n{}", e.name, e.path);
+                let text = format!("{}\n{}", e.name, path);
                 let bytes = text.as_bytes();
+                if let Some(prefilter) = &prefilter_opt {
+                    if !passes_prefilter(prefilter, bytes) {
+                        return;
+                    }
+                }
                 if QueryMatcher::new(pool.clone()).is_match(compiled, bytes) {
                     let metas = QueryMatcher::new(pool.clone()).captures_meta(compiled, bytes);
                     // prefer compiled node field when metas don't specify one
@@ -93,68 +376,344 @@ pub fn start_search_with_index(idx: Arc<Index>, query: &str) -> u64 {
                         parts.push(format!("{{\"field\":{},\"ranges\":[{}]}}", field_json, ranges_parts.join(",")));
                     }
                     let highlights = format!("[{}]", parts.join(","));
-                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: e.path.clone(), size: e.size, mtime: e.mtime, highlights };
-                    let _ = s.send(res);
+                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights };
+                    let _ = sender.send((generation, res));
+                    record_match(&found, search_options.max_results, &cancel);
+                }
+            } else if is_fuzzy {
+                let name_match = fuzzy_match::fuzzy_match(&fuzzy_pattern, &e.name);
+                let path_match = fuzzy_match::fuzzy_match(&fuzzy_pattern, &path);
+                if name_match.is_some() || path_match.is_some() {
+                    let highlights = fuzzy_highlights_json(&e.name, &name_match, &path, &path_match);
+                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights };
+                    let _ = sender.send((generation, res));
+                    record_match(&found, search_options.max_results, &cancel);
                 }
             } else {
                 let matched = if let Some(re) = &regex {
-                    re.is_match(&e.path) || re.is_match(&e.name)
+                    let folded_path = fold_for_match(&path, true, options.unicode_normalize);
+                    let folded_name = fold_for_match(&e.name, true, options.unicode_normalize);
+                    re.is_match(&folded_path) || re.is_match(&folded_name)
+                } else if !case_sensitive && options.unicode_normalize {
+                    // fast path: Entry::normalized is already NFKC+lowercased
+                    e.normalized.contains(&folded_pat) || fold_for_match(&path, false, true).contains(&folded_pat)
                 } else {
-                    // case-insensitive substring search on normalized fields
-                    e.normalized.contains(&lower_pat) || e.path.to_lowercase().contains(&lower_pat)
+                    fold_for_match(&e.name, case_sensitive, options.unicode_normalize).contains(&folded_pat)
+                        || fold_for_match(&path, case_sensitive, options.unicode_normalize).contains(&folded_pat)
                 };
                 if matched {
-                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: e.path.clone(), size: e.size, mtime: e.mtime, highlights: String::new() };
-                    let _ = s.send(res);
+                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights: String::new() };
+                    let _ = sender.send((generation, res));
+                    record_match(&found, search_options.max_results, &cancel);
                 }
             }
         });
 
-        // finished
-        drop(s);
+        // finished -- `_notify_on_drop` reports completion when it drops below
+        drop(sender);
+    })
+}
+
+/// Tell every `SearchSet` tracking this handle (see `SearchContext::completion_listeners`)
+/// that its worker has exited, and whether it was cancelled.
+fn notify_completion(listeners: &Mutex<Vec<Sender<bool>>>, cancelled: bool) {
+    for l in listeners.lock().iter() {
+        let _ = l.send(cancelled);
+    }
+}
+
+/// Start a search over the provided index (snapshot) using a simple query language.
+/// For now: plain substring or regex if query starts with `re:`, case-sensitive
+/// or not per `options.case_mode`.
+/// `search_options.max_results`, if set, stops the search once that many
+/// hits have been found (see `record_match`) instead of scanning the whole
+/// index.
+pub fn start_search_with_index(idx: Arc<Index>, query: &str, options: MatchOptions, search_options: SearchOptions) -> u64 {
+    let (s, r): (Sender<(u64, FfiSearchResult)>, Receiver<(u64, FfiSearchResult)>) = unbounded();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let generation = Arc::new(AtomicU64::new(0));
+    let pending = Arc::new(AtomicU64::new(0));
+    let completion_listeners = Arc::new(Mutex::new(Vec::new()));
+
+    let join = spawn_channel_worker(idx.clone(), query, cancel.clone(), s.clone(), 0, options, completion_listeners.clone(), search_options.clone());
+
+    let id = next_handle_id();
+    let ctx = SearchContext {
+        receiver: r,
+        cancel_flag: cancel,
+        join_handle: Some(join),
+        ranked: None,
+        restart: Some(RestartState { idx, sender: s, generation, pending, options, search_options, completion_listeners: completion_listeners.clone() }),
+        completion_listeners,
+    };
+    HANDLE_MAP.insert(id, ctx);
+    id
+}
+
+/// How long `update_search` waits before actually restarting the worker, so
+/// a burst of keystrokes collapses into a single restart instead of one per
+/// call.
+const UPDATE_DEBOUNCE: Duration = Duration::from_millis(120);
+
+/// Re-point a channel-based handle (one from `start_search_with_index`) at
+/// `new_query`, reusing the same handle, channel, and index snapshot rather
+/// than allocating new ones. The restart itself is debounced by
+/// `UPDATE_DEBOUNCE`; if another `update_search` call supersedes this one
+/// before the debounce elapses, this call does nothing. Results already in
+/// flight from a superseded query are dropped by `poll_results` once their
+/// stamped generation falls behind `handle`'s current one.
+///
+/// Returns `false` if `handle` doesn't refer to a channel-based handle (the
+/// callback- and ranked-search entry points don't support incremental
+/// re-query; restart those by calling their `start_search_*` function again
+/// with a fresh handle).
+pub fn update_search(handle: u64, new_query: &str) -> bool {
+    let restart = match HANDLE_MAP.with(handle, |ctx| ctx.and_then(|c| c.restart.clone())) {
+        Some(r) => r,
+        None => return false,
+    };
+
+    let my_ticket = restart.pending.fetch_add(1, Ordering::SeqCst) + 1;
+    let my_generation = restart.generation.fetch_add(1, Ordering::SeqCst) + 1;
+    let q = new_query.to_string();
+
+    std::thread::spawn(move || {
+        std::thread::sleep(UPDATE_DEBOUNCE);
+        if restart.pending.load(Ordering::SeqCst) != my_ticket {
+            // A later update_search call superseded this one before the
+            // debounce elapsed; that call will perform the restart.
+            return;
+        }
+
+        let cancel = match HANDLE_MAP.with(handle, |ctx| ctx.map(|c| c.cancel_flag.clone())) {
+            Some(c) => c,
+            None => return,
+        };
+        cancel.store(true, Ordering::SeqCst);
+
+        let old_join = HANDLE_MAP.with_mut(handle, |ctx| ctx.and_then(|c| c.join_handle.take()));
+        if let Some(j) = old_join {
+            join_recording_panic(handle, j);
+        }
+        cancel.store(false, Ordering::SeqCst);
+
+        let new_join = spawn_channel_worker(restart.idx.clone(), &q, cancel, restart.sender.clone(), my_generation, restart.options, restart.completion_listeners.clone(), restart.search_options);
+        HANDLE_MAP.with_mut(handle, |ctx| {
+            if let Some(ctx) = ctx {
+                ctx.join_handle = Some(new_join);
+            }
+        });
+    });
+
+    true
+}
+
+/// Like `start_search_with_index`, but instead of streaming every match the
+/// worker keeps only the best `limit` results (see `ScoredResult`/
+/// `RankedState`). Poll with `poll_ranked_results`, which returns the heap's
+/// current contents sorted by descending score and truncated to `limit`.
+pub fn start_search_with_index_ranked(idx: Arc<Index>, query: &str, limit: usize, options: MatchOptions) -> u64 {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let heap: Arc<Mutex<BinaryHeap<std::cmp::Reverse<ScoredResult>>>> = Arc::new(Mutex::new(BinaryHeap::new()));
+
+    let q = query.to_string();
+    let cancel_clone = cancel.clone();
+    let heap_clone = heap.clone();
+    let join = std::thread::spawn(move || {
+        if idx.entries.is_empty() {
+            return;
+        }
+
+        let is_fuzzy = q.starts_with("fz:");
+        let fuzzy_pattern = if is_fuzzy { q[3..].to_string() } else { String::new() };
+
+        let mut compiled_opt: Option<crate::query::matcher::CompiledNode> = None;
+        let mut prefilter_opt: Option<LiteralPrefilter> = None;
+        let pool = PatternPool::new();
+        if !is_fuzzy {
+            if let Ok(mut parser) = std::panic::catch_unwind(|| Parser::new(&q)) {
+                if let Some(node) = parser.parse() {
+                    prefilter_opt = LiteralPrefilter::build(&node);
+                    if let Ok(comp) = QueryMatcher::new(pool.clone()).compile(&node) {
+                        compiled_opt = Some(comp);
+                    }
+                }
+            }
+        }
+
+        let is_regex = q.starts_with("re:");
+        let pattern = if is_regex { q[3..].to_string() } else { q.clone() };
+        let case_sensitive = is_case_sensitive(options.case_mode, &pattern);
+        let regex: Option<Regex> = if is_regex {
+            regex::RegexBuilder::new(&pattern).case_insensitive(!case_sensitive).build().ok()
+        } else {
+            None
+        };
+        let folded_pat = fold_for_match(&pattern, case_sensitive, options.unicode_normalize);
+
+        idx.entries.par_iter().for_each(|e| {
+            if cancel_clone.load(Ordering::SeqCst) {
+                return;
+            }
+            let path = idx.entry_path(e);
+            if let Some(compiled) = &compiled_opt {
+                let text = format!("{}\n{}", e.name, path);
+                let bytes = text.as_bytes();
+                if let Some(prefilter) = &prefilter_opt {
+                    if !passes_prefilter(prefilter, bytes) {
+                        return;
+                    }
+                }
+                if QueryMatcher::new(pool.clone()).is_match(compiled, bytes) {
+                    let metas = QueryMatcher::new(pool.clone()).captures_meta(compiled, bytes);
+                    let compiled_field = match compiled {
+                        crate::query::matcher::CompiledNode::Leaf { field, .. } => field.clone(),
+                        crate::query::matcher::CompiledNode::Compare { field, .. } => field.clone(),
+                        crate::query::matcher::CompiledNode::Range { field, .. } => field.clone(),
+                        _ => None,
+                    };
+                    let matched_name = metas.iter().any(|m| m.ranges.iter().any(|&(a, _)| a < e.name.len()));
+                    let mut parts = Vec::new();
+                    for mut m in metas {
+                        if m.field.is_none() {
+                            m.field = compiled_field.clone();
+                        }
+                        let mut ranges_parts = Vec::new();
+                        for (a, b) in m.ranges {
+                            ranges_parts.push(format!("[{},{}]", a, b));
+                        }
+                        let field_json = match m.field { Some(f) => format!("\"{}\"", f), None => "null".to_string() };
+                        parts.push(format!("{{\"field\":{},\"ranges\":[{}]}}", field_json, ranges_parts.join(",")));
+                    }
+                    let highlights = format!("[{}]", parts.join(","));
+                    let score = heuristic_score(&path, matched_name);
+                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights };
+                    push_ranked(&heap_clone, limit, score, res);
+                }
+            } else if is_fuzzy {
+                let name_match = fuzzy_match::fuzzy_match(&fuzzy_pattern, &e.name);
+                let path_match = fuzzy_match::fuzzy_match(&fuzzy_pattern, &path);
+                let score = name_match.as_ref().map(|m| m.score).into_iter().chain(path_match.as_ref().map(|m| m.score)).max();
+                if let Some(score) = score {
+                    let highlights = fuzzy_highlights_json(&e.name, &name_match, &path, &path_match);
+                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights };
+                    push_ranked(&heap_clone, limit, score, res);
+                }
+            } else {
+                let (matched_name, matched_path) = if let Some(re) = &regex {
+                    let folded_name = fold_for_match(&e.name, true, options.unicode_normalize);
+                    let folded_path = fold_for_match(&path, true, options.unicode_normalize);
+                    (re.is_match(&folded_name), re.is_match(&folded_path))
+                } else if !case_sensitive && options.unicode_normalize {
+                    // fast path: Entry::normalized is already NFKC+lowercased
+                    (e.normalized.contains(&folded_pat), fold_for_match(&path, false, true).contains(&folded_pat))
+                } else {
+                    (
+                        fold_for_match(&e.name, case_sensitive, options.unicode_normalize).contains(&folded_pat),
+                        fold_for_match(&path, case_sensitive, options.unicode_normalize).contains(&folded_pat),
+                    )
+                };
+                if matched_name || matched_path {
+                    let score = heuristic_score(&path, matched_name);
+                    let res = FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights: String::new() };
+                    push_ranked(&heap_clone, limit, score, res);
+                }
+            }
+        });
     });
 
     let id = next_handle_id();
-    let ctx = SearchContext { receiver: r, cancel_flag: cancel, join_handle: Some(join) };
-    HANDLE_MAP.lock().insert(id, ctx);
+    let (_s, r): (Sender<(u64, FfiSearchResult)>, Receiver<(u64, FfiSearchResult)>) = unbounded();
+    let ctx = SearchContext {
+        receiver: r,
+        cancel_flag: cancel,
+        join_handle: Some(join),
+        ranked: Some(RankedState { limit, heap }),
+        restart: None,
+        completion_listeners: Arc::new(Mutex::new(Vec::new())),
+    };
+    HANDLE_MAP.insert(id, ctx);
     id
 }
 
+/// Precomputed grapheme-cluster boundaries for a candidate string, so
+/// mapping many highlight byte-ranges from the same match (e.g. every range
+/// of every `captures_meta` hit, or a fuzzy match's ranges) only scans
+/// `text` once instead of rescanning it per range -- see `map`.
+struct Utf16GraphemeMap {
+    /// Grapheme cluster start byte offsets, sorted, with a trailing entry
+    /// at `text.len()`.
+    grapheme_starts: Vec<usize>,
+    /// `utf16_counts[i]` is the number of UTF-16 code units in
+    /// `text[..grapheme_starts[i]]`.
+    utf16_counts: Vec<usize>,
+}
+
+impl Utf16GraphemeMap {
+    fn build(text: &str) -> Self {
+        let mut grapheme_starts: Vec<usize> = text.grapheme_indices(true).map(|(b, _)| b).collect();
+        if grapheme_starts.last().copied().unwrap_or(0) != text.len() {
+            grapheme_starts.push(text.len());
+        }
+        let mut utf16_counts = Vec::with_capacity(grapheme_starts.len());
+        let mut prev_byte = 0;
+        let mut count = 0usize;
+        for &b in &grapheme_starts {
+            count += text[prev_byte..b].encode_utf16().count();
+            utf16_counts.push(count);
+            prev_byte = b;
+        }
+        Utf16GraphemeMap { grapheme_starts, utf16_counts }
+    }
+
+    /// Map a byte range to grapheme-aligned UTF-16 `[start, end)` bounds via
+    /// two binary searches over the precomputed boundaries.
+    fn map(&self, start: usize, end: usize) -> (usize, usize) {
+        // last grapheme boundary <= start
+        let gstart_idx = match self.grapheme_starts.binary_search(&start) {
+            Ok(i) => i,
+            Err(i) => i.saturating_sub(1),
+        };
+        // first grapheme boundary >= end
+        let gend_idx = match self.grapheme_starts.binary_search(&end) {
+            Ok(i) => i,
+            Err(i) => i,
+        };
+        (self.utf16_counts[gstart_idx], self.utf16_counts[gend_idx])
+    }
+}
+
 /// Convert a byte-range (start..end) inside `text` into UTF-16 code-unit
 /// [start,end) indices that align to grapheme cluster boundaries. This makes
 /// the resulting indices safe to apply directly to Qt `QString` (which uses
-/// UTF-16 code units for indexing).
+/// UTF-16 code units for indexing). Building a `Utf16GraphemeMap` directly
+/// and calling `map` repeatedly is cheaper when mapping more than one range
+/// for the same `text`.
 fn byte_range_to_utf16_bounds(text: &str, start: usize, end: usize) -> (usize, usize) {
-    // Collect grapheme start byte indices
-    let mut starts: Vec<usize> = Vec::new();
-    for (byte_idx, _) in text.grapheme_indices(true) {
-        starts.push(byte_idx);
-    }
-    // Ensure final boundary at text.len()
-    if starts.last().copied().unwrap_or(0) != text.len() {
-        starts.push(text.len());
-    }
-
-    // find grapheme that contains start
-    let mut gstart = 0usize;
-    for i in 0..starts.len()-1 {
-        if start >= starts[i] && start < starts[i+1] {
-            gstart = starts[i];
-            break;
-        }
-    }
-    // find grapheme boundary that contains end -> take next boundary
-    let mut gend = text.len();
-    for i in 0..starts.len()-1 {
-        if end > starts[i] && end <= starts[i+1] {
-            gend = starts[i+1];
-            break;
+    Utf16GraphemeMap::build(text).map(start, end)
+}
+
+/// Build the `highlights` JSON for a `fz:` match, covering whichever of
+/// `name`/`path` actually matched.
+fn fuzzy_highlights_json(
+    name: &str,
+    name_match: &Option<fuzzy_match::FuzzyMatch>,
+    path: &str,
+    path_match: &Option<fuzzy_match::FuzzyMatch>,
+) -> String {
+    let mut parts = Vec::new();
+    for (field, text, m) in [("name", name, name_match), ("path", path, path_match)] {
+        if let Some(m) = m {
+            let utf16_map = Utf16GraphemeMap::build(text);
+            let mut ranges_parts = Vec::new();
+            for &(start, end) in &m.ranges {
+                let (su, eu) = utf16_map.map(start, end);
+                ranges_parts.push(format!("[{},{}]", su, eu));
+            }
+            parts.push(format!("{{\"field\":\"{}\",\"ranges\":[{}]}}", field, ranges_parts.join(",")));
         }
     }
-
-    let start_units = text[..gstart].encode_utf16().count();
-    let end_units = text[..gend].encode_utf16().count();
-    (start_units, end_units)
+    format!("[{}]", parts.join(","))
 }
 
 #[cfg(test)]
@@ -170,6 +729,80 @@ mod tests {
     use std::io::Write;
     use serde_json::Value;
 
+    #[test]
+    fn max_results_stops_the_search_before_the_whole_index_is_scanned() {
+        let dir = tempdir().expect("tempdir");
+        let p = dir.path();
+        let total = 200;
+        for i in 0..total {
+            File::create(p.join(format!("match_{i}.txt"))).unwrap().write_all(b"x").unwrap();
+        }
+
+        let mut idx = Index::new();
+        idx.build_from_paths(vec![p.to_string_lossy().to_string()]);
+        let idx = Arc::new(idx);
+
+        let max_results = 5;
+        let handle = start_search_with_index(idx, "match", MatchOptions::default(), SearchOptions { max_results: Some(max_results), ..SearchOptions::default() });
+
+        // Give the worker time to run to its (early) stop; once
+        // `record_match` trips the cancel flag, every other in-flight
+        // `par_iter` closure returns without sending, so the result count
+        // settles and stops growing well before the whole index is scanned.
+        std::thread::sleep(Duration::from_millis(500));
+
+        let results = poll_results(handle);
+        assert!(!results.is_empty(), "expected at least one match before stopping");
+        assert!(
+            results.len() < total,
+            "max_results should have stopped the search well before scanning all {total} entries, got {}",
+            results.len()
+        );
+
+        cancel_search(handle);
+    }
+
+    #[test]
+    fn handle_map_spreads_many_handles_across_shards_and_tracks_each_one() {
+        let map = HandleMap::new();
+        let ids: Vec<HandleId> = (1..=200).collect();
+        for &id in &ids {
+            let (_s, r): (Sender<(u64, FfiSearchResult)>, Receiver<(u64, FfiSearchResult)>) = unbounded();
+            let ctx = SearchContext {
+                receiver: r,
+                cancel_flag: Arc::new(AtomicBool::new(false)),
+                join_handle: None,
+                ranked: None,
+                restart: None,
+                completion_listeners: Arc::new(Mutex::new(Vec::new())),
+            };
+            map.insert(id, ctx);
+        }
+
+        // every inserted handle is reachable via `with`, regardless of which
+        // shard its id happened to land in
+        for &id in &ids {
+            assert!(map.with(id, |ctx| ctx.is_some()), "handle {id} missing after insert");
+        }
+
+        // `keys_snapshot` sees every handle across every shard, not just shard 0
+        let mut seen = map.keys_snapshot();
+        seen.sort_unstable();
+        assert_eq!(seen, ids);
+
+        // the ids actually spread across more than one shard -- otherwise
+        // this "sharded" map would just be a slower plain `Mutex<HashMap<..>>`
+        let shards_used: std::collections::HashSet<usize> = ids.iter().map(|&id| shard_index(id)).collect();
+        assert!(shards_used.len() > 1, "expected handles to spread across multiple shards, got {shards_used:?}");
+
+        // removing a handle takes it out of the map, and it's no longer
+        // found by `with`/`keys_snapshot`
+        let removed = ids[0];
+        assert!(map.remove(removed).is_some());
+        assert!(map.with(removed, |ctx| ctx.is_none()));
+        assert!(!map.keys_snapshot().contains(&removed));
+    }
+
     #[test]
     fn utf16_mapping_flag_emoji() {
         let s = "aðŸ‡ºðŸ‡¸b"; // 'ðŸ‡ºðŸ‡¸' is a flag composed of two regional indicators
@@ -240,7 +873,7 @@ mod tests {
         }
 
         let q = CString::new("test").unwrap();
-        let handle = crate::fsearch_start_search_with_cb_c(q.as_ptr(), Some(cb), tx_box as *mut std::os::raw::c_void);
+        let handle = crate::fsearch_start_search_with_cb_c(q.as_ptr(), Some(cb), tx_box as *mut std::os::raw::c_void, 1);
         assert!(handle != 0);
 
         // wait for at least one highlight message
@@ -319,7 +952,7 @@ mod tests {
         }
 
         let q = CString::new("path:test").unwrap();
-        let handle = crate::fsearch_start_search_with_cb_c(q.as_ptr(), Some(cb), tx_box as *mut std::os::raw::c_void);
+        let handle = crate::fsearch_start_search_with_cb_c(q.as_ptr(), Some(cb), tx_box as *mut std::os::raw::c_void, 1);
         assert!(handle != 0);
 
         // wait for a callback
@@ -346,7 +979,7 @@ mod tests {
                         // compute combined text UTF-16 indices mapping: name + '\n' + path
                         let name = wrapper.get("name").and_then(|v| v.as_str()).unwrap_or("");
                         let name_units = name.encode_utf16().count();
-                        // the worker code constructs text = format!("{}\n{}", e.name, e.path)
+                        // the worker code constructs text = format!("{}\n{}", e.name, path)
                         // so path UTF-16 indices start at name_units + 1 (the newline)
                         let combined_start = name_units + 1;
                         if s_idx >= combined_start {
@@ -377,138 +1010,450 @@ mod tests {
     }
 }
 
+/// Default number of entries matched per `rayon` chunk before their results
+/// are handed to the callback together; see `start_search_with_index_and_cb`.
+const DEFAULT_CB_BATCH_SIZE: usize = 64;
+
 /// Start a search and invoke the provided C callback for each matching result.
 /// This is event-driven: results are delivered by Rust calling the callback as
-/// they are found. Note: callers should ensure the callback is thread-safe or
-/// marshal GUI updates to the main thread (Qt client does this).
-pub fn start_search_with_index_and_cb(idx: Arc<Index>, query: &str, cb: extern "C" fn(u64, *const std::os::raw::c_char, *const std::os::raw::c_char, u64, u64, *const std::os::raw::c_char, *mut std::os::raw::c_void), userdata: *mut std::os::raw::c_void) -> u64 {
+/// they are found, from `rayon` worker threads. Note: callers should ensure
+/// the callback is thread-safe or marshal GUI updates to the main thread (Qt
+/// client does this).
+///
+/// Matching runs in chunks of `batch_size` entries (`0` falls back to
+/// `DEFAULT_CB_BATCH_SIZE`); each chunk's matches are collected into a local
+/// batch and only then walked to invoke `cb`, so the callback doesn't
+/// interleave with matching work on the same thread and FFI calls for
+/// adjacent hits land back-to-back.
+pub fn start_search_with_index_and_cb(idx: Arc<Index>, query: &str, cb: extern "C" fn(u64, *const std::os::raw::c_char, *const std::os::raw::c_char, u64, u64, *const std::os::raw::c_char, *mut std::os::raw::c_void), userdata: *mut std::os::raw::c_void, batch_size: usize, options: MatchOptions) -> u64 {
     let cancel = Arc::new(AtomicBool::new(false));
     let cancel_clone = cancel.clone();
     let q = query.to_string();
     let id = next_handle_id();
+    // `*mut c_void` isn't `Send`, but the bits we want to move across the
+    // rayon pool are just an address; the callback (a plain `extern "C" fn`,
+    // already `Send`/`Sync`) is the only thing that ever dereferences it.
     let userdata_usize = userdata as usize;
+    let batch_size = if batch_size == 0 { DEFAULT_CB_BATCH_SIZE } else { batch_size };
 
-    // Spawn worker thread that calls cb directly for matches.
+    // Spawn a thread that drives rayon-parallel matching and calls cb for
+    // each match, grouped into per-chunk batches.
     let join = std::thread::spawn(move || {
         if idx.entries.is_empty() { return; }
 
+        // See the matching comment in `start_search_with_index`: `fz:` takes
+        // priority over the compiled-AST path.
+        let is_fuzzy = q.starts_with("fz:");
+        let fuzzy_pattern = if is_fuzzy { q[3..].to_string() } else { String::new() };
+
         let mut compiled_opt: Option<crate::query::matcher::CompiledNode> = None;
+        let mut prefilter_opt: Option<LiteralPrefilter> = None;
         let pool = PatternPool::new();
-        if let Ok(mut parser) = std::panic::catch_unwind(|| Parser::new(&q)) {
-            if let Some(node) = parser.parse() {
-                if let Ok(comp) = QueryMatcher::new(pool.clone()).compile(&node) {
-                    compiled_opt = Some(comp);
+        if !is_fuzzy {
+            if let Ok(mut parser) = std::panic::catch_unwind(|| Parser::new(&q)) {
+                if let Some(node) = parser.parse() {
+                    prefilter_opt = LiteralPrefilter::build(&node);
+                    if let Ok(comp) = QueryMatcher::new(pool.clone()).compile(&node) {
+                        compiled_opt = Some(comp);
+                    }
                 }
             }
         }
 
         let is_regex = q.starts_with("re:");
         let pattern = if is_regex { q[3..].to_string() } else { q.clone() };
-        let regex: Option<regex::Regex> = if is_regex { regex::Regex::new(&pattern).ok() } else { None };
-        let lower_pat = pattern.to_lowercase();
+        let case_sensitive = is_case_sensitive(options.case_mode, &pattern);
+        let regex: Option<regex::Regex> = if is_regex {
+            regex::RegexBuilder::new(&pattern).case_insensitive(!case_sensitive).build().ok()
+        } else {
+            None
+        };
+        let folded_pat = fold_for_match(&pattern, case_sensitive, options.unicode_normalize);
 
-        for e in idx.entries.iter() {
-            if cancel_clone.load(Ordering::SeqCst) { break; }
-            if let Some(compiled) = &compiled_opt {
-                let text = format!("{}\n{}", e.name, e.path);
-                let bytes = text.as_bytes();
-                if QueryMatcher::new(pool.clone()).is_match(compiled, bytes) {
-                    let metas = QueryMatcher::new(pool.clone()).captures_meta(compiled, bytes);
-                    // prefer compiled node field when metas don't specify one
-                    let compiled_field = match compiled {
-                        crate::query::matcher::CompiledNode::Leaf { field, .. } => field.clone(),
-                        crate::query::matcher::CompiledNode::Compare { field, .. } => field.clone(),
-                        crate::query::matcher::CompiledNode::Range { field, .. } => field.clone(),
-                        _ => None,
-                    };
-                    // Build highlights JSON using UTF-16 grapheme-safe boundaries
-                    let mut parts = Vec::new();
-                    for mut m in metas {
-                        if m.field.is_none() {
-                            m.field = compiled_field.clone();
+        idx.entries.par_chunks(batch_size).for_each(|chunk| {
+            if cancel_clone.load(Ordering::SeqCst) {
+                return;
+            }
+            let mut batch: Vec<FfiSearchResult> = Vec::new();
+            for e in chunk {
+                let path = idx.entry_path(e);
+                if let Some(compiled) = &compiled_opt {
+                    let text = format!("{}\n{}", e.name, path);
+                    let bytes = text.as_bytes();
+                    if let Some(prefilter) = &prefilter_opt {
+                        if !passes_prefilter(prefilter, bytes) {
+                            continue;
                         }
-                        let mut ranges_parts = Vec::new();
-                        for (a,b) in m.ranges {
-                            let s = a;
-                            let e_b = b;
-                            let (su, eu) = byte_range_to_utf16_bounds(&text, s, e_b);
-                            ranges_parts.push(format!("[{},{}]", su, eu));
+                    }
+                    if QueryMatcher::new(pool.clone()).is_match(compiled, bytes) {
+                        let metas = QueryMatcher::new(pool.clone()).captures_meta(compiled, bytes);
+                        // prefer compiled node field when metas don't specify one
+                        let compiled_field = match compiled {
+                            crate::query::matcher::CompiledNode::Leaf { field, .. } => field.clone(),
+                            crate::query::matcher::CompiledNode::Compare { field, .. } => field.clone(),
+                            crate::query::matcher::CompiledNode::Range { field, .. } => field.clone(),
+                            _ => None,
+                        };
+                        // Build highlights JSON using UTF-16 grapheme-safe boundaries.
+                        // One map covers every range below, instead of rescanning
+                        // `text` per range.
+                        let utf16_map = Utf16GraphemeMap::build(&text);
+                        let mut parts = Vec::new();
+                        for mut m in metas {
+                            if m.field.is_none() {
+                                m.field = compiled_field.clone();
+                            }
+                            let mut ranges_parts = Vec::new();
+                            for (a,b) in m.ranges {
+                                let (su, eu) = utf16_map.map(a, b);
+                                ranges_parts.push(format!("[{},{}]", su, eu));
+                            }
+                            let field_json = match m.field { Some(f) => format!("\"{}\"", f), None => "null".to_string() };
+                            parts.push(format!("{{\"field\":{},\"ranges\":[{}]}}", field_json, ranges_parts.join(",")));
                         }
-                        let field_json = match m.field { Some(f) => format!("\"{}\"", f), None => "null".to_string() };
-                        parts.push(format!("{{\"field\":{},\"ranges\":[{}]}}", field_json, ranges_parts.join(",")));
+                        let highlights = format!("[{}]", parts.join(","));
+                        batch.push(FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights });
+                    }
+                } else if is_fuzzy {
+                    let name_match = fuzzy_match::fuzzy_match(&fuzzy_pattern, &e.name);
+                    let path_match = fuzzy_match::fuzzy_match(&fuzzy_pattern, &path);
+                    if name_match.is_some() || path_match.is_some() {
+                        let highlights = fuzzy_highlights_json(&e.name, &name_match, &path, &path_match);
+                        batch.push(FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights });
                     }
-                    let highlights = format!("[{}]", parts.join(","));
-                    // call callback
-                    let name_c = std::ffi::CString::new(e.name.clone()).unwrap_or_default();
-                    let path_c = std::ffi::CString::new(e.path.clone()).unwrap_or_default();
-                    let highlights_c = std::ffi::CString::new(highlights).unwrap_or_default();
-                    let ud = userdata_usize as *mut std::os::raw::c_void;
-                    cb(e.id, name_c.as_ptr(), path_c.as_ptr(), e.size, e.mtime, highlights_c.as_ptr(), ud);
-                }
-            } else {
-                let matched = if let Some(re) = &regex {
-                    re.is_match(&e.path) || re.is_match(&e.name)
                 } else {
-                    e.normalized.contains(&lower_pat) || e.path.to_lowercase().contains(&lower_pat)
-                };
-                if matched {
-                    let name_c = std::ffi::CString::new(e.name.clone()).unwrap_or_default();
-                    let path_c = std::ffi::CString::new(e.path.clone()).unwrap_or_default();
-                    let highlights_c = std::ffi::CString::new("".to_string()).unwrap_or_default();
-                    let ud = userdata_usize as *mut std::os::raw::c_void;
-                    cb(e.id, name_c.as_ptr(), path_c.as_ptr(), e.size, e.mtime, highlights_c.as_ptr(), ud);
+                    let matched = if let Some(re) = &regex {
+                        let folded_path = fold_for_match(&path, true, options.unicode_normalize);
+                        let folded_name = fold_for_match(&e.name, true, options.unicode_normalize);
+                        re.is_match(&folded_path) || re.is_match(&folded_name)
+                    } else if !case_sensitive && options.unicode_normalize {
+                        // fast path: Entry::normalized is already NFKC+lowercased
+                        e.normalized.contains(&folded_pat) || fold_for_match(&path, false, true).contains(&folded_pat)
+                    } else {
+                        fold_for_match(&e.name, case_sensitive, options.unicode_normalize).contains(&folded_pat)
+                            || fold_for_match(&path, case_sensitive, options.unicode_normalize).contains(&folded_pat)
+                    };
+                    if matched {
+                        batch.push(FfiSearchResult { id: e.id, name: e.name.clone(), path: path.clone(), size: e.size, mtime: e.mtime, highlights: String::new() });
+                    }
                 }
             }
-        }
+
+            // Deliver the whole batch together: the matching above is done,
+            // so these calls are back-to-back FFI crossings with no
+            // interleaved scan work.
+            for res in batch {
+                let name_c = std::ffi::CString::new(res.name).unwrap_or_default();
+                let path_c = std::ffi::CString::new(res.path).unwrap_or_default();
+                let highlights_c = std::ffi::CString::new(res.highlights).unwrap_or_default();
+                let ud = userdata_usize as *mut std::os::raw::c_void;
+                cb(res.id, name_c.as_ptr(), path_c.as_ptr(), res.size, res.mtime, highlights_c.as_ptr(), ud);
+            }
+        });
     });
 
-    let ctx = SearchContext { receiver: crossbeam_channel::unbounded().1 /*unused*/, cancel_flag: cancel, join_handle: Some(join) };
-    HANDLE_MAP.lock().insert(id, ctx);
+    let ctx = SearchContext { receiver: crossbeam_channel::unbounded().1 /*unused*/, cancel_flag: cancel, join_handle: Some(join), ranked: None, restart: None, completion_listeners: Arc::new(Mutex::new(Vec::new())) };
+    HANDLE_MAP.insert(id, ctx);
     id
 }
 
 pub fn poll_results(handle: u64) -> Vec<FfiSearchResult> {
     let mut out = Vec::new();
-    let map = HANDLE_MAP.lock();
-    if let Some(ctx) = map.get(&handle) {
-        use crossbeam_channel::TryRecvError;
-        loop {
-            match ctx.receiver.try_recv() {
-                Ok(item) => out.push(item),
-                Err(TryRecvError::Empty) => break,
-                Err(TryRecvError::Disconnected) => {
-                    // channel closed and drained; remove handle
-                    drop(map);
-                    HANDLE_MAP.lock().remove(&handle);
-                    break;
+    let mut disconnected = false;
+    HANDLE_MAP.with(handle, |ctx| {
+        if let Some(ctx) = ctx {
+            // Results are stamped with the generation of the worker that
+            // found them; if `update_search` has since restarted the
+            // search, drop anything left over from an older generation
+            // instead of surfacing stale results for the query the caller
+            // has already moved past.
+            let current_generation = ctx.restart.as_ref().map(|r| r.generation.load(Ordering::SeqCst)).unwrap_or(0);
+            use crossbeam_channel::TryRecvError;
+            loop {
+                match ctx.receiver.try_recv() {
+                    Ok((generation, item)) => {
+                        if generation == current_generation {
+                            out.push(item);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        // channel closed and drained; remove handle
+                        disconnected = true;
+                        break;
+                    }
                 }
             }
         }
+    });
+    if disconnected {
+        HANDLE_MAP.remove(handle);
     }
     out
 }
 
+/// Read back the current contents of a ranked search's heap (see
+/// `start_search_with_index_ranked`), sorted by descending score and
+/// truncated to its `limit`. Unlike `poll_results`, this is non-destructive
+/// and safe to call repeatedly while the search is still running.
+pub fn poll_ranked_results(handle: u64) -> Vec<FfiSearchResult> {
+    let mut out = Vec::new();
+    HANDLE_MAP.with(handle, |ctx| {
+        if let Some(ranked) = ctx.and_then(|c| c.ranked.as_ref()) {
+            let mut h = ranked.heap.lock();
+            let mut items: Vec<ScoredResult> = std::mem::take(&mut *h).into_vec().into_iter().map(|std::cmp::Reverse(x)| x).collect();
+            items.sort_by(|a, b| b.score.cmp(&a.score));
+            items.truncate(ranked.limit);
+
+            for sr in &items {
+                out.push(FfiSearchResult {
+                    id: sr.result.id,
+                    name: sr.result.name.clone(),
+                    path: sr.result.path.clone(),
+                    size: sr.result.size,
+                    mtime: sr.result.mtime,
+                    highlights: sr.result.highlights.clone(),
+                });
+            }
+            for sr in items.into_iter() {
+                h.push(std::cmp::Reverse(sr));
+            }
+        }
+    });
+    out
+}
+
+/// Register `cancel`/`join` under a fresh handle id in the same registry
+/// `start_search_with_index` uses, so non-search background work --
+/// currently just `lib::start_index_build`'s walker thread -- can be
+/// cancelled and joined via the ordinary `cancel_search`/`outcome` API
+/// instead of needing a parallel one. The returned handle has no channel
+/// results to poll; callers track completion via `outcome`.
+pub fn register_cancelable(cancel: Arc<AtomicBool>, join: std::thread::JoinHandle<()>) -> u64 {
+    let id = next_handle_id();
+    let (_s, r): (Sender<(u64, FfiSearchResult)>, Receiver<(u64, FfiSearchResult)>) = unbounded();
+    let ctx = SearchContext {
+        receiver: r,
+        cancel_flag: cancel,
+        join_handle: Some(join),
+        ranked: None,
+        restart: None,
+        completion_listeners: Arc::new(Mutex::new(Vec::new())),
+    };
+    HANDLE_MAP.insert(id, ctx);
+    id
+}
+
 pub fn cancel_search(handle: u64) {
     // Set cancel flag and join the worker thread if present to ensure it exits
-    let mut map = HANDLE_MAP.lock();
-    if let Some(ctx) = map.remove(&handle) {
+    if let Some(ctx) = HANDLE_MAP.remove(handle) {
         ctx.cancel_flag.store(true, Ordering::SeqCst);
         if let Some(join) = ctx.join_handle {
-            // drop lock while joining
-            drop(map);
-            let _ = join.join();
+            join_recording_panic(handle, join);
         }
     }
 }
 
 /// Cancel and join all active searches. Safe to call multiple times.
 pub fn shutdown_all() {
-    // collect handles first to avoid holding lock while joining
-    let handles: Vec<u64> = {
-        let map = HANDLE_MAP.lock();
-        map.keys().copied().collect()
-    };
-    for h in handles {
+    // snapshot handles across every shard first to avoid holding any shard
+    // lock while joining
+    for h in HANDLE_MAP.keys_snapshot() {
         cancel_search(h);
     }
+    // An index build cancelled above mid-`fs_cache::store` may still hold
+    // that cache's lock file open; release it so a process being torn down
+    // doesn't wedge the cache for other readers/writers.
+    crate::fs_cache::release_all_locks();
+}
+
+/// How a search tracked by a `SearchSet` (or queried via `outcome`) ended:
+/// its worker ran to completion and `Completed` carries every result still
+/// unread on its channel at that point, it was cancelled (via
+/// `SearchSet::abort` or a direct `cancel_search` call) before finishing, or
+/// it unwound with a panic, whose message `Panicked` carries so the caller
+/// can distinguish a crashed search from a clean empty result instead of the
+/// panic just making results silently vanish.
+#[derive(Debug, Clone)]
+pub enum SearchOutcome {
+    Completed(Vec<FfiSearchResult>),
+    Cancelled,
+    Panicked(String),
+}
+
+/// Recovers a human-readable message from a thread panic payload -- the
+/// `Box<dyn Any>` a panicking worker's `JoinHandle::join()` returns in its
+/// `Err` case. `std::panic!`/`.unwrap()`/`.expect()` payloads are always
+/// `&str` or `String`; anything else (a custom `panic::panic_any` payload)
+/// falls back to a placeholder rather than failing to report the panic at
+/// all.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "worker thread panicked with a non-string payload".to_string()
+    }
+}
+
+/// How many panicked handles `LAST_OUTCOME` keeps before evicting the
+/// oldest -- see `PatternCache` in `pcre2_pool.rs` for the same
+/// bounded-cache shape. `HandleId`s are assigned sequentially and never
+/// reused, so insertion order and age order are the same thing here: a
+/// plain FIFO queue is enough, unlike `PatternCache`'s LRU (which needs to
+/// reorder on read).
+const MAX_TRACKED_OUTCOMES: usize = 1024;
+
+/// Outcomes of handles whose worker thread has already been joined, keyed by
+/// handle so `outcome` can still answer for a handle `cancel_search` already
+/// removed from `HANDLE_MAP`. Only panics are recorded here today (see
+/// `join_recording_panic`); a handle absent from this map simply has no
+/// panic to report. Bounded to `MAX_TRACKED_OUTCOMES` entries (oldest
+/// evicted first) so a long-running process doesn't leak one entry per
+/// panicking handle forever.
+struct OutcomeLog {
+    entries: HashMap<HandleId, SearchOutcome>,
+    order: VecDeque<HandleId>,
+}
+
+impl OutcomeLog {
+    fn new() -> Self {
+        OutcomeLog { entries: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn insert(&mut self, handle: HandleId, outcome: SearchOutcome) {
+        if !self.entries.contains_key(&handle) && self.entries.len() >= MAX_TRACKED_OUTCOMES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.push_back(handle);
+        self.entries.insert(handle, outcome);
+    }
+
+    fn get(&self, handle: HandleId) -> Option<SearchOutcome> {
+        self.entries.get(&handle).cloned()
+    }
+}
+
+static LAST_OUTCOME: Lazy<Mutex<OutcomeLog>> = Lazy::new(|| Mutex::new(OutcomeLog::new()));
+
+/// Join `join` (the worker thread behind `handle`), recording
+/// `SearchOutcome::Panicked` into `LAST_OUTCOME` if it unwound instead of
+/// returning normally -- replaces the `let _ = join.join();` teardown
+/// pattern that silently discarded the panic payload.
+fn join_recording_panic(handle: HandleId, join: std::thread::JoinHandle<()>) {
+    if let Err(payload) = join.join() {
+        LAST_OUTCOME.lock().insert(handle, SearchOutcome::Panicked(panic_message(&*payload)));
+    }
+}
+
+/// The last recorded outcome for `handle`, if its worker thread has panicked
+/// since being joined (by `cancel_search`, `update_search`, or
+/// `SearchSet::insert`). Lets a caller distinguish a search that crashed
+/// from one that simply found nothing.
+pub fn outcome(handle: u64) -> Option<SearchOutcome> {
+    LAST_OUTCOME.lock().get(handle)
+}
+
+/// Take `handle`'s worker `JoinHandle` out of `HANDLE_MAP` without removing
+/// the rest of its context, if it's still registered and hasn't already
+/// been joined by `cancel_search`/`update_search`. Lets `SearchSet::insert`
+/// join the worker itself (to learn whether it panicked) without racing a
+/// concurrent `cancel_search`/`abort` over who gets to remove the handle.
+fn take_join_handle(handle: HandleId) -> Option<std::thread::JoinHandle<()>> {
+    HANDLE_MAP.with_mut(handle, |ctx| ctx.and_then(|c| c.join_handle.take()))
+}
+
+/// A `tokio_util::task::JoinMap`-style completion queue over channel-based
+/// search handles (ones from `start_search_with_index`): `insert` a handle,
+/// then `join_next` blocks until *any* inserted search finishes (or is
+/// cancelled) and hands back its handle plus outcome, in completion order
+/// rather than insertion order. Meant for UI code that wants to stream the
+/// first-to-finish query among several without polling every handle.
+///
+/// Don't also call `poll_results` on a handle tracked by a `SearchSet` --
+/// both read the same underlying channel, so they'd race over its results.
+pub struct SearchSet {
+    sender: Sender<(u64, SearchOutcome)>,
+    receiver: Receiver<(u64, SearchOutcome)>,
+}
+
+impl SearchSet {
+    pub fn new() -> Self {
+        let (sender, receiver) = unbounded();
+        SearchSet { sender, receiver }
+    }
+
+    /// Track `handle`'s completion. Spawns a thread that waits for `handle`'s
+    /// worker to exit, drains whatever results are left unread on its
+    /// channel, and reports the outcome to `join_next`/`join_next_with_timeout`
+    /// -- distinguishing a genuine panic from an ordinary cancellation by
+    /// also joining the worker's own `JoinHandle` (see `take_join_handle`)
+    /// and consulting `outcome`, the same way `cancel_search` already does.
+    /// A no-op if `handle` isn't a channel-based handle.
+    pub fn insert(&self, handle: u64) {
+        let (done_tx, done_rx) = unbounded::<bool>();
+        let found = HANDLE_MAP.with(handle, |ctx| ctx.filter(|c| c.restart.is_some()).map(|c| (c.completion_listeners.clone(), c.receiver.clone())));
+        let (listeners, receiver) = match found {
+            Some(t) => t,
+            None => return,
+        };
+        listeners.lock().push(done_tx);
+
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            // The worker thread's own clone of `receiver` (and any clone
+            // held by its handle's `RestartState`) stays alive independent
+            // of whether `handle` is later removed from `HANDLE_MAP`, so
+            // this keeps working even if `abort` races ahead and cancels it.
+            let cancelled = done_rx.recv().unwrap_or(true);
+
+            // The worker has already exited by the time `done_rx` fires (its
+            // `NotifyOnDrop` guard notifies on the way out), so joining its
+            // `JoinHandle` here never blocks. If `abort`/`cancel_search` got
+            // there first, the handle is already gone and this is a no-op --
+            // whatever panic it found is already in `LAST_OUTCOME`.
+            if let Some(join) = take_join_handle(handle) {
+                join_recording_panic(handle, join);
+            }
+
+            let mut results = Vec::new();
+            while let Ok((_, item)) = receiver.try_recv() {
+                results.push(item);
+            }
+            let outcome = match outcome(handle) {
+                Some(SearchOutcome::Panicked(msg)) => SearchOutcome::Panicked(msg),
+                _ if cancelled => SearchOutcome::Cancelled,
+                _ => SearchOutcome::Completed(results),
+            };
+            let _ = sender.send((handle, outcome));
+        });
+    }
+
+    /// Block until any tracked search finishes, returning its handle and
+    /// outcome in completion order. `None` once every tracked search has
+    /// reported and this `SearchSet`'s own sender side has been dropped.
+    pub fn join_next(&self) -> Option<(u64, SearchOutcome)> {
+        self.receiver.recv().ok()
+    }
+
+    /// Like `join_next`, but gives up and returns `None` after `timeout`
+    /// instead of blocking indefinitely.
+    pub fn join_next_with_timeout(&self, timeout: Duration) -> Option<(u64, SearchOutcome)> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Cancel a tracked search; its outcome surfaces via `join_next` as
+    /// `SearchOutcome::Cancelled` once its worker thread exits.
+    pub fn abort(&self, handle: u64) {
+        cancel_search(handle);
+    }
+}
+
+impl Default for SearchSet {
+    fn default() -> Self {
+        Self::new()
+    }
 }
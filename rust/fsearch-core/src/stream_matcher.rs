@@ -0,0 +1,613 @@
+//! A content-search engine for files too large to load into memory.
+//!
+//! `Matcher` and `Pcre2Compiled` both take a `&[u8]` that's already
+//! resident in RAM -- fine for filenames and small fields, but it means
+//! searching file *contents* requires reading the whole file first. This
+//! module compiles a pattern once into a small Thompson NFA and then runs
+//! it as a lazy (on-demand) DFA: states are never pre-enumerated (which is
+//! exponential in the worst case), only materialized the first time the
+//! scan actually visits them, and memoized so repeat visits are an O(1)
+//! lookup instead of re-deriving the same subset construction. The
+//! transition memo is capped; once it's full it's cleared and rebuilt from
+//! scratch rather than growing without bound, so memory stays flat however
+//! large the input is.
+//!
+//! The search loop (`StreamSearch`) reads a `Read` in fixed 64 KiB chunks
+//! and feeds it to the DFA one byte at a time, carrying the current state
+//! (and a running line/byte counter) across chunk boundaries, so a match
+//! that straddles two reads is still found.
+//!
+//! Supported syntax is a practical subset for grep-like content search:
+//! literals, `.` (any byte but `\n`), character classes (`[abc]`,
+//! `[^abc]`, ranges like `[a-z]`), concatenation, alternation (`|`),
+//! grouping (`(...)`), and the `*`/`+`/`?` quantifiers. This is not a
+//! general regex engine -- for filename/field matching, where the haystack
+//! already fits in memory, use `Matcher` or `Pcre2Compiled` instead, which
+//! support the full syntax of their underlying crates.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+const BUF_SIZE: usize = 64 * 1024;
+/// Distinct `(state, byte)` transitions memoized before the cache is
+/// cleared and rebuilt. Keeps memory flat regardless of input size instead
+/// of growing the memo without bound.
+const DEFAULT_CACHE_CAP: usize = 8192;
+
+/// Error building a [`StreamMatcher`] from a pattern string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError(String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid stream pattern: {}", self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// A 256-bit bitmap of which bytes a single NFA transition accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct ByteClass([u64; 4]);
+
+impl ByteClass {
+    fn empty() -> Self {
+        ByteClass([0; 4])
+    }
+
+    fn insert(&mut self, b: u8) {
+        self.0[(b >> 6) as usize] |= 1 << (b & 63);
+    }
+
+    fn insert_range(&mut self, lo: u8, hi: u8) {
+        for b in lo..=hi {
+            self.insert(b);
+        }
+    }
+
+    fn negate(&mut self) {
+        for word in self.0.iter_mut() {
+            *word = !*word;
+        }
+    }
+
+    fn contains(&self, b: u8) -> bool {
+        self.0[(b >> 6) as usize] & (1 << (b & 63)) != 0
+    }
+
+    /// Any byte except `\n`, matching grep's line-oriented `.`.
+    fn dot() -> Self {
+        let mut c = ByteClass::empty();
+        c.insert(b'\n');
+        c.negate();
+        c
+    }
+}
+
+/// A single Thompson-construction instruction. `Char`/`Split` carry the
+/// program-counter(s) of the instruction(s) to continue with; dangling
+/// exits are patched in by `Parser::patch` as fragments are combined.
+#[derive(Clone, Copy, Debug)]
+enum Inst {
+    Char(ByteClass, usize),
+    Split(usize, usize),
+    Match,
+}
+
+/// A Thompson NFA compiled from pattern source, plus its entry point.
+struct Nfa {
+    prog: Vec<Inst>,
+    start: usize,
+}
+
+/// A dangling "out" pointer left by a fragment, to be patched once the
+/// following fragment's start is known.
+#[derive(Clone, Copy)]
+enum Hole {
+    /// `Inst::Char`'s target.
+    Char(usize),
+    /// `Inst::Split`'s first or second target.
+    SplitA(usize),
+    SplitB(usize),
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    prog: Vec<Inst>,
+}
+
+/// A compiled fragment: its entry instruction and the exits still needing
+/// a target (patched in once the next fragment in sequence is known).
+struct Frag {
+    start: usize,
+    outs: Vec<Hole>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self {
+        Parser { src: src.as_bytes(), pos: 0, prog: Vec::new() }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn push(&mut self, inst: Inst) -> usize {
+        self.prog.push(inst);
+        self.prog.len() - 1
+    }
+
+    fn patch(&mut self, holes: &[Hole], target: usize) {
+        for hole in holes {
+            match *hole {
+                Hole::Char(pc) => {
+                    if let Inst::Char(class, _) = self.prog[pc] {
+                        self.prog[pc] = Inst::Char(class, target);
+                    }
+                }
+                Hole::SplitA(pc) => {
+                    if let Inst::Split(_, b) = self.prog[pc] {
+                        self.prog[pc] = Inst::Split(target, b);
+                    }
+                }
+                Hole::SplitB(pc) => {
+                    if let Inst::Split(a, _) = self.prog[pc] {
+                        self.prog[pc] = Inst::Split(a, target);
+                    }
+                }
+            }
+        }
+    }
+
+    /// `alt := concat ('|' concat)*`
+    fn parse_alt(&mut self) -> Result<Frag, PatternError> {
+        let mut frag = self.parse_concat()?;
+        while self.peek() == Some(b'|') {
+            self.bump();
+            let rhs = self.parse_concat()?;
+            let split = self.push(Inst::Split(frag.start, rhs.start));
+            let mut outs = frag.outs;
+            outs.extend(rhs.outs);
+            frag = Frag { start: split, outs };
+        }
+        Ok(frag)
+    }
+
+    /// `concat := repeat*`, empty concatenation matches the empty string.
+    fn parse_concat(&mut self) -> Result<Frag, PatternError> {
+        let mut frag: Option<Frag> = None;
+        while let Some(b) = self.peek() {
+            if b == b'|' || b == b')' {
+                break;
+            }
+            let next = self.parse_repeat()?;
+            frag = Some(match frag {
+                None => next,
+                Some(prev) => {
+                    self.patch(&prev.outs, next.start);
+                    Frag { start: prev.start, outs: next.outs }
+                }
+            });
+        }
+        match frag {
+            Some(f) => Ok(f),
+            // Empty fragment: a no-op split whose both arms are the same
+            // dangling hole, so the next patch sends it straight through.
+            None => {
+                let split = self.push(Inst::Split(0, 0));
+                Ok(Frag { start: split, outs: vec![Hole::SplitA(split), Hole::SplitB(split)] })
+            }
+        }
+    }
+
+    /// `repeat := atom ('*' | '+' | '?')?`
+    fn parse_repeat(&mut self) -> Result<Frag, PatternError> {
+        let atom = self.parse_atom()?;
+        match self.peek() {
+            Some(b'*') => {
+                self.bump();
+                let split = self.push(Inst::Split(atom.start, 0));
+                self.patch(&atom.outs, split);
+                Ok(Frag { start: split, outs: vec![Hole::SplitB(split)] })
+            }
+            Some(b'+') => {
+                self.bump();
+                let split = self.push(Inst::Split(atom.start, 0));
+                self.patch(&atom.outs, split);
+                Ok(Frag { start: atom.start, outs: vec![Hole::SplitB(split)] })
+            }
+            Some(b'?') => {
+                self.bump();
+                let split = self.push(Inst::Split(atom.start, 0));
+                Ok(Frag { start: split, outs: { let mut o = atom.outs; o.push(Hole::SplitB(split)); o } })
+            }
+            _ => Ok(atom),
+        }
+    }
+
+    /// `atom := literal | '.' | class | '(' alt ')'`
+    fn parse_atom(&mut self) -> Result<Frag, PatternError> {
+        match self.bump() {
+            Some(b'(') => {
+                let inner = self.parse_alt()?;
+                if self.bump() != Some(b')') {
+                    return Err(PatternError("unbalanced '('".to_string()));
+                }
+                Ok(inner)
+            }
+            Some(b'.') => {
+                let pc = self.push(Inst::Char(ByteClass::dot(), 0));
+                Ok(Frag { start: pc, outs: vec![Hole::Char(pc)] })
+            }
+            Some(b'[') => {
+                let class = self.parse_class()?;
+                let pc = self.push(Inst::Char(class, 0));
+                Ok(Frag { start: pc, outs: vec![Hole::Char(pc)] })
+            }
+            Some(b'\\') => {
+                let lit = self.bump().ok_or_else(|| PatternError("trailing '\\'".to_string()))?;
+                let mut class = ByteClass::empty();
+                class.insert(lit);
+                let pc = self.push(Inst::Char(class, 0));
+                Ok(Frag { start: pc, outs: vec![Hole::Char(pc)] })
+            }
+            Some(b) => {
+                let mut class = ByteClass::empty();
+                class.insert(b);
+                let pc = self.push(Inst::Char(class, 0));
+                Ok(Frag { start: pc, outs: vec![Hole::Char(pc)] })
+            }
+            None => Err(PatternError("unexpected end of pattern".to_string())),
+        }
+    }
+
+    /// `[...]` already past the opening `[`.
+    fn parse_class(&mut self) -> Result<ByteClass, PatternError> {
+        let negate = self.peek() == Some(b'^');
+        if negate {
+            self.bump();
+        }
+        let mut class = ByteClass::empty();
+        let mut first = true;
+        loop {
+            match self.bump() {
+                Some(b']') if !first => break,
+                Some(lo) => {
+                    first = false;
+                    if self.peek() == Some(b'-') && self.src.get(self.pos + 1).is_some_and(|&b| b != b']') {
+                        self.bump();
+                        let hi = self.bump().ok_or_else(|| PatternError("unbalanced '['".to_string()))?;
+                        class.insert_range(lo, hi);
+                    } else {
+                        class.insert(lo);
+                    }
+                }
+                None => return Err(PatternError("unbalanced '['".to_string())),
+            }
+        }
+        if negate {
+            class.negate();
+        }
+        Ok(class)
+    }
+
+    fn parse(mut self) -> Result<Nfa, PatternError> {
+        let frag = self.parse_alt()?;
+        if self.pos != self.src.len() {
+            return Err(PatternError(format!("unexpected '{}'", self.src[self.pos] as char)));
+        }
+        let m = self.push(Inst::Match);
+        self.patch(&frag.outs, m);
+        Ok(Nfa { prog: self.prog, start: frag.start })
+    }
+}
+
+/// The lazy-DFA "state": the set of `Char` instructions an in-progress
+/// scan could still be sitting on, after following every epsilon
+/// (`Split`) transition reachable from wherever it was. Kept sorted so two
+/// equivalent subsets compare/hash equal regardless of visit order.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct DfaState {
+    chars: Vec<usize>,
+    is_match: bool,
+}
+
+impl Nfa {
+    fn closure(&self, seeds: &[usize]) -> DfaState {
+        let mut seen = vec![false; self.prog.len()];
+        let mut stack: Vec<usize> = seeds.to_vec();
+        let mut chars = Vec::new();
+        let mut is_match = false;
+        while let Some(pc) = stack.pop() {
+            if seen[pc] {
+                continue;
+            }
+            seen[pc] = true;
+            match self.prog[pc] {
+                Inst::Char(..) => chars.push(pc),
+                Inst::Split(a, b) => {
+                    stack.push(a);
+                    stack.push(b);
+                }
+                Inst::Match => is_match = true,
+            }
+        }
+        chars.sort_unstable();
+        DfaState { chars, is_match }
+    }
+
+    fn start_state(&self) -> DfaState {
+        self.closure(&[self.start])
+    }
+
+    /// Materialize the DFA transition on `byte` out of `state` by stepping
+    /// every active `Char` instruction and re-closing over the results.
+    fn step(&self, state: &DfaState, byte: u8) -> DfaState {
+        let mut seeds = Vec::new();
+        for &pc in &state.chars {
+            if let Inst::Char(class, next) = self.prog[pc] {
+                if class.contains(byte) {
+                    seeds.push(next);
+                }
+            }
+        }
+        self.closure(&seeds)
+    }
+}
+
+/// A compiled content pattern: the NFA plus a bounded memo of transitions
+/// the lazy DFA has already materialized.
+pub struct StreamMatcher {
+    nfa: Nfa,
+    start: DfaState,
+    cache: RefCell<HashMap<(DfaState, u8), DfaState>>,
+    cache_cap: usize,
+}
+
+impl StreamMatcher {
+    /// Compile `pattern` (see the module docs for supported syntax).
+    pub fn new(pattern: &str) -> Result<Self, PatternError> {
+        Self::with_cache_capacity(pattern, DEFAULT_CACHE_CAP)
+    }
+
+    /// Compile `pattern` with a custom bound on how many distinct
+    /// transitions are memoized before the cache is cleared and rebuilt.
+    pub fn with_cache_capacity(pattern: &str, cache_cap: usize) -> Result<Self, PatternError> {
+        let nfa = Parser::new(pattern).parse()?;
+        let start = nfa.start_state();
+        Ok(StreamMatcher { nfa, start, cache: RefCell::new(HashMap::new()), cache_cap })
+    }
+
+    /// The memoized transition for `(state, byte)`, computing and caching
+    /// it on a miss. On cache overflow the memo is cleared and rebuilt from
+    /// scratch rather than left to grow -- callers never see stale data
+    /// either way, since `state` (not a cache-owned id) is what's carried
+    /// across calls.
+    fn next_state(&self, state: &DfaState, byte: u8) -> DfaState {
+        let key = (state.clone(), byte);
+        if let Some(next) = self.cache.borrow().get(&key) {
+            return next.clone();
+        }
+        let next = self.nfa.step(state, byte);
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= self.cache_cap {
+            cache.clear();
+        }
+        cache.insert(key, next.clone());
+        next
+    }
+
+    /// Scan `reader`'s content for matches, reading it in fixed-size
+    /// buffers rather than loading it whole.
+    pub fn search_stream<R: Read>(&self, reader: R) -> StreamSearch<'_, R> {
+        StreamSearch {
+            matcher: self,
+            reader,
+            buf: vec![0u8; BUF_SIZE],
+            buf_len: 0,
+            buf_pos: 0,
+            eof: false,
+            state: self.start.clone(),
+            abs_pos: 0,
+            line: 1,
+            // `state` already starts primed to accept the pattern's first
+            // byte, so an attempt beginning at offset 0 is already "in
+            // flight" from the caller's perspective.
+            pending_start: Some((0, 1)),
+            best_match: None,
+        }
+    }
+}
+
+/// One content match: 1-based line number the match begins on, and its
+/// `[byte_start, byte_end)` range within the stream.
+pub type Hit = (usize, u64, u64);
+
+/// Iterator-style streaming scan produced by [`StreamMatcher::search_stream`].
+///
+/// Carries the DFA state, and the absolute byte offset/line number it
+/// corresponds to, across buffer refills, so a match whose bytes land in
+/// two different reads is still reported correctly. Matching is maximal
+/// munch: once an attempt starts, it keeps extending while a longer match
+/// remains possible and reports the longest one reached before the
+/// attempt can no longer continue. Scanning itself is a single attempt at
+/// a time, restarted at the byte an attempt dies on if that byte can
+/// itself begin a new one -- exact for the literal and simple-class
+/// patterns this engine targets; patterns with self-overlapping prefixes
+/// (e.g. `abab` against `ababab`) can miss a start a full leftmost-longest
+/// engine with per-thread priorities would find.
+pub struct StreamSearch<'a, R> {
+    matcher: &'a StreamMatcher,
+    reader: R,
+    buf: Vec<u8>,
+    buf_len: usize,
+    buf_pos: usize,
+    eof: bool,
+    state: DfaState,
+    abs_pos: u64,
+    line: usize,
+    /// Start (byte offset, line) of the attempt `state` belongs to, if any.
+    pending_start: Option<(u64, usize)>,
+    /// Longest match reached so far within the in-progress attempt.
+    best_match: Option<Hit>,
+}
+
+impl<'a, R: Read> StreamSearch<'a, R> {
+    fn refill(&mut self) -> io::Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        let n = self.reader.read(&mut self.buf)?;
+        if n == 0 {
+            self.eof = true;
+            return Ok(false);
+        }
+        self.buf_len = n;
+        self.buf_pos = 0;
+        Ok(true)
+    }
+}
+
+impl<'a, R: Read> Iterator for StreamSearch<'a, R> {
+    type Item = io::Result<Hit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.buf_pos >= self.buf_len {
+                match self.refill() {
+                    Ok(true) => {}
+                    Ok(false) => return self.best_match.take().map(Ok),
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            let byte = self.buf[self.buf_pos];
+            self.buf_pos += 1;
+            let start_pos = self.abs_pos;
+            let end_pos = start_pos + 1;
+
+            // No attempt in flight: this byte is a candidate start.
+            if self.state.chars.is_empty() && self.best_match.is_none() {
+                self.state = self.matcher.start.clone();
+                self.pending_start = Some((start_pos, self.line));
+            }
+
+            let next = self.matcher.next_state(&self.state, byte);
+            if next.is_match {
+                let (start, line) = self.pending_start.expect("is_match implies an attempt is in flight");
+                self.best_match = Some((line, start, end_pos));
+            }
+
+            if next.chars.is_empty() {
+                // The attempt can't extend any further. Report the longest
+                // match it reached, if any, then decide what the state for
+                // the next byte should be.
+                let hit = self.best_match.take();
+                let line_of_byte = self.line;
+                if byte == b'\n' {
+                    self.line += 1;
+                }
+                self.abs_pos = end_pos;
+                if hit.is_some_and(|(_, _, end)| end == end_pos) {
+                    // This byte completed a match, so it's already spoken
+                    // for -- resume fresh at the *next* byte, or the byte
+                    // that just ended a match would also be claimed as the
+                    // start of an overlapping one (e.g. `aa` against
+                    // `aaaa` would report a match at every offset instead
+                    // of the two non-overlapping ones).
+                    self.state = self.matcher.start.clone();
+                    self.pending_start = Some((self.abs_pos, self.line));
+                } else {
+                    // A failed attempt, or one whose match ended before
+                    // this byte, can still restart on this same byte, since
+                    // it hasn't been claimed by anything.
+                    let restart = self.matcher.next_state(&self.matcher.start, byte);
+                    if restart.is_match {
+                        self.best_match = Some((line_of_byte, start_pos, end_pos));
+                    }
+                    self.state = restart;
+                    self.pending_start = if !self.state.chars.is_empty() {
+                        Some((start_pos, line_of_byte))
+                    } else {
+                        None
+                    };
+                }
+                if let Some(hit) = hit {
+                    return Some(Ok(hit));
+                }
+                continue;
+            }
+
+            if byte == b'\n' {
+                self.line += 1;
+            }
+            self.abs_pos = end_pos;
+            self.state = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn hits(pattern: &str, text: &[u8]) -> Vec<Hit> {
+        let m = StreamMatcher::new(pattern).unwrap();
+        m.search_stream(Cursor::new(text)).collect::<io::Result<Vec<_>>>().unwrap()
+    }
+
+    #[test]
+    fn finds_a_plain_literal() {
+        assert_eq!(hits("foo", b"xxfooxx"), vec![(1, 2, 5)]);
+    }
+
+    #[test]
+    fn reports_line_numbers_across_newlines() {
+        let h = hits("foo", b"one\ntwo foo\nthree");
+        assert_eq!(h, vec![(2, 8, 11)]);
+    }
+
+    #[test]
+    fn finds_match_spanning_a_buffer_boundary() {
+        // Force the match to straddle the 64 KiB read boundary.
+        let mut text = vec![b'x'; BUF_SIZE - 1];
+        text.extend_from_slice(b"needle");
+        let h = hits("needle", &text);
+        assert_eq!(h, vec![(1, (BUF_SIZE - 1) as u64, (BUF_SIZE + 5) as u64)]);
+    }
+
+    #[test]
+    fn supports_classes_and_quantifiers() {
+        assert_eq!(hits("[0-9]+", b"abc123def"), vec![(1, 3, 6)]);
+        assert_eq!(hits("ab?c", b"ac xx abc"), vec![(1, 0, 2), (1, 6, 9)]);
+    }
+
+    #[test]
+    fn supports_alternation_and_groups() {
+        assert_eq!(hits("(foo|bar)baz", b"xxbarbazxx"), vec![(1, 2, 8)]);
+    }
+
+    #[test]
+    fn back_to_back_matches_are_not_reported_as_overlapping() {
+        assert_eq!(hits("aa", b"aaaa"), vec![(1, 0, 2), (1, 2, 4)]);
+    }
+
+    #[test]
+    fn dot_matches_any_byte_but_newline() {
+        assert_eq!(hits(".", b"a\nb"), vec![(1, 0, 1), (2, 2, 3)]);
+    }
+
+    #[test]
+    fn no_match_yields_no_hits() {
+        assert!(hits("zzz", b"abcdef").is_empty());
+    }
+}